@@ -0,0 +1,78 @@
+// Streaming throughput and per-chunk overhead, using the mock client's configurable chunking
+//
+// Run with `cargo bench --features mock`.
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kona::api::mock::MockOpenRouterClient;
+use kona::config::Config;
+use kona::utils::flush::FlushBuffer;
+
+// Roughly the size of a long answer
+const RESPONSE_BYTES: usize = 64 * 1024;
+
+const CHUNK_SIZES: [usize; 5] = [1, 4, 16, 64, 256];
+
+fn response_text() -> String {
+    "The quick brown fox jumps over the lazy dog.\n"
+        .chars()
+        .cycle()
+        .take(RESPONSE_BYTES)
+        .collect()
+}
+
+fn mock_client(chunk_size: usize) -> MockOpenRouterClient {
+    MockOpenRouterClient::new(Config::default(), response_text()).with_chunking(chunk_size, Duration::ZERO)
+}
+
+// Bytes per second through `collect_string`, for each chunk size
+fn bench_collect(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("collect_string");
+    group.throughput(Throughput::Bytes(RESPONSE_BYTES as u64));
+
+    for chunk_size in CHUNK_SIZES {
+        let client = mock_client(chunk_size);
+        group.bench_with_input(BenchmarkId::from_parameter(chunk_size), &client, |b, client| {
+            b.to_async(&runtime).iter(|| async {
+                let stream = client.send_message_streaming("benchmark").await.unwrap();
+                stream.collect_string().await.unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+// Time per chunk through the rendering path: `for_each_chunk` feeding a `FlushBuffer`
+fn bench_render(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("render_per_chunk");
+
+    for chunk_size in CHUNK_SIZES {
+        let client = mock_client(chunk_size);
+        group.throughput(Throughput::Elements(RESPONSE_BYTES.div_ceil(chunk_size) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(chunk_size), &client, |b, client| {
+            b.to_async(&runtime).iter(|| async {
+                let mut stream = client.send_message_streaming("benchmark").await.unwrap();
+                let mut buffer = FlushBuffer::new(16);
+                let mut rendered = String::with_capacity(RESPONSE_BYTES);
+                stream
+                    .for_each_chunk(|chunk| {
+                        if let Some(text) = buffer.push(chunk) {
+                            rendered.push_str(&text);
+                        }
+                    })
+                    .await
+                    .unwrap();
+                if let Some(text) = buffer.finish() {
+                    rendered.push_str(&text);
+                }
+                rendered
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_collect, bench_render);
+criterion_main!(benches);
@@ -0,0 +1,154 @@
+// On-disk response cache, keyed on a hash of everything that shapes the answer
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::debug;
+
+use super::client::Message;
+use crate::utils::error::{KonaError, Result};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    created_at: DateTime<Utc>,
+    model: String,
+    response: String,
+}
+
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Result<Self> {
+        Ok(Self::with_dir(Self::get_cache_dir()?, ttl))
+    }
+
+    pub fn with_dir(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    pub fn get_cache_dir() -> Result<PathBuf> {
+        let mut dir = dirs::cache_dir().ok_or_else(|| {
+            KonaError::IoError(io::Error::new(io::ErrorKind::NotFound, "Could not determine cache directory"))
+        })?;
+        dir.push("kona");
+        dir.push("responses");
+        Ok(dir)
+    }
+
+    // Cached response for `key`, unless it is missing, unreadable or older than the TTL
+    pub fn get(&self, key: &str) -> Option<String> {
+        let text = fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry: CacheEntry = match serde_json::from_str(&text) {
+            Ok(entry) => entry,
+            Err(err) => {
+                debug!("Ignoring unreadable cache entry {}: {}", key, err);
+                return None;
+            }
+        };
+
+        let age = Utc::now().signed_duration_since(entry.created_at).to_std().unwrap_or_default();
+        if age > self.ttl {
+            debug!("Cache entry {} expired", key);
+            return None;
+        }
+
+        debug!("Cache hit for {} ({})", key, entry.model);
+        Some(entry.response)
+    }
+
+    pub fn put(&self, key: &str, model: &str, response: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = CacheEntry {
+            created_at: Utc::now(),
+            model: model.to_string(),
+            response: response.to_string(),
+        };
+        let json = serde_json::to_string(&entry)
+            .map_err(|e| KonaError::IoError(io::Error::other(e)))?;
+        fs::write(self.entry_path(key), json)?;
+        Ok(())
+    }
+
+    // Remove every cached response, returning how many were deleted
+    pub fn clear(&self) -> Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Request settings besides the messages that change the answer, and so the cache key
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sampling<'a> {
+    pub temperature: f32,
+    pub top_p: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    /// The `json_schema` of a structured output request
+    pub response_format: Option<&'a serde_json::Value>,
+}
+
+// Unset settings hash differently from any value, so e.g. no top_p and top_p = 0 stay apart
+fn hash_option(hasher: &mut Sha256, value: Option<f32>) {
+    match value {
+        Some(value) => {
+            hasher.update([1]);
+            hasher.update(value.to_le_bytes());
+        }
+        None => hasher.update([0]),
+    }
+}
+
+// Hex SHA-256 of the model, sampling settings, stop sequences and messages; stable across runs and Rust versions
+pub fn cache_key(model: &str, max_tokens: u32, sampling: &Sampling, stop: &[String], messages: &[Message]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model.as_bytes());
+    hasher.update([0]);
+    hasher.update(max_tokens.to_le_bytes());
+    hasher.update(sampling.temperature.to_le_bytes());
+    for value in [sampling.top_p, sampling.frequency_penalty, sampling.presence_penalty] {
+        hash_option(&mut hasher, value);
+    }
+    let schema = sampling.response_format.map(|schema| schema.to_string()).unwrap_or_default();
+    hasher.update((schema.len() as u64).to_le_bytes());
+    hasher.update(schema.as_bytes());
+    // The count keeps stop sequences apart from the messages that follow
+    hasher.update((stop.len() as u64).to_le_bytes());
+    for sequence in stop {
+        hasher.update((sequence.len() as u64).to_le_bytes());
+        hasher.update(sequence.as_bytes());
+    }
+    for message in messages {
+        // Length prefixes keep e.g. ("ab", "c") and ("a", "bc") apart; serializing covers image blocks too
+        let content = serde_json::to_string(&message.content).unwrap_or_default();
+        for part in [&message.role, &content] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+    }
+
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -1,26 +1,94 @@
+use chrono::{DateTime, Utc};
 use futures::stream::{Stream, StreamExt, TryStreamExt};
 use reqwest::{Client, header};
 use serde::{Deserialize, Serialize};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, OnceCell, Semaphore};
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info, warn};
 
 use crate::utils::mask_api_key;
 
+use crate::api::cache;
+use crate::api::content::MessageContent;
+use crate::api::models::{lookup_model, suggest_models, supports_prompt_caching};
 use crate::config::Config;
 use crate::utils::error::{KonaError, Result};
+use crate::utils::tokens::{estimate_tokens, trim_to_budget};
 
-// Using OpenRouter API that can route to Anthropic's Claude
-#[cfg(not(test))]
-const API_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+// Endpoints relative to `Config::base_url`; the completions API routes to Anthropic's Claude
+const COMPLETIONS_PATH: &str = "chat/completions";
+// Model catalogue used to validate model names
+const MODELS_PATH: &str = "models";
+// Limit for fetching the catalogue behind a chat request, so a missing endpoint doesn't hold the request up
+const MODEL_CATALOG_TIMEOUT_SECS: u64 = 10;
+// Key details; a cheap way to confirm the API key works
+const AUTH_KEY_PATH: &str = "auth/key";
+// Account balance: credits purchased and spent
+const CREDITS_PATH: &str = "credits";
 
-// For testing, we'll set this in the test module
-#[cfg(test)]
-thread_local! {
-    static API_URL: std::cell::RefCell<String> = std::cell::RefCell::new(
-        "https://openrouter.ai/api/v1/chat/completions".to_string()
-    );
+// OpenRouter wraps account endpoints in a `data` object
+#[derive(Debug, Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+/// Details of the API key reported by OpenRouter's `auth/key` endpoint
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct KeyInfo {
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Credits spent with this key, in USD
+    #[serde(default)]
+    pub usage: f64,
+    /// Spending limit for this key, if one is set
+    #[serde(default)]
+    pub limit: Option<f64>,
+    #[serde(default)]
+    pub limit_remaining: Option<f64>,
+    #[serde(default)]
+    pub is_free_tier: bool,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitInfo>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateLimitInfo {
+    pub requests: u64,
+    pub interval: String,
+}
+
+/// Account balance reported by OpenRouter's `credits` endpoint, in USD
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub struct Credits {
+    #[serde(default)]
+    pub total_credits: f64,
+    #[serde(default)]
+    pub total_usage: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelListResponse {
+    data: Vec<ModelListEntry>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ModelListEntry {
+    id: String,
+    #[serde(default)]
+    context_length: Option<u32>,
+    // Limits of the provider OpenRouter routes to by default
+    #[serde(default)]
+    top_provider: Option<TopProvider>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+struct TopProvider {
+    #[serde(default)]
+    max_completion_tokens: Option<u32>,
 }
 
 #[derive(Debug, Serialize)]
@@ -32,23 +100,136 @@ struct MessageRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    // Number of completions to generate; the API default is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+// Streaming extras; `include_usage` asks for token counts in the final event
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+// Structured output request, e.g. {"type": "json_schema", "json_schema": {"name": ..., "schema": ...}}
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    json_schema: serde_json::Value,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    pub content: MessageContent,
+    /// Model that produced an assistant message; kept in saved conversations, never sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// When the message was added to a conversation; kept in saved conversations, never sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl Message {
+    /// Creates a message from plain text or content blocks
+    pub fn new(role: &str, content: impl Into<MessageContent>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            model: None,
+            timestamp: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
-struct MessageResponse {
+pub(crate) struct MessageResponse {
+    // Both are missing when the body only carries an `error`
+    #[serde(default)]
     id: String,
+    #[serde(default)]
     choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<Usage>,
+    #[serde(default)]
+    pub(crate) error: Option<ResponseError>,
 }
 
+/// Error object OpenRouter can send in place of choices, even with HTTP 200 (e.g. moderation or provider failures)
 #[derive(Debug, Deserialize)]
-struct Choice {
+pub(crate) struct ResponseError {
+    // Usually an HTTP-style number, but some providers send a string
+    #[serde(default)]
+    code: Option<serde_json::Value>,
+    #[serde(default)]
+    message: String,
+}
+
+impl ResponseError {
+    pub(crate) fn into_error(self) -> KonaError {
+        let message = if self.message.trim().is_empty() { "unknown error" } else { self.message.trim() };
+        match self.code {
+            Some(serde_json::Value::String(code)) => KonaError::ApiError(format!("OpenRouter error {}: {}", code, message)),
+            Some(code) if !code.is_null() => KonaError::ApiError(format!("OpenRouter error {}: {}", code, message)),
+            _ => KonaError::ApiError(format!("OpenRouter error: {}", message)),
+        }
+    }
+}
+
+/// Token counts reported by OpenRouter for a request
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+/// Breakdown of the prompt tokens, reported by providers that cache prompts
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct PromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u32,
+}
+
+impl Usage {
+    /// Adds another request's counts to this running total
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        let cached_tokens = self.cached_tokens() + other.cached_tokens();
+        if cached_tokens > 0 {
+            self.prompt_tokens_details = Some(PromptTokensDetails { cached_tokens });
+        }
+    }
+
+    /// Prompt tokens read from the provider's prompt cache
+    pub fn cached_tokens(&self) -> u32 {
+        self.prompt_tokens_details.map_or(0, |details| details.cached_tokens)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Choice {
     message: ChoiceMessage,
     index: usize,
     finish_reason: Option<String>,
@@ -61,32 +242,522 @@ struct ChoiceMessage {
 }
 
 // Streaming response types
-// Note: We no longer need the StreamEvent and Delta structs
-// as we're parsing the OpenRouter streaming responses as generic JSON
 
-// Define a stream of text chunks
+/// Something a streamed response produced
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// A chunk of the answer
+    Text(String),
+    /// A chunk of the model's reasoning, streamed separately from the answer
+    Reasoning(String),
+    /// A tool call assembled from its fragments once the stream ends, as `name(arguments)`
+    ToolCall(String),
+}
+
+// Define a stream of text chunks; reasoning and tool calls are only seen through `next_event`
 pub struct ResponseStream {
-    receiver: mpsc::Receiver<Result<String>>,
+    receiver: mpsc::Receiver<Result<StreamEvent>>,
+    // Attached to any error the stream yields
+    request_id: Option<String>,
+    // Token counts, filled in if the final event reports them
+    usage: Arc<Mutex<Option<Usage>>>,
+    // Why generation stopped, from the final event
+    finish_reason: Arc<Mutex<Option<String>>>,
+    // Background task reading the HTTP response, aborted when the stream is dropped
+    task: Option<JoinHandle<()>>,
+}
+
+impl Drop for ResponseStream {
+    fn drop(&mut self) {
+        self.abort();
+    }
 }
 
 impl Stream for ResponseStream {
     type Item = Result<String>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.receiver.poll_recv(cx)
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match this.poll_event(cx) {
+                Poll::Ready(Some(Ok(StreamEvent::Text(chunk)))) => return Poll::Ready(Some(Ok(chunk))),
+                Poll::Ready(Some(Ok(_))) => continue,
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
     }
 }
 
 impl ResponseStream {
-    fn new(receiver: mpsc::Receiver<Result<String>>) -> Self {
-        Self { receiver }
+    pub(crate) fn new(receiver: mpsc::Receiver<Result<StreamEvent>>) -> Self {
+        Self {
+            receiver,
+            request_id: None,
+            usage: Arc::new(Mutex::new(None)),
+            finish_reason: Arc::new(Mutex::new(None)),
+            task: None,
+        }
+    }
+
+    pub(crate) fn with_task(mut self, task: JoinHandle<()>) -> Self {
+        self.task = Some(task);
+        self
+    }
+
+    /// Stops the background request, closing the HTTP connection; the stream then ends
+    pub fn abort(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+
+    fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<StreamEvent>>> {
+        let request_id = self.request_id.as_deref();
+        self.receiver
+            .poll_recv(cx)
+            .map(|item| item.map(|event| event.map_err(|e| tag_request_id(e, request_id))))
+    }
+
+    /// The next event, including reasoning and tool calls, or `None` once the stream has ended
+    pub async fn next_event(&mut self) -> Option<Result<StreamEvent>> {
+        std::future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    /// The correlation id sent with the request, if any
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    /// Token counts for the response, available once the stream has finished if OpenRouter reported them
+    pub fn usage(&self) -> Option<Usage> {
+        self.usage.lock().ok().and_then(|usage| *usage)
+    }
+
+    /// Why the model stopped (e.g. "stop" or "length"), available once the stream has finished
+    pub fn finish_reason(&self) -> Option<String> {
+        self.finish_reason.lock().ok().and_then(|reason| reason.clone())
+    }
+
+    /// Drains the stream into a single string, stopping at the first error
+    pub async fn collect_string(mut self) -> Result<String> {
+        self.for_each_chunk(|_| {}).await
+    }
+
+    /// Drains the stream, calling `on_chunk` with each chunk, and returns the full text
+    ///
+    /// Stops at the first error. The stream is borrowed, so usage and finish reason can be read afterwards.
+    pub async fn for_each_chunk<F: FnMut(&str)>(&mut self, mut on_chunk: F) -> Result<String> {
+        self.for_each_event(|event| {
+            if let StreamEvent::Text(chunk) = event {
+                on_chunk(chunk);
+            }
+        })
+        .await
+    }
+
+    /// Like `for_each_chunk`, but also passes on reasoning and tool calls; the returned text is the answer only
+    pub async fn for_each_event<F: FnMut(&StreamEvent)>(&mut self, mut on_event: F) -> Result<String> {
+        let mut full_response = String::new();
+        while let Some(event) = self.next_event().await {
+            let event = event?;
+            on_event(&event);
+            if let StreamEvent::Text(chunk) = &event {
+                full_response.push_str(chunk);
+            }
+        }
+        Ok(full_response)
+    }
+}
+
+/// Parses a `Retry-After` header given either as seconds or as an HTTP date
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Maps an unsuccessful HTTP response to the matching `KonaError` variant
+async fn error_for_response(response: reqwest::Response) -> KonaError {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_retry_after);
+    let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+    error!("API error: {} - {}", status, error_text);
+
+    match status.as_u16() {
+        401 => KonaError::Unauthorized,
+        // A refusal (moderation, a spending limit, a blocked model) rather than a bad key, so keep the provider's reason
+        403 => parse_sse_error(&error_text)
+            .unwrap_or_else(|| KonaError::ApiError(format!("API returned error {}: {}", status, error_text))),
+        429 => KonaError::RateLimited { retry_after },
+        _ => KonaError::ApiError(format!("API returned error {}: {}", status, error_text)),
     }
 }
 
+// Providers for well-known bare model names, used to suggest the full `provider/model` id
+const PROVIDER_HINTS: &[(&str, &str)] = &[
+    ("gpt-", "openai"),
+    ("o1", "openai"),
+    ("o3", "openai"),
+    ("gemini-", "google"),
+    ("gemma-", "google"),
+    ("llama-", "meta-llama"),
+    ("mistral-", "mistralai"),
+    ("mixtral-", "mistralai"),
+    ("command-", "cohere"),
+    ("deepseek-", "deepseek"),
+];
+
+/// Maps a model name to OpenRouter's model format, e.g. "anthropic/claude-3-sonnet"
+///
+/// `provider/model` ids are left untouched and only bare `claude-*` names get a provider
+/// prefix; other bare names are passed through as given (see `model_name_warning`).
+pub(crate) fn openrouter_model_id(model: &str) -> String {
+    if !model.contains('/') && model.to_ascii_lowercase().starts_with("claude-") {
+        format!("anthropic/{}", model)
+    } else {
+        model.to_string()
+    }
+}
+
+/// Warning for a bare model name OpenRouter is unlikely to resolve, suggesting a provider when known
+pub fn model_name_warning(model: &str) -> Option<String> {
+    if model.contains('/') || openrouter_model_id(model) != model {
+        return None;
+    }
+
+    let lower = model.to_ascii_lowercase();
+    let hint = PROVIDER_HINTS
+        .iter()
+        .find(|(prefix, _)| lower.starts_with(prefix))
+        .map(|(_, provider)| format!("; did you mean {}/{}?", provider, model))
+        .unwrap_or_else(|| "; use the provider/model form, e.g. openai/gpt-4o".to_string());
+    Some(format!("Model '{}' has no provider prefix{}", model, hint))
+}
+
+/// Appends the request id to API and timeout errors so users can quote it in support requests
+fn tag_request_id(error: KonaError, request_id: Option<&str>) -> KonaError {
+    match (error, request_id) {
+        (KonaError::ApiError(msg), Some(id)) => KonaError::ApiError(format!("{} (request id: {})", msg, id)),
+        (KonaError::Timeout(msg), Some(id)) => KonaError::Timeout(format!("{} (request id: {})", msg, id)),
+        (error, _) => error,
+    }
+}
+
+/// Converts a transport error into a `KonaError`, reporting timeouts separately
+fn map_request_error(error: reqwest::Error, timeout_secs: u64) -> KonaError {
+    if error.is_timeout() {
+        KonaError::Timeout(format!(
+            "OpenRouter did not respond within {} seconds. Check your connection or raise request_timeout_secs.",
+            timeout_secs
+        ))
+    } else {
+        KonaError::ApiError(format!("API request failed: {}", error))
+    }
+}
+
+const HTML_RESPONSE_ERROR: &str = "Received HTML instead of JSON — are you behind a proxy or captive portal?";
+
+// A 200 with nothing in it; usually an upstream hiccup that a second attempt gets past
+const EMPTY_RESPONSE_ERROR: &str = "No response content received";
+
+/// Whether a completion came back without choices, or with empty content and no finish reason
+pub(crate) fn is_empty_completion(response: &MessageResponse) -> bool {
+    match response.choices.first() {
+        Some(choice) => choice.message.content.trim().is_empty() && choice.finish_reason.is_none(),
+        None => true,
+    }
+}
+
+/// Whether the error is a 400 about the `n` parameter, from a provider that rejects several choices rather than ignoring it
+///
+/// Goes by the `param` field OpenAI-style providers report, including errors OpenRouter passes on in
+/// `metadata.raw`; without one, only a message quoting the parameter name counts.
+pub(crate) fn rejects_choice_count(error: &KonaError) -> bool {
+    let KonaError::ApiError(message) = error else {
+        return false;
+    };
+    let Some(body) = message.strip_prefix("API returned error 400 Bad Request: ") else {
+        return false;
+    };
+    let Some(error) = serde_json::from_str::<serde_json::Value>(body).ok().and_then(|json| json.get("error").cloned()) else {
+        return false;
+    };
+
+    let upstream = error
+        .pointer("/metadata/raw")
+        .and_then(serde_json::Value::as_str)
+        .and_then(|raw| serde_json::from_str::<serde_json::Value>(raw).ok())
+        .and_then(|json| json.get("error").cloned());
+    for error in [Some(error), upstream].into_iter().flatten() {
+        if let Some(param) = error.get("param").and_then(serde_json::Value::as_str) {
+            return param == "n";
+        }
+        let message = error.get("message").and_then(serde_json::Value::as_str).unwrap_or_default();
+        if ["'n'", "\"n\"", "`n`"].iter().any(|quoted| message.contains(quoted)) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Detects an HTML page (typically from a proxy or captive portal) where JSON was expected
+pub(crate) fn looks_like_html(content_type: Option<&str>, body: &[u8]) -> bool {
+    if content_type.is_some_and(|content_type| content_type.to_ascii_lowercase().contains("text/html")) {
+        return true;
+    }
+
+    body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<')
+}
+
+/// Decodes the complete UTF-8 sequences in `pending`, leaving a trailing partial character for the next chunk
+pub(crate) fn drain_utf8(pending: &mut Vec<u8>) -> String {
+    let valid_up_to = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        // `error_len` is None when the bytes end mid-character
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => {
+            warn!("Invalid UTF-8 in stream, replacing malformed bytes");
+            let decoded = String::from_utf8_lossy(pending).into_owned();
+            pending.clear();
+            return decoded;
+        }
+    };
+
+    let rest = pending.split_off(valid_up_to);
+    String::from_utf8(std::mem::replace(pending, rest)).unwrap_or_default()
+}
+
+/// Splits a streamed byte body into SSE `data:` payloads as complete events arrive
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    // Bytes of a multibyte character split across network chunks
+    pending: Vec<u8>,
+    // Decoded text of the event still being received
+    buffer: String,
+}
+
+impl SseDecoder {
+    /// Feeds one network chunk and returns the payloads of every event it completes
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        self.buffer.push_str(&drain_utf8(&mut self.pending));
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.find("\n\n") {
+            let event: String = self.buffer.drain(..end + 2).collect();
+            if let Some(data) = event.trim().strip_prefix("data: ") {
+                events.push(data.to_string());
+            }
+        }
+        events
+    }
+}
+
+/// Extracts the content delta from one OpenRouter SSE `data:` payload
+pub(crate) fn parse_sse_delta(data: &str) -> std::result::Result<Option<String>, serde_json::Error> {
+    let json = serde_json::from_str::<serde_json::Value>(data)?;
+    let content = json
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .and_then(|choices| choices.first())
+        .and_then(|choice| choice.get("delta"))
+        .and_then(|delta| delta.get("content"))
+        .and_then(|c| c.as_str())
+        .filter(|content| !content.is_empty())
+        .map(|content| content.to_string());
+    Ok(content)
+}
+
+/// The error carried by an SSE `data:` payload, if the upstream failed mid-stream
+pub(crate) fn parse_sse_error(data: &str) -> Option<KonaError> {
+    let json = serde_json::from_str::<serde_json::Value>(data).ok()?;
+    let error = json.get("error").filter(|error| !error.is_null())?;
+    let error = serde_json::from_value::<ResponseError>(error.clone()).unwrap_or_else(|_| ResponseError {
+        code: None,
+        message: error.to_string(),
+    });
+    Some(error.into_error())
+}
+
+/// Extracts the reasoning delta some models stream alongside (or before) the content
+pub(crate) fn parse_sse_reasoning(data: &str) -> Option<String> {
+    let json = serde_json::from_str::<serde_json::Value>(data).ok()?;
+    json.get("choices")?
+        .as_array()?
+        .first()?
+        .get("delta")?
+        .get("reasoning")?
+        .as_str()
+        .filter(|reasoning| !reasoning.is_empty())
+        .map(str::to_string)
+}
+
+/// Tool calls assembled from streamed `delta.tool_calls` fragments
+#[derive(Debug, Default)]
+pub(crate) struct ToolCallAccumulator {
+    // Name and argument text of each call, by its `index`
+    calls: Vec<(String, String)>,
+}
+
+impl ToolCallAccumulator {
+    /// Adds the fragments in one SSE `data:` payload
+    pub(crate) fn push(&mut self, data: &str) {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(data) else {
+            return;
+        };
+        let fragments = json
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .and_then(|choices| choices.first())
+            .and_then(|choice| choice.get("delta"))
+            .and_then(|delta| delta.get("tool_calls"))
+            .and_then(|calls| calls.as_array());
+
+        for fragment in fragments.into_iter().flatten() {
+            let index = fragment.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+            if self.calls.len() <= index {
+                self.calls.resize(index + 1, Default::default());
+            }
+            let function = fragment.get("function");
+            let (name, arguments) = &mut self.calls[index];
+            if let Some(part) = function.and_then(|f| f.get("name")).and_then(|n| n.as_str()) {
+                name.push_str(part);
+            }
+            if let Some(part) = function.and_then(|f| f.get("arguments")).and_then(|a| a.as_str()) {
+                arguments.push_str(part);
+            }
+        }
+    }
+
+    /// Each complete call as `name(arguments)`
+    pub(crate) fn calls(&self) -> Vec<String> {
+        self.calls
+            .iter()
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, arguments)| format!("{}({})", name, arguments))
+            .collect()
+    }
+}
+
+/// Extracts the token counts from an SSE `data:` payload, usually only present on the last event
+pub(crate) fn parse_sse_usage(data: &str) -> Option<Usage> {
+    let json = serde_json::from_str::<serde_json::Value>(data).ok()?;
+    let usage = json.get("usage").filter(|usage| !usage.is_null())?;
+    serde_json::from_value(usage.clone()).ok()
+}
+
+/// Extracts the finish reason from an SSE `data:` payload; only the last content event carries one
+pub(crate) fn parse_sse_finish_reason(data: &str) -> Option<String> {
+    let json = serde_json::from_str::<serde_json::Value>(data).ok()?;
+    json.get("choices")?
+        .as_array()?
+        .first()?
+        .get("finish_reason")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Shown after an answer that stopped because it reached max_tokens
+pub const TRUNCATED_NOTICE: &str = "[response truncated: increase --max-tokens]";
+
+/// The notice to show for a finish reason, if the answer was cut short
+pub fn truncation_notice(finish_reason: Option<&str>) -> Option<&'static str> {
+    (finish_reason == Some("length")).then_some(TRUNCATED_NOTICE)
+}
+
+/// Joins the content deltas of a complete SSE body into the full response text
+pub(crate) fn accumulate_sse_content(body: &str) -> String {
+    let mut full_response = String::new();
+    for line in body.lines() {
+        let data = match line.trim().strip_prefix("data:") {
+            Some(data) => data.trim(),
+            None => continue,
+        };
+        if data == "[DONE]" {
+            break;
+        }
+        match parse_sse_delta(data) {
+            Ok(Some(content)) => full_response.push_str(&content),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to parse event JSON: {}", e),
+        }
+    }
+    full_response
+}
+
+const JSON_CORRECTION_PROMPT: &str =
+    "Your previous reply was not valid JSON. Return only valid JSON, with no commentary or code fences.";
+
+/// Progress reported while streaming a response that must be valid JSON
+pub enum JsonStreamEvent<'a> {
+    /// A chunk of the response as it arrives
+    Chunk(&'a str),
+    /// The response is complete and is being parsed
+    Validating,
+    /// The response was invalid and a correction has been requested
+    Retrying(usize),
+}
+
+/// Parses a model reply as JSON, tolerating a surrounding Markdown code fence
+pub(crate) fn parse_json_response(text: &str) -> std::result::Result<serde_json::Value, serde_json::Error> {
+    let trimmed = text.trim();
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(unfenced.trim())
+}
+
 /// Client for communicating with OpenRouter API to access Claude models
+#[derive(Clone)]
 pub struct OpenRouterClient {
     client: Client,
+    // Separate client without an overall timeout, since streamed responses can run for minutes
+    stream_client: Client,
     pub config: Config,
+    // Correlation id of the most recent request
+    last_request_id: Arc<Mutex<Option<String>>>,
+    // Model list from OpenRouter, kept for the rest of the session; `None` once a fetch has failed
+    model_cache: Arc<OnceCell<Option<Vec<ModelListEntry>>>>,
+    // Finish reason of the most recent non-streaming response
+    last_finish_reason: Arc<Mutex<Option<String>>>,
+    // When the last chat request went out, for `min_request_interval_ms` pacing
+    last_request_at: Arc<tokio::sync::Mutex<Option<Instant>>>,
+    // One permit per request in flight, capped at `max_concurrent_requests`
+    request_slots: Arc<Semaphore>,
+}
+
+/// Replaces any occurrence of the API key in `text` with its masked form
+pub(crate) fn redact_api_key(text: &str, api_key: &str) -> String {
+    if api_key.is_empty() {
+        return text.to_string();
+    }
+    text.replace(api_key, &mask_api_key(api_key))
+}
+
+/// How long to wait before the next request so it starts at least `interval` after the last one
+pub(crate) fn throttle_delay(last: Option<Instant>, now: Instant, interval: Duration) -> Duration {
+    match last {
+        Some(last) => (last + interval).saturating_duration_since(now),
+        None => Duration::ZERO,
+    }
 }
 
 impl OpenRouterClient {
@@ -111,11 +782,22 @@ impl OpenRouterClient {
                 .map_err(|e| KonaError::ApiError(format!("Invalid API key: {}", e)))?,
         );
 
-        // Set the HTTP-REFERER header (OpenRouter likes to know where requests come from)
-        headers.insert(
-            "HTTP-REFERER",
-            header::HeaderValue::from_static("https://github.com/yourusername/kona"),
-        );
+        // Attribution headers OpenRouter uses for its app rankings and analytics
+        let attribution = [
+            ("HTTP-Referer", &config.http_referer),
+            ("X-Title", &config.app_title),
+        ];
+        for (name, value) in attribution {
+            let value = match value.as_deref().map(str::trim) {
+                Some(value) if !value.is_empty() => value,
+                _ => continue,
+            };
+            headers.insert(
+                name,
+                header::HeaderValue::from_str(value)
+                    .map_err(|e| KonaError::ConfigError(format!("Invalid {} header value: {}", name, e)))?,
+            );
+        }
 
         // Set the Content-Type header
         headers.insert(
@@ -123,12 +805,415 @@ impl OpenRouterClient {
             header::HeaderValue::from_static("application/json"),
         );
 
-        let client = Client::builder()
+        let request_timeout = Duration::from_secs(config.request_timeout_secs);
+
+        // Without an explicit proxy, reqwest picks up HTTPS_PROXY/NO_PROXY from the environment
+        let proxy = match config.proxy.as_deref() {
+            Some(url) => Some(
+                reqwest::Proxy::all(url)
+                    .map_err(|e| KonaError::ConfigError(format!("Invalid proxy URL '{}': {}", url, e)))?
+                    .no_proxy(reqwest::NoProxy::from_env()),
+            ),
+            None => None,
+        };
+
+        let mut client_builder = Client::builder()
+            .default_headers(headers.clone())
+            .timeout(request_timeout);
+
+        // Streaming is bounded by the idle timeout between chunks instead
+        let mut stream_client_builder = Client::builder()
             .default_headers(headers)
+            .connect_timeout(request_timeout);
+
+        if let Some(proxy) = proxy {
+            debug!("Using proxy from config");
+            client_builder = client_builder.proxy(proxy.clone());
+            stream_client_builder = stream_client_builder.proxy(proxy);
+        }
+
+        let client = client_builder
             .build()
             .map_err(|e| KonaError::ApiError(format!("Failed to create HTTP client: {}", e)))?;
+        let stream_client = stream_client_builder
+            .build()
+            .map_err(|e| KonaError::ApiError(format!("Failed to create HTTP client: {}", e)))?;
+        let request_slots = Arc::new(Semaphore::new(config.max_concurrent_requests.max(1)));
 
-        Ok(Self { client, config })
+        Ok(Self {
+            client,
+            stream_client,
+            config,
+            last_request_id: Arc::new(Mutex::new(None)),
+            model_cache: Arc::new(OnceCell::new()),
+            last_finish_reason: Arc::new(Mutex::new(None)),
+            last_request_at: Arc::new(tokio::sync::Mutex::new(None)),
+            request_slots,
+        })
+    }
+
+    /// Waits until `min_request_interval_ms` has passed since the previous chat request
+    ///
+    /// The lock is held while sleeping, so concurrent callers are paced one after another.
+    async fn throttle(&self) {
+        let interval = Duration::from_millis(self.config.min_request_interval_ms);
+        if interval.is_zero() {
+            return;
+        }
+
+        let mut last = self.last_request_at.lock().await;
+        let delay = throttle_delay(*last, Instant::now(), interval);
+        if !delay.is_zero() {
+            debug!("Delaying request by {} ms to respect min_request_interval_ms", delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+        *last = Some(Instant::now());
+    }
+
+    /// Full URL of an API endpoint under the configured base URL
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Generates the `X-Request-Id` for a new request if request ids are enabled
+    fn new_request_id(&self) -> Option<String> {
+        if !self.config.send_request_id {
+            return None;
+        }
+
+        let request_id = uuid::Uuid::new_v4().to_string();
+        debug!("Request id: {}", request_id);
+        if let Ok(mut last) = self.last_request_id.lock() {
+            *last = Some(request_id.clone());
+        }
+        Some(request_id)
+    }
+
+    /// Returns the correlation id sent with the most recent request, if any
+    pub fn last_request_id(&self) -> Option<String> {
+        self.last_request_id.lock().ok().and_then(|last| last.clone())
+    }
+
+    /// Returns why the most recent non-streaming response stopped, if the API said
+    pub fn last_finish_reason(&self) -> Option<String> {
+        self.last_finish_reason.lock().ok().and_then(|last| last.clone())
+    }
+
+    fn set_last_finish_reason(&self, finish_reason: Option<String>) {
+        if let Ok(mut last) = self.last_finish_reason.lock() {
+            *last = finish_reason;
+        }
+    }
+
+    /// Fetches the ids of the models available on OpenRouter, cached after the first call
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<String>>` - The available model ids or an error
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let models = self.model_catalog().await?;
+        Ok(models.into_iter().map(|entry| entry.id).collect())
+    }
+
+    // The full model list from OpenRouter, fetched once per session; a failure is reported and tried again next time
+    async fn model_catalog(&self) -> Result<Vec<ModelListEntry>> {
+        if let Some(Some(models)) = self.model_cache.get() {
+            return Ok(models.clone());
+        }
+
+        let models = self.fetch_model_catalog(self.config.request_timeout_secs).await?;
+        // Only fills the cache if no lookup has settled it yet
+        let _ = self.model_cache.set(Some(models.clone()));
+        Ok(models)
+    }
+
+    // The model list for limit lookups, fetched at most once per session; `None` if that fetch failed
+    async fn cached_model_catalog(&self) -> Option<&[ModelListEntry]> {
+        self.model_cache
+            .get_or_init(|| async {
+                match self.fetch_model_catalog(MODEL_CATALOG_TIMEOUT_SECS).await {
+                    Ok(models) => Some(models),
+                    Err(err) => {
+                        debug!("Could not fetch model list, using built-in limits for this session: {}", err);
+                        None
+                    }
+                }
+            })
+            .await
+            .as_deref()
+    }
+
+    async fn fetch_model_catalog(&self, timeout_secs: u64) -> Result<Vec<ModelListEntry>> {
+        let response = self
+            .client
+            .get(self.endpoint(MODELS_PATH))
+            .timeout(Duration::from_secs(timeout_secs))
+            .send()
+            .await
+            .map_err(|e| map_request_error(e, timeout_secs))?;
+
+        if !response.status().is_success() {
+            return Err(error_for_response(response).await);
+        }
+
+        let list: ModelListResponse = response
+            .json()
+            .await
+            .map_err(|e| KonaError::ApiError(format!("Failed to parse model list: {}", e)))?;
+        debug!("Fetched {} models from OpenRouter", list.data.len());
+        Ok(list.data)
+    }
+
+    /// Confirms that OpenRouter accepts the API key, without using any tokens
+    ///
+    /// # Returns
+    ///
+    /// * `Result<()>` - Ok if the key is valid, otherwise the API error
+    pub async fn check_auth(&self) -> Result<()> {
+        let response = self
+            .client
+            .get(self.endpoint(AUTH_KEY_PATH))
+            .send()
+            .await
+            .map_err(|e| map_request_error(e, self.config.request_timeout_secs))?;
+
+        if !response.status().is_success() {
+            return Err(error_for_response(response).await);
+        }
+        Ok(())
+    }
+
+    /// Fetches the label, usage, limits and rate limit of the API key
+    ///
+    /// # Returns
+    ///
+    /// * `Result<KeyInfo>` - The key details or an error (`Unauthorized` for a rejected key)
+    pub async fn key_info(&self) -> Result<KeyInfo> {
+        self.get_data(AUTH_KEY_PATH, "key details").await
+    }
+
+    /// Fetches the account's purchased and used credits
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Credits>` - The account balance or an error
+    pub async fn credits(&self) -> Result<Credits> {
+        self.get_data(CREDITS_PATH, "credits").await
+    }
+
+    // GET an account endpoint and unwrap its `data` object
+    async fn get_data<T: serde::de::DeserializeOwned>(&self, path: &str, what: &str) -> Result<T> {
+        let response = self
+            .client
+            .get(self.endpoint(path))
+            .send()
+            .await
+            .map_err(|e| map_request_error(e, self.config.request_timeout_secs))?;
+
+        if !response.status().is_success() {
+            return Err(error_for_response(response).await);
+        }
+
+        let body: DataResponse<T> = response
+            .json()
+            .await
+            .map_err(|e| KonaError::ApiError(format!("Failed to parse {}: {}", what, e)))?;
+        Ok(body.data)
+    }
+
+    /// Looks up the context length of a model, preferring OpenRouter's model list over the built-in table
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The model name to look up
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u32>` - The context length in tokens, if known
+    pub async fn context_length(&self, model: &str) -> Option<u32> {
+        let model_id = openrouter_model_id(model);
+        let remote = self
+            .cached_model_catalog()
+            .await
+            .and_then(|models| models.iter().find(|entry| entry.id == model_id)?.context_length);
+        remote.or_else(|| lookup_model(model).map(|info| info.context_length))
+    }
+
+    // Fetch the model list once per session so max_tokens is checked against the live output limit
+    async fn load_model_limits(&self) {
+        self.cached_model_catalog().await;
+    }
+
+    // The output limit from OpenRouter's model list once fetched, otherwise from the built-in table
+    fn max_output_tokens(&self, model: &str) -> Option<u32> {
+        let model_id = openrouter_model_id(model);
+        let remote = self.model_cache.get().and_then(|cache| {
+            cache.as_ref()?.iter().find(|entry| entry.id == model_id)?.top_provider?.max_completion_tokens
+        });
+        remote.or_else(|| lookup_model(model).map(|info| info.max_output_tokens))
+    }
+
+    /// Drops the oldest turns until the conversation, the system prompt and room for the reply
+    /// fit the current model's context window
+    ///
+    /// Messages are returned unchanged when the model's context length isn't known.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - The conversation history about to be sent
+    ///
+    /// # Returns
+    ///
+    /// * `(Vec<Message>, usize)` - The messages that fit and how many turns were dropped
+    pub async fn fit_context_window(&self, messages: Vec<Message>) -> (Vec<Message>, usize) {
+        let Some(context_length) = self.context_length(&self.config.model).await else {
+            return (messages, 0);
+        };
+        let system_tokens = self.config.system_prompt.as_deref().map(estimate_tokens).unwrap_or(0);
+        let budget = (context_length as usize)
+            .saturating_sub(self.config.max_tokens as usize)
+            .saturating_sub(system_tokens);
+        trim_to_budget(messages, budget)
+    }
+
+    /// Checks a model name against the models available on OpenRouter
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The model name to check
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Vec<String>>>` - `None` if the model exists, otherwise the closest matches
+    pub async fn validate_model(&self, model: &str) -> Result<Option<Vec<String>>> {
+        let models = self.list_models().await?;
+        let model_id = openrouter_model_id(model);
+        if models.contains(&model_id) {
+            return Ok(None);
+        }
+
+        let suggestions = suggest_models(model, &models, 3);
+        warn!("Model {} not found on OpenRouter (closest: {:?})", model, suggestions);
+        Ok(Some(suggestions))
+    }
+
+    /// Returns the max_tokens value to send, respecting the model's output limit
+    ///
+    /// The limit comes from OpenRouter's model list once it has been fetched, and from the built-in table before that.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u32>` - The configured value, clamped if needed, or an error when clamping is disabled
+    pub(crate) fn effective_max_tokens(&self) -> Result<u32> {
+        let requested = self.config.max_tokens;
+        let limit = match self.max_output_tokens(&self.config.model) {
+            Some(limit) => limit,
+            None => return Ok(requested),
+        };
+
+        if requested <= limit {
+            return Ok(requested);
+        }
+
+        if self.config.clamp_max_tokens {
+            warn!(
+                "max_tokens {} exceeds the output limit of {} for {}, clamping",
+                requested, limit, self.config.model
+            );
+            Ok(limit)
+        } else {
+            Err(KonaError::ConfigError(format!(
+                "max_tokens {} exceeds the output limit of {} for model {}. Lower max_tokens or enable clamp_max_tokens.",
+                requested, limit, self.config.model
+            )))
+        }
+    }
+
+    /// Assembles the request body: system prompt, normalized model name and sampling settings
+    fn build_request(&self, messages: Vec<Message>, streaming: bool) -> Result<MessageRequest> {
+        // If system message is set, add it as the first message
+        let mut all_messages = Vec::new();
+
+        let caching = self.config.enable_prompt_caching && supports_prompt_caching(&self.config.model);
+
+        // Add system prompt if configured
+        if let Some(system_prompt) = &self.config.system_prompt {
+            let content = MessageContent::from(system_prompt.clone());
+            let content = if caching { content.with_cache_breakpoint() } else { content };
+            all_messages.push(Message::new("system", content));
+        }
+
+        // Add user messages, dropping the local-only model labels and timestamps
+        all_messages.extend(messages.into_iter().map(|message| Message { model: None, timestamp: None, ..message }));
+
+        // Everything before the newest message is resent unchanged next turn, so cache it too
+        if caching {
+            let history_end = all_messages.len().checked_sub(2).filter(|&i| all_messages[i].role != "system");
+            if let Some(message) = history_end.map(|i| &mut all_messages[i]) {
+                message.content = message.content.clone().with_cache_breakpoint();
+            }
+        }
+
+        Ok(MessageRequest {
+            model: openrouter_model_id(&self.config.model),
+            max_tokens: self.effective_max_tokens()?,
+            messages: all_messages,
+            stream: if streaming { Some(true) } else { None },
+            stream_options: streaming.then_some(StreamOptions { include_usage: true }),
+            temperature: Some(self.config.temperature),
+            top_p: self.config.top_p,
+            frequency_penalty: self.config.frequency_penalty,
+            presence_penalty: self.config.presence_penalty,
+            n: None,
+            stop: Some(self.config.stop_sequences.clone()).filter(|stop| !stop.is_empty()),
+            response_format: self
+                .config
+                .response_schema
+                .clone()
+                .map(|json_schema| ResponseFormat { kind: "json_schema", json_schema }),
+        })
+    }
+
+    /// Computes the response cache key for a single message
+    ///
+    /// The key covers the model, max_tokens, the sampling settings, any response schema, stop sequences
+    /// and every message sent, including the system prompt, so a change to any of them misses the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The user message to send
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String>` - The hex-encoded key or an error
+    pub fn cache_key(&self, message: Message) -> Result<String> {
+        let request = self.build_request(vec![message], false)?;
+        let sampling = cache::Sampling {
+            temperature: request.temperature.unwrap_or_default(),
+            top_p: request.top_p,
+            frequency_penalty: request.frequency_penalty,
+            presence_penalty: request.presence_penalty,
+            response_format: request.response_format.as_ref().map(|format| &format.json_schema),
+        };
+        let stop = request.stop.as_deref().unwrap_or_default();
+        Ok(cache::cache_key(&request.model, request.max_tokens, &sampling, stop, &request.messages))
+    }
+
+    /// Renders the request that would be sent for these messages, without sending it
+    ///
+    /// The API key travels in a header, so it never appears in the rendered body.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - A vector of messages representing the conversation history
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String>` - The request body as pretty-printed JSON or an error
+    pub fn dry_run_request(&self, messages: Vec<Message>) -> Result<String> {
+        let request = self.build_request(messages, self.config.use_streaming)?;
+        serde_json::to_string_pretty(&request)
+            .map_err(|e| KonaError::ApiError(format!("Failed to serialize request: {}", e)))
     }
 
     /// Sends a single message to the OpenRouter API and waits for the complete response
@@ -142,10 +1227,7 @@ impl OpenRouterClient {
     /// * `Result<String>` - The response from the API or an error
     pub async fn send_message(&self, message: &str) -> Result<String> {
         // Call the non-streaming version with a single message
-        let messages = vec![Message {
-            role: "user".to_string(),
-            content: message.to_string(),
-        }];
+        let messages = vec![Message::new("user", message)];
         self.send_message_with_history(messages).await
     }
 
@@ -160,7 +1242,97 @@ impl OpenRouterClient {
     /// * `Result<String>` - The response from the API or an error
     pub async fn send_message_with_history(&self, messages: Vec<Message>) -> Result<String> {
         // Call the non-streaming version with message history
-        self.send_message_internal_with_history(messages, false).await
+        let (response, _usage) = self.send_message_internal_with_history(messages, false).await?;
+        Ok(response)
+    }
+
+    /// Sends a conversation history and returns the response along with its token usage
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - A vector of messages representing the conversation history
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(String, Usage)>` - The response and token counts (zero if not reported) or an error
+    pub async fn send_message_with_usage(&self, messages: Vec<Message>) -> Result<(String, Usage)> {
+        let (response, usage) = self.send_message_internal_with_history(messages, false).await?;
+        Ok((response, usage.unwrap_or_default()))
+    }
+
+    /// Sends each prompt as its own single-message request, running up to
+    /// `max_concurrent_requests` of them at once
+    ///
+    /// # Arguments
+    ///
+    /// * `prompts` - The messages to send
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Result<String>>` - One response or error per prompt, in the order given
+    pub async fn send_many(&self, prompts: Vec<String>) -> Vec<Result<String>> {
+        let conversations = prompts.into_iter().map(|prompt| vec![Message::new("user", prompt)]).collect();
+        self.send_many_with_usage(conversations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|(response, _usage)| response))
+            .collect()
+    }
+
+    /// Sends each conversation as its own request, running up to `max_concurrent_requests` of them at once
+    ///
+    /// # Arguments
+    ///
+    /// * `conversations` - The message histories to send
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Result<(String, Usage)>>` - One response and its token counts or an error per conversation, in the order given
+    pub async fn send_many_with_usage(&self, conversations: Vec<Vec<Message>>) -> Vec<Result<(String, Usage)>> {
+        futures::future::join_all(conversations.into_iter().map(|messages| self.send_message_with_usage(messages))).await
+    }
+
+    /// Requests several completions for the same conversation
+    ///
+    /// Uses the API's `n` parameter; providers that ignore or reject it return a single choice, so the
+    /// remaining completions are then requested as separate requests through `send_many_with_usage`.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - A vector of messages representing the conversation history
+    /// * `count` - How many completions to return
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Vec<String>, Usage)>` - The completions and their combined token counts or an error
+    pub async fn send_message_completions(&self, messages: Vec<Message>, count: u32) -> Result<(Vec<String>, Usage)> {
+        let count = count.max(1) as usize;
+        let request_id = self.new_request_id();
+        let (mut completions, usage) = match self
+            .execute_request(messages.clone(), false, Some(count as u32), request_id.as_deref())
+            .await
+        {
+            Ok(result) => result,
+            Err(err) if count > 1 && rejects_choice_count(&err) => {
+                debug!("Model rejected n={}, requesting completions separately: {}", count, err);
+                (Vec::new(), None)
+            }
+            Err(err) => return Err(tag_request_id(err, request_id.as_deref())),
+        };
+        let mut total = usage.unwrap_or_default();
+
+        let missing = count.saturating_sub(completions.len());
+        if missing > 0 {
+            debug!("Received {} of {} completions, requesting the rest separately", completions.len(), count);
+        }
+        for result in self.send_many_with_usage(vec![messages; missing]).await {
+            let (response, usage) = result?;
+            total.add(&usage);
+            completions.push(response);
+        }
+
+        completions.truncate(count);
+        Ok((completions, total))
     }
 
     /// Sends a single message to the OpenRouter API and streams the response
@@ -174,13 +1346,24 @@ impl OpenRouterClient {
     /// * `Result<ResponseStream>` - A stream of response chunks or an error
     pub async fn send_message_streaming(&self, message: &str) -> Result<ResponseStream> {
         // Call the streaming version with a single message
-        let messages = vec![Message {
-            role: "user".to_string(),
-            content: message.to_string(),
-        }];
+        let messages = vec![Message::new("user", message)];
         self.send_message_streaming_with_history(messages).await
     }
 
+    /// Sends a single message and streams the response into a callback
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message content to send
+    /// * `on_chunk` - Called with each chunk as it arrives
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String>` - The full response text or an error
+    pub async fn send_message_with_callback<F: FnMut(&str)>(&self, message: &str, on_chunk: F) -> Result<String> {
+        self.send_message_streaming(message).await?.for_each_chunk(on_chunk).await
+    }
+
     /// Sends a conversation history to the OpenRouter API and streams the response
     ///
     /// # Arguments
@@ -193,125 +1376,169 @@ impl OpenRouterClient {
     pub async fn send_message_streaming_with_history(&self, messages: Vec<Message>) -> Result<ResponseStream> {
         let (sender, receiver) = mpsc::channel(100);
 
-        // If system message is set, add it as the first message
-        let mut all_messages = Vec::new();
+        self.load_model_limits().await;
+        let request = self.build_request(messages, true)?;
 
-        // Add system prompt if configured
-        if let Some(system_prompt) = &self.config.system_prompt {
-            all_messages.push(Message {
-                role: "system".to_string(),
-                content: system_prompt.clone(),
-            });
-        }
-
-        // Add user messages
-        all_messages.extend(messages);
-
-        // Map model name to OpenRouter's model format for Claude
-        // OpenRouter uses format like "anthropic/claude-3-sonnet"
-        let model_name = if self.config.model.contains("claude") && !self.config.model.starts_with("anthropic/") {
-            format!("anthropic/{}", self.config.model)
-        } else {
-            self.config.model.clone()
-        };
-
-        let request = MessageRequest {
-            model: model_name,
-            max_tokens: self.config.max_tokens,
-            messages: all_messages,
-            stream: Some(true),
-            temperature: Some(0.7), // Default temperature
-        };
+        // Moved into the reading task, so the slot is held until the response ends or the stream is dropped
+        let permit = self
+            .request_slots
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| KonaError::ApiError(format!("Request limiter closed: {}", e)))?;
+        self.throttle().await;
 
         debug!("Using API key: {}", mask_api_key(&self.config.api_key));
         debug!("Sending streaming message to OpenRouter API");
 
+        let request_id = self.new_request_id();
+        let stream = ResponseStream::new(receiver).with_request_id(request_id.clone());
+        let stream_usage = stream.usage.clone();
+        let stream_finish_reason = stream.finish_reason.clone();
+
         // Create a clone of the client for the async task
-        let client = self.client.clone();
+        let client = self.stream_client.clone();
+        let request_id_header = request_id;
+        let request_timeout_secs = self.config.request_timeout_secs;
+        let idle_timeout_secs = self.config.stream_idle_timeout_secs;
+        let raw_response = self.config.raw_response;
+        let api_key = self.config.api_key.clone();
 
         // Clone relevant data for the tokio task to avoid lifetime issues
-        #[cfg(not(test))]
-        let api_url = API_URL.to_string();
-
-        #[cfg(test)]
-        let api_url = API_URL.with(|url| url.borrow().clone());
+        let api_url = self.endpoint(COMPLETIONS_PATH);
 
         // Start a new task to handle the streaming response
-        tokio::spawn(async move {
-            match client.post(api_url)
-                .json(&request)
-                .send()
-                .await
-            {
+        let task = tokio::spawn(async move {
+            let _permit = permit;
+            let mut request_builder = client.post(api_url).json(&request);
+            if let Some(id) = &request_id_header {
+                request_builder = request_builder.header("X-Request-Id", id.as_str());
+            }
+
+            let send_result = tokio::time::timeout(
+                Duration::from_secs(request_timeout_secs),
+                request_builder.send(),
+            )
+            .await;
+
+            let send_result = match send_result {
+                Ok(result) => result,
+                Err(_) => {
+                    let error = KonaError::Timeout(format!(
+                        "OpenRouter did not start responding within {} seconds. Check your connection or raise request_timeout_secs.",
+                        request_timeout_secs
+                    ));
+                    let _ = sender.send(Err(error)).await;
+                    return;
+                }
+            };
+
+            match send_result {
                 Ok(response) => {
                     if !response.status().is_success() {
-                        let status = response.status();
-                        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                        let error = KonaError::ApiError(format!("API returned error {}: {}", status, error_text));
+                        let error = error_for_response(response).await;
                         let _ = sender.send(Err(error)).await;
                         return;
                     }
 
+                    let content_type = response
+                        .headers()
+                        .get(header::CONTENT_TYPE)
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_string());
+                    if looks_like_html(content_type.as_deref(), &[]) {
+                        let _ = sender.send(Err(KonaError::ApiError(HTML_RESPONSE_ERROR.to_string()))).await;
+                        return;
+                    }
+
                     let stream = response.bytes_stream();
                     let mut stream = stream
                         .map_err(|e| KonaError::ApiError(format!("Stream error: {}", e)));
 
-                    let mut buffer = String::new();
+                    // OpenRouter uses the SSE format: "data: {...}\n\n"
+                    let mut decoder = SseDecoder::default();
+                    let mut first_chunk = true;
+                    let mut tool_calls = ToolCallAccumulator::default();
+
+                    let idle_timeout = Duration::from_secs(idle_timeout_secs);
+
+                    loop {
+                        // Bound the wait for each chunk rather than the whole response
+                        let chunk_result = match tokio::time::timeout(idle_timeout, stream.next()).await {
+                            Ok(Some(chunk_result)) => chunk_result,
+                            Ok(None) => break,
+                            Err(_) => {
+                                let error = KonaError::Timeout(format!(
+                                    "Stream stalled: no data received for {} seconds. Raise stream_idle_timeout_secs if the model is slow.",
+                                    idle_timeout_secs
+                                ));
+                                let _ = sender.send(Err(error)).await;
+                                break;
+                            }
+                        };
 
-                    while let Some(chunk_result) = stream.next().await {
                         match chunk_result {
                             Ok(chunk) => {
-                                // Convert bytes to string
-                                if let Ok(chunk_str) = String::from_utf8(chunk.to_vec()) {
-                                    buffer.push_str(&chunk_str);
+                                // An SSE stream never starts with markup
+                                if first_chunk {
+                                    first_chunk = false;
+                                    if looks_like_html(None, &chunk) {
+                                        let _ = sender.send(Err(KonaError::ApiError(HTML_RESPONSE_ERROR.to_string()))).await;
+                                        break;
+                                    }
+                                }
 
-                                    // Process the buffer to extract events and update the buffer
-                                    // OpenRouter uses the SSE format: "data: {...}\n\n"
-                                    let lines: Vec<&str> = buffer.split("\n\n").collect();
+                                for data in decoder.push(&chunk) {
+                                    if raw_response {
+                                        eprintln!("data: {}", redact_api_key(&data, &api_key));
+                                    }
 
-                                    // Process all but the last line (which might be incomplete)
-                                    for i in 0..lines.len().saturating_sub(1) {
-                                        let line = lines[i].trim();
+                                    // Check for the completion signal
+                                    if data == "[DONE]" {
+                                        debug!("Received [DONE] event");
+                                        continue;
+                                    }
 
-                                        if line.is_empty() {
-                                            continue;
-                                        }
+                                    if let Some(error) = parse_sse_error(&data) {
+                                        error!("Error event in stream: {}", data);
+                                        let _ = sender.send(Err(error)).await;
+                                        return;
+                                    }
 
-                                        // Lines should start with "data: "
-                                        if let Some(data) = line.strip_prefix("data: ") {
-                                            // Check for the completion signal
-                                            if data == "[DONE]" {
-                                                debug!("Received [DONE] event");
-                                                continue;
-                                            }
+                                    // Extra delta fields are passed on as their own events, for front ends to show or skip
+                                    if let Some(reasoning) = parse_sse_reasoning(&data)
+                                        && sender.send(Ok(StreamEvent::Reasoning(reasoning))).await.is_err()
+                                    {
+                                        debug!("Stream receiver dropped, cancelling response");
+                                        return;
+                                    }
+                                    tool_calls.push(&data);
 
-                                            // Parse the data as JSON
-                                            match serde_json::from_str::<serde_json::Value>(data) {
-                                                Ok(json) => {
-                                                    // Extract the content delta from OpenRouter format
-                                                    if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                                                        if let Some(choice) = choices.first() {
-                                                            if let Some(delta) = choice.get("delta") {
-                                                                if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                                                    if !content.is_empty() {
-                                                                        let _ = sender.send(Ok(content.to_string())).await;
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                },
-                                                Err(e) => {
-                                                    warn!("Failed to parse event JSON: {}", e);
-                                                    warn!("Raw data: {}", data);
-                                                }
-                                            }
-                                        }
+                                    if let Some(usage) = parse_sse_usage(&data)
+                                        && let Ok(mut slot) = stream_usage.lock()
+                                    {
+                                        *slot = Some(usage);
+                                    }
+                                    if let Some(reason) = parse_sse_finish_reason(&data)
+                                        && let Ok(mut slot) = stream_finish_reason.lock()
+                                    {
+                                        *slot = Some(reason);
                                     }
 
-                                    // Keep only the last (potentially incomplete) event
-                                    if lines.len() > 0 {
-                                        buffer = lines.last().unwrap_or(&"").to_string();
+                                    // Parse the data as JSON
+                                    match parse_sse_delta(&data) {
+                                        Ok(Some(content)) => {
+                                            // The receiver is gone once the caller cancels, so stop reading
+                                            if sender.send(Ok(StreamEvent::Text(content))).await.is_err() {
+                                                debug!("Stream receiver dropped, cancelling response");
+                                                return;
+                                            }
+                                        },
+                                        Ok(None) => {},
+                                        Err(e) => {
+                                            warn!("Failed to parse event JSON: {}", e);
+                                            warn!("Raw data: {}", data);
+                                        }
                                     }
                                 }
                             },
@@ -321,15 +1548,67 @@ impl OpenRouterClient {
                             }
                         }
                     }
+
+                    for call in tool_calls.calls() {
+                        let _ = sender.send(Ok(StreamEvent::ToolCall(call))).await;
+                    }
                 },
                 Err(e) => {
-                    let error = KonaError::ApiError(format!("API request failed: {}", e));
+                    let error = map_request_error(e, request_timeout_secs);
                     let _ = sender.send(Err(error)).await;
                 }
             }
         });
 
-        Ok(ResponseStream::new(receiver))
+        Ok(stream.with_task(task))
+    }
+
+    /// Streams a response that must be valid JSON, asking the model to correct itself if it isn't
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - A vector of messages representing the conversation history
+    /// * `max_corrections` - How many times to re-ask after an invalid reply
+    /// * `on_event` - Called with each chunk and with validation progress
+    ///
+    /// # Returns
+    ///
+    /// * `Result<serde_json::Value>` - The parsed JSON or an error
+    pub async fn send_message_streaming_json<F>(
+        &self,
+        messages: Vec<Message>,
+        max_corrections: usize,
+        mut on_event: F,
+    ) -> Result<serde_json::Value>
+    where
+        F: FnMut(JsonStreamEvent<'_>),
+    {
+        let mut messages = messages;
+        let mut attempt = 0;
+
+        loop {
+            let mut stream = self.send_message_streaming_with_history(messages.clone()).await?;
+            let full_response = stream.for_each_chunk(|chunk| on_event(JsonStreamEvent::Chunk(chunk))).await?;
+
+            on_event(JsonStreamEvent::Validating);
+            match parse_json_response(&full_response) {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < max_corrections => {
+                    attempt += 1;
+                    warn!("Response was not valid JSON ({}), requesting a correction", e);
+                    on_event(JsonStreamEvent::Retrying(attempt));
+                    messages.push(Message::new("assistant", full_response));
+                    messages.push(Message::new("user", JSON_CORRECTION_PROMPT.to_string()));
+                }
+                Err(e) => {
+                    return Err(KonaError::ApiError(format!(
+                        "Response was not valid JSON after {} attempt(s): {}",
+                        attempt + 1,
+                        e
+                    )));
+                }
+            }
+        }
     }
 
     // OpenRouter streaming response handling is now directly
@@ -344,93 +1623,160 @@ impl OpenRouterClient {
     ///
     /// # Returns
     ///
-    /// * `Result<String>` - The full response text or an error
-    async fn send_message_internal_with_history(&self, messages: Vec<Message>, streaming: bool) -> Result<String> {
-        // If system message is set, add it as the first message
-        let mut all_messages = Vec::new();
+    /// * `Result<(String, Option<Usage>)>` - The full response text and reported usage, or an error
+    async fn send_message_internal_with_history(&self, messages: Vec<Message>, streaming: bool) -> Result<(String, Option<Usage>)> {
+        let request_id = self.new_request_id();
+        let mut result = self.execute_request(messages.clone(), streaming, None, request_id.as_deref()).await;
 
-        // Add system prompt if configured
-        if let Some(system_prompt) = &self.config.system_prompt {
-            all_messages.push(Message {
-                role: "system".to_string(),
-                content: system_prompt.clone(),
-            });
+        // Empty answers are usually transient, so try once more before reporting them
+        if matches!(&result, Err(KonaError::ApiError(msg)) if msg == EMPTY_RESPONSE_ERROR) {
+            warn!("OpenRouter returned an empty response, retrying once");
+            result = self.execute_request(messages, streaming, None, request_id.as_deref()).await;
         }
 
-        // Add user messages
-        all_messages.extend(messages);
+        result
+            .map(|(mut completions, usage)| (completions.swap_remove(0), usage))
+            .map_err(|e| tag_request_id(e, request_id.as_deref()))
+    }
 
-        // Map model name to OpenRouter's model format for Claude
-        // OpenRouter uses format like "anthropic/claude-3-sonnet"
-        let model_name = if self.config.model.contains("claude") && !self.config.model.starts_with("anthropic/") {
-            format!("anthropic/{}", self.config.model)
-        } else {
-            self.config.model.clone()
-        };
+    /// Builds and sends a single request, tagging it with `request_id` when present
+    ///
+    /// Returns the content of every choice (at least one), in order.
+    async fn execute_request(
+        &self,
+        messages: Vec<Message>,
+        streaming: bool,
+        n: Option<u32>,
+        request_id: Option<&str>,
+    ) -> Result<(Vec<String>, Option<Usage>)> {
+        self.load_model_limits().await;
+        let mut request = self.build_request(messages, streaming)?;
+        request.n = n.filter(|n| *n > 1);
 
-        let request = MessageRequest {
-            model: model_name,
-            max_tokens: self.config.max_tokens,
-            messages: all_messages,
-            stream: if streaming { Some(true) } else { None },
-            temperature: Some(0.7), // Default temperature
-        };
+        // Held until the response is read, so callers sending in parallel wait for a free slot
+        let _permit = self
+            .request_slots
+            .acquire()
+            .await
+            .map_err(|e| KonaError::ApiError(format!("Request limiter closed: {}", e)))?;
+        self.throttle().await;
 
         // Log the request with masked API key
         debug!("Using API key: {}", mask_api_key(&self.config.api_key));
         debug!("Sending message to OpenRouter API");
 
-        // Get the API URL depending on the build configuration
-        #[cfg(not(test))]
-        let api_url = API_URL.to_string();
-
-        #[cfg(test)]
-        let api_url = API_URL.with(|url| url.borrow().clone());
+        let api_url = self.endpoint(COMPLETIONS_PATH);
 
         // Print the full request for debugging
         debug!("Request URL: {}", api_url);
         debug!("Request body: {}", serde_json::to_string_pretty(&request).unwrap_or_default());
 
-        let response = self
-            .client
-            .post(&api_url)
-            .json(&request)
+        let mut request_builder = self.client.post(&api_url).json(&request);
+        if let Some(id) = request_id {
+            request_builder = request_builder.header("X-Request-Id", id);
+        }
+
+        let response = request_builder
             .send()
             .await
-            .map_err(|e| KonaError::ApiError(format!("API request failed: {}", e)))?;
+            .map_err(|e| map_request_error(e, self.config.request_timeout_secs))?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("API error: {} - {}", status, error_text);
-
-            // Provide a more helpful error message for authentication issues
-            if status.as_u16() == 401 {
-                return Err(KonaError::ApiError(
-                    "Authentication failed with OpenRouter. Please check that your API key is valid and properly formatted. \
-                    For OpenRouter, the API key should be from openrouter.ai and not directly from Anthropic.".to_string()
-                ));
-            }
-
-            return Err(KonaError::ApiError(format!(
-                "API returned error {}: {}",
-                status, error_text
-            )));
+            return Err(error_for_response(response).await);
         }
 
-        let response_data: MessageResponse = response
-            .json()
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        let body = response
+            .bytes()
             .await
-            .map_err(|e| KonaError::ApiError(format!("Failed to parse API response: {}", e)))?;
+            .map_err(|e| {
+                if e.is_timeout() {
+                    map_request_error(e, self.config.request_timeout_secs)
+                } else {
+                    KonaError::ApiError(format!("Failed to read API response: {}", e))
+                }
+            })?;
+
+        if self.config.raw_response {
+            let raw = serde_json::from_slice::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|value| serde_json::to_string_pretty(&value).ok())
+                .unwrap_or_else(|| String::from_utf8_lossy(&body).to_string());
+            eprintln!("{}", redact_api_key(&raw, &self.config.api_key));
+        }
+
+        // Captive portals answer with a 200 and an HTML login page
+        if looks_like_html(content_type.as_deref(), &body) {
+            error!("Expected JSON but received HTML (content type: {:?})", content_type);
+            return Err(KonaError::ApiError(HTML_RESPONSE_ERROR.to_string()));
+        }
+
+        // Some upstreams stream even when `stream` is unset
+        let is_event_stream = content_type
+            .as_deref()
+            .map(|ct| ct.to_ascii_lowercase().contains("text/event-stream"))
+            .unwrap_or(false);
+        if is_event_stream {
+            debug!("Received an event stream for a non-streaming request, accumulating deltas");
+            let body = String::from_utf8_lossy(&body);
+            let stream_error = body
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("data:"))
+                .find_map(|data| parse_sse_error(data.trim()));
+            if let Some(error) = stream_error {
+                return Err(error);
+            }
+            let finish_reason = body
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("data:"))
+                .find_map(|data| parse_sse_finish_reason(data.trim()));
+            self.set_last_finish_reason(finish_reason);
+            let full_response = accumulate_sse_content(&body);
+            if full_response.is_empty() {
+                debug!("Empty event stream response: {}", body);
+                return Err(KonaError::ApiError(EMPTY_RESPONSE_ERROR.to_string()));
+            }
+            return Ok((vec![full_response], None));
+        }
+
+        let mut response_data: MessageResponse = serde_json::from_slice(&body).map_err(|e| {
+            let preview = String::from_utf8_lossy(&body[..body.len().min(200)]).to_string();
+            KonaError::ApiError(format!("Failed to parse API response: {} (body starts with: {:?})", e, preview))
+        })?;
+
+        if let Some(error) = response_data.error.take() {
+            error!("API error in response body: {}", String::from_utf8_lossy(&body));
+            return Err(error.into_error());
+        }
 
         info!("Received response with ID: {}", response_data.id);
+        if let Some(usage) = &response_data.usage {
+            debug!(
+                "Token usage: {} prompt + {} completion = {} total",
+                usage.prompt_tokens, usage.completion_tokens, usage.total_tokens
+            );
+            if usage.cached_tokens() > 0 {
+                debug!("Prompt cache hit: {} cached prompt tokens", usage.cached_tokens());
+            }
+        }
 
-        // Extract response content from the first choice
-        if let Some(choice) = response_data.choices.first() {
-            Ok(choice.message.content.clone())
-        } else {
-            Err(KonaError::ApiError("No response content received".to_string()))
+        if is_empty_completion(&response_data) {
+            debug!("Empty response body: {}", String::from_utf8_lossy(&body));
+            return Err(KonaError::ApiError(EMPTY_RESPONSE_ERROR.to_string()));
+        }
+
+        // The first choice's finish reason stands for the response
+        match response_data.choices.first() {
+            Some(choice) => self.set_last_finish_reason(choice.finish_reason.clone()),
+            None => return Err(KonaError::ApiError(EMPTY_RESPONSE_ERROR.to_string())),
         }
+        let completions = response_data.choices.into_iter().map(|choice| choice.message.content).collect();
+        Ok((completions, response_data.usage))
     }
 }
 
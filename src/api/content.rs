@@ -0,0 +1,166 @@
+// Message content: plain text, or typed blocks for multimodal prompts
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+
+/// The content of a chat message
+///
+/// Text-only content is sent as a plain string, which every model accepts; block content is
+/// sent as an array of typed parts (OpenRouter's OpenAI-compatible format) only when it holds
+/// something other than text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+/// One typed part of a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ImageUrl { image_url: ImageUrl },
+}
+
+impl ContentBlock {
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentBlock::Text { text: text.into(), cache_control: None }
+    }
+}
+
+/// Anthropic prompt caching breakpoint: everything up to and including the marked block is cached
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl CacheControl {
+    /// The only cache type Anthropic offers, kept for about five minutes
+    pub fn ephemeral() -> Self {
+        Self { kind: "ephemeral".to_string() }
+    }
+}
+
+/// An image given by URL, or inline as a `data:` URL
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+impl MessageContent {
+    /// The text of the message, with the text blocks joined by newlines and other blocks left out
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            MessageContent::Text(text) => Cow::Borrowed(text),
+            MessageContent::Blocks(blocks) => {
+                let texts: Vec<&str> = blocks
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::Text { text, .. } => Some(text.as_str()),
+                        ContentBlock::ImageUrl { .. } => None,
+                    })
+                    .collect();
+                Cow::Owned(texts.join("\n"))
+            }
+        }
+    }
+
+    /// Whether the content is text only and can be sent as a plain string
+    pub fn is_text(&self) -> bool {
+        match self {
+            MessageContent::Text(_) => true,
+            MessageContent::Blocks(blocks) => blocks
+                .iter()
+                .all(|block| matches!(block, ContentBlock::Text { cache_control: None, .. })),
+        }
+    }
+
+    /// The same content with a prompt caching breakpoint on its last text block
+    pub fn with_cache_breakpoint(self) -> Self {
+        let mut blocks = match self {
+            MessageContent::Text(text) => vec![ContentBlock::text(text)],
+            MessageContent::Blocks(blocks) => blocks,
+        };
+        let last_text = blocks.iter_mut().rev().find_map(|block| match block {
+            ContentBlock::Text { cache_control, .. } => Some(cache_control),
+            ContentBlock::ImageUrl { .. } => None,
+        });
+        if let Some(cache_control) = last_text {
+            *cache_control = Some(CacheControl::ephemeral());
+        }
+        MessageContent::Blocks(blocks)
+    }
+}
+
+impl fmt::Display for MessageContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text())
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentBlock>> for MessageContent {
+    fn from(blocks: Vec<ContentBlock>) -> Self {
+        MessageContent::Blocks(blocks)
+    }
+}
+
+impl PartialEq<str> for MessageContent {
+    fn eq(&self, other: &str) -> bool {
+        self.is_text() && self.text() == other
+    }
+}
+
+impl PartialEq<&str> for MessageContent {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<String> for MessageContent {
+    fn eq(&self, other: &String) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl Serialize for MessageContent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MessageContent::Blocks(blocks) if !self.is_text() => blocks.serialize(serializer),
+            _ => serializer.serialize_str(&self.text()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageContent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Blocks(Vec<ContentBlock>),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => MessageContent::Text(text),
+            Raw::Blocks(blocks) => MessageContent::Blocks(blocks),
+        })
+    }
+}
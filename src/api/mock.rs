@@ -1,13 +1,29 @@
-use crate::api::{Message, ResponseStream};
+use crate::api::{Message, ResponseStream, StreamEvent};
 use crate::config::Config;
-use crate::utils::error::Result;
+use crate::utils::error::{KonaError, Result};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
 
 // Mock API client for testing
 pub struct MockOpenRouterClient {
     pub config: Config,
     pub response: Arc<Mutex<String>>,
+    // Characters per streamed chunk; `None` streams word by word, each word keeping its trailing whitespace
+    pub chunk_size: Option<usize>,
+    pub chunk_delay: Duration,
+    pub stream_mode: MockStreamMode,
+}
+
+// How a mock stream ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockStreamMode {
+    // Every chunk of the response, then the end of the stream
+    Complete,
+    // The first N chunks, then an API error
+    ErrorAfter(usize),
+    // No chunks at all
+    Empty,
 }
 
 impl MockOpenRouterClient {
@@ -15,9 +31,31 @@ impl MockOpenRouterClient {
         Self {
             config,
             response: Arc::new(Mutex::new(response)),
+            chunk_size: None,
+            chunk_delay: Duration::from_millis(50),
+            stream_mode: MockStreamMode::Complete,
         }
     }
     
+    // Fail the stream with an API error after `chunks` successful chunks
+    pub fn with_error_after(mut self, chunks: usize) -> Self {
+        self.stream_mode = MockStreamMode::ErrorAfter(chunks);
+        self
+    }
+    
+    // End the stream without yielding any chunks
+    pub fn with_empty(mut self) -> Self {
+        self.stream_mode = MockStreamMode::Empty;
+        self
+    }
+    
+    // Stream fixed-size chunks with the given delay between them
+    pub fn with_chunking(mut self, chunk_size: usize, chunk_delay: Duration) -> Self {
+        self.chunk_size = Some(chunk_size.max(1));
+        self.chunk_delay = chunk_delay;
+        self
+    }
+    
     pub fn set_response(&self, response: String) {
         let mut r = self.response.lock().unwrap();
         *r = response;
@@ -37,20 +75,47 @@ impl MockOpenRouterClient {
         let response = self.response.lock().unwrap().clone();
         let (sender, receiver) = mpsc::channel(10);
         
-        // Clone response for the spawned task
-        let response_clone = response.clone();
+        // Split the response into chunks to simulate streaming
+        let mut chunks: Vec<String> = match self.chunk_size {
+            Some(size) => {
+                let chars: Vec<char> = response.chars().collect();
+                chars.chunks(size).map(|c| c.iter().collect()).collect()
+            }
+            None => response.split_inclusive(char::is_whitespace).map(str::to_string).collect(),
+        };
+        let error = match self.stream_mode {
+            MockStreamMode::Complete => None,
+            MockStreamMode::ErrorAfter(count) => {
+                chunks.truncate(count);
+                Some(KonaError::ApiError(format!("Mock stream failed after {} chunks", count)))
+            }
+            MockStreamMode::Empty => {
+                chunks.clear();
+                None
+            }
+        };
+        let delay = self.chunk_delay;
         
-        tokio::spawn(async move {
-            // Split the response into chunks to simulate streaming
-            // For simplicity, we'll split by spaces
-            for word in response_clone.split_whitespace() {
-                let _ = sender.send(Ok(word.to_string() + " ")).await;
+        let task = tokio::spawn(async move {
+            for chunk in chunks {
+                if sender.send(Ok(StreamEvent::Text(chunk))).await.is_err() {
+                    return;
+                }
                 // Add a small delay to simulate streaming
-                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            if let Some(error) = error {
+                let _ = sender.send(Err(error)).await;
             }
         });
         
-        Ok(ResponseStream::new(receiver))
+        Ok(ResponseStream::new(receiver).with_task(task))
+    }
+    
+    pub async fn send_message_with_callback<F: FnMut(&str)>(&self, message: &str, on_chunk: F) -> Result<String> {
+        self.send_message_streaming(message).await?.for_each_chunk(on_chunk).await
     }
     
     pub async fn send_message_streaming_with_history(&self, _messages: Vec<Message>) -> Result<ResponseStream> {
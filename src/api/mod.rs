@@ -1,6 +1,13 @@
 // API client module
+pub mod cache;
 pub mod client;
-#[cfg(test)]
+pub mod content;
+pub mod models;
+pub mod schema;
+#[cfg(any(test, feature = "mock"))]
 pub mod mock;
+#[cfg(test)]
+mod tests;
 
-pub use client::{OpenRouterClient, Message, ResponseStream};
\ No newline at end of file
+pub use client::{truncation_notice, OpenRouterClient, Credits, JsonStreamEvent, KeyInfo, Message, ResponseStream, StreamEvent, Usage};
+pub use content::{ContentBlock, ImageUrl, MessageContent};
\ No newline at end of file
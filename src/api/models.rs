@@ -0,0 +1,95 @@
+// Known model limits, used to validate requests when OpenRouter's model list isn't available
+
+/// Token limits for a model available through OpenRouter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelInfo {
+    pub context_length: u32,
+    pub max_output_tokens: u32,
+}
+
+// Keys are normalized (no provider prefix, dots replaced by dashes) and matched as prefixes,
+// so dated releases like "claude-3-sonnet-20240229" resolve to their family
+const KNOWN_MODELS: &[(&str, ModelInfo)] = &[
+    ("claude-3-opus", ModelInfo { context_length: 200_000, max_output_tokens: 4_096 }),
+    ("claude-3-sonnet", ModelInfo { context_length: 200_000, max_output_tokens: 4_096 }),
+    ("claude-3-haiku", ModelInfo { context_length: 200_000, max_output_tokens: 4_096 }),
+    ("claude-3-5-sonnet", ModelInfo { context_length: 200_000, max_output_tokens: 8_192 }),
+    ("claude-3-5-haiku", ModelInfo { context_length: 200_000, max_output_tokens: 8_192 }),
+    ("claude-3-7-sonnet", ModelInfo { context_length: 200_000, max_output_tokens: 64_000 }),
+    ("claude-sonnet-4", ModelInfo { context_length: 200_000, max_output_tokens: 64_000 }),
+    ("claude-opus-4", ModelInfo { context_length: 200_000, max_output_tokens: 32_000 }),
+    ("gpt-4o-mini", ModelInfo { context_length: 128_000, max_output_tokens: 16_384 }),
+    ("gpt-4o", ModelInfo { context_length: 128_000, max_output_tokens: 16_384 }),
+];
+
+// Strip the provider prefix and unify version separators
+fn normalize_model_name(model: &str) -> String {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    name.to_lowercase().replace('.', "-")
+}
+
+/// Looks up the limits for a model, if it is one we know about
+pub fn lookup_model(model: &str) -> Option<ModelInfo> {
+    let name = normalize_model_name(model);
+    KNOWN_MODELS
+        .iter()
+        .find(|(key, _)| name.starts_with(key))
+        .map(|(_, info)| *info)
+}
+
+// Model families that accept image input, matched like KNOWN_MODELS
+const VISION_MODELS: &[&str] = &[
+    "claude-3",
+    "claude-sonnet-4",
+    "claude-opus-4",
+    "gpt-4o",
+    "gpt-4-turbo",
+    "gemini",
+    "pixtral",
+    "llama-3-2-11b-vision",
+    "llama-3-2-90b-vision",
+];
+
+/// Whether a model is known to accept images
+pub fn supports_vision(model: &str) -> bool {
+    let name = normalize_model_name(model);
+    VISION_MODELS.iter().any(|key| name.starts_with(key))
+}
+
+/// Whether a model accepts Anthropic `cache_control` breakpoints
+pub fn supports_prompt_caching(model: &str) -> bool {
+    normalize_model_name(model).starts_with("claude")
+}
+
+// Levenshtein distance between two names, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Returns up to `limit` model ids from `available` closest to `model`, best match first
+pub fn suggest_models(model: &str, available: &[String], limit: usize) -> Vec<String> {
+    let wanted = normalize_model_name(model);
+    // Anything further than half the name away is unlikely to be a typo
+    let max_distance = (wanted.chars().count() / 2).max(2);
+
+    let mut ranked: Vec<(usize, &String)> = available
+        .iter()
+        .map(|id| (edit_distance(&wanted, &normalize_model_name(id)), id))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    ranked.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+
+    ranked.into_iter().take(limit).map(|(_, id)| id.clone()).collect()
+}
@@ -0,0 +1,132 @@
+// Structured outputs for `ask --schema`: the schema sent as `response_format` and a check of the reply against it
+
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+use crate::api::client::parse_json_response;
+use crate::utils::error::{KonaError, Result};
+
+/// Reads a JSON schema file into the `json_schema` object of a `response_format`
+///
+/// The file can hold a bare schema, or `{"name": ..., "schema": ...}` in the shape the API expects.
+/// A bare schema is sent non-strict, since strict mode rejects schemas that leave properties optional
+/// or don't set `additionalProperties: false`; the wrapped form can set `"strict": true` itself.
+pub fn load_schema(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| KonaError::ConfigError(format!("Could not read schema {:?}: {}", path, e)))?;
+    let schema: Value = serde_json::from_str(&content)
+        .map_err(|e| KonaError::ConfigError(format!("Schema {:?} is not valid JSON: {}", path, e)))?;
+    if !schema.is_object() {
+        return Err(KonaError::ConfigError(format!("Schema {:?} must be a JSON object", path)));
+    }
+
+    let wrapped = schema.get("name").is_some_and(Value::is_string) && schema.get("schema").is_some_and(Value::is_object);
+    if wrapped {
+        return Ok(schema);
+    }
+
+    // Names may only use letters, digits, '_' and '-'
+    let name: String = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-').collect())
+        .filter(|name: &String| !name.is_empty())
+        .unwrap_or_else(|| "response".to_string());
+    Ok(json!({ "name": name, "schema": schema }))
+}
+
+/// Parses a reply (a surrounding code fence is allowed) and checks it against a `load_schema` result
+pub fn check_response(text: &str, json_schema: &Value) -> std::result::Result<Value, String> {
+    let value = parse_json_response(text).map_err(|e| format!("not valid JSON: {}", e))?;
+    validate(&value, json_schema.get("schema").unwrap_or(&Value::Bool(true)))?;
+    Ok(value)
+}
+
+/// Checks `value` against a JSON schema, returning the first mismatch found
+///
+/// Covers the keywords structured outputs rely on: `type`, `enum`, `const`, `properties`, `required`,
+/// `additionalProperties` and `items`. Other keywords are not checked.
+pub fn validate(value: &Value, schema: &Value) -> std::result::Result<(), String> {
+    validate_at(value, schema, "$")
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str) -> std::result::Result<(), String> {
+    // `true` and other non-object schemas accept anything
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(kind)) => vec![kind.as_str()],
+        Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|kind| has_type(value, kind)) {
+        return Err(format!("{} should be {}, got {}", path, types.join(" or "), type_name(value)));
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum")
+        && !options.contains(value)
+    {
+        return Err(format!("{} is {}, which is not one of {}", path, value, Value::Array(options.clone())));
+    }
+    if let Some(expected) = schema.get("const")
+        && value != expected
+    {
+        return Err(format!("{} should be {}, got {}", path, expected, value));
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required")
+            && let Some(key) = required.iter().filter_map(Value::as_str).find(|key| !object.contains_key(*key))
+        {
+            return Err(format!("{} is missing required property '{}'", path, key));
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, item) in object {
+            let item_path = format!("{}.{}", path, key);
+            match (properties.and_then(|properties| properties.get(key)), schema.get("additionalProperties")) {
+                (Some(property), _) => validate_at(item, property, &item_path)?,
+                (None, Some(Value::Bool(false))) => {
+                    return Err(format!("{} has unexpected property '{}'", path, key));
+                }
+                (None, Some(extra)) => validate_at(item, extra, &item_path)?,
+                (None, None) => {}
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate_at(item, item_schema, &format!("{}[{}]", path, i))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn has_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        // Unknown type names can't be checked
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
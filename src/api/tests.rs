@@ -0,0 +1,891 @@
+#[cfg(test)]
+mod tests {
+    use crate::api::client::looks_like_html;
+    use crate::api::OpenRouterClient;
+    use crate::config::Config;
+    
+    // Settings for a client talking to a mock server
+    fn config_for(server: &mockito::Server) -> Config {
+        Config {
+            api_key: "test-key".to_string(),
+            base_url: server.url(),
+            ..Config::default()
+        }
+    }
+    
+    fn client_for(server: &mockito::Server) -> OpenRouterClient {
+        OpenRouterClient::new(config_for(server)).unwrap()
+    }
+    
+    #[test]
+    fn test_looks_like_html() {
+        // HTML content type is detected regardless of body
+        assert!(looks_like_html(Some("text/html; charset=utf-8"), b""));
+        
+        // Markup body is detected even without a content type
+        assert!(looks_like_html(None, b"  \n<!DOCTYPE html><html></html>"));
+        
+        // JSON and SSE bodies pass through
+        assert!(!looks_like_html(Some("application/json"), b"{\"id\": \"123\"}"));
+        assert!(!looks_like_html(Some("text/event-stream"), b"data: {}\n\n"));
+        assert!(!looks_like_html(None, b""));
+    }
+    
+    #[test]
+    fn test_lookup_model() {
+        use crate::api::models::lookup_model;
+        
+        // Dated and prefixed names resolve to their family
+        let info = lookup_model("claude-3-sonnet-20240229").unwrap();
+        assert_eq!(info.max_output_tokens, 4_096);
+        let info = lookup_model("anthropic/claude-3.5-sonnet").unwrap();
+        assert_eq!(info.max_output_tokens, 8_192);
+        
+        // Unknown models are not limited
+        assert!(lookup_model("some-vendor/unknown-model").is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_collect_string_from_mock_stream() {
+        use crate::api::mock::MockOpenRouterClient;
+        use std::time::Duration;
+        
+        let client = MockOpenRouterClient::new(Config::default(), "Hello, streaming world!".to_string())
+            .with_chunking(4, Duration::ZERO);
+        
+        let stream = client.send_message_streaming("ignored").await.unwrap();
+        assert_eq!(stream.collect_string().await.unwrap(), "Hello, streaming world!");
+    }
+    
+    #[tokio::test]
+    async fn test_mock_stream_errors() {
+        use crate::api::mock::MockOpenRouterClient;
+        use crate::utils::error::KonaError;
+        use futures::StreamExt;
+        use std::time::Duration;
+        
+        // Two chunks arrive before the failure, and the stream ends after it
+        let client = MockOpenRouterClient::new(Config::default(), "one two three four".to_string())
+            .with_chunking(4, Duration::ZERO)
+            .with_error_after(2);
+        let mut stream = client.send_message_streaming("ignored").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "one ");
+        assert_eq!(stream.next().await.unwrap().unwrap(), "two ");
+        assert!(matches!(stream.next().await, Some(Err(KonaError::ApiError(_)))));
+        assert!(stream.next().await.is_none());
+        
+        // Collecting surfaces the error instead of a partial answer
+        let stream = client.send_message_streaming("ignored").await.unwrap();
+        assert!(stream.collect_string().await.is_err());
+        
+        // Failing before the first chunk
+        let client = MockOpenRouterClient::new(Config::default(), "unused".to_string()).with_error_after(0);
+        let mut stream = client.send_message_streaming("ignored").await.unwrap();
+        assert!(matches!(stream.next().await, Some(Err(_))));
+        
+        // An empty stream just ends
+        let client = MockOpenRouterClient::new(Config::default(), "unused".to_string()).with_empty();
+        let mut stream = client.send_message_streaming_with_history(Vec::new()).await.unwrap();
+        assert!(stream.next().await.is_none());
+        let stream = client.send_message_streaming("ignored").await.unwrap();
+        assert_eq!(stream.collect_string().await.unwrap(), "");
+    }
+    
+    #[tokio::test]
+    async fn test_send_message_with_callback() {
+        use crate::api::mock::MockOpenRouterClient;
+        use std::time::Duration;
+        
+        // Every chunk reaches the callback, in order, and the full text is returned
+        let client = MockOpenRouterClient::new(Config::default(), "Hello there, world".to_string())
+            .with_chunking(5, Duration::ZERO);
+        let mut chunks = Vec::new();
+        let response = client.send_message_with_callback("ignored", |chunk| chunks.push(chunk.to_string())).await;
+        assert_eq!(response.unwrap(), "Hello there, world");
+        assert_eq!(chunks, vec!["Hello", " ther", "e, wo", "rld"]);
+        
+        // Chunks before a failure are still delivered, then the error is returned
+        let client = client.with_error_after(2);
+        let mut chunks = Vec::new();
+        let response = client.send_message_with_callback("ignored", |chunk| chunks.push(chunk.to_string())).await;
+        assert!(response.is_err());
+        assert_eq!(chunks, vec!["Hello", " ther"]);
+        
+        // Usage and finish reason stay readable after driving a stream with a callback
+        let client = MockOpenRouterClient::new(Config::default(), "done".to_string());
+        let mut stream = client.send_message_streaming("ignored").await.unwrap();
+        assert_eq!(stream.for_each_chunk(|_| {}).await.unwrap(), "done");
+        assert_eq!(stream.finish_reason(), None);
+    }
+    
+    #[test]
+    fn test_accumulate_sse_content() {
+        use crate::api::client::accumulate_sse_content;
+        
+        let body = "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n\
+                    : keep-alive comment\n\n\
+                    data: {\"choices\":[{\"delta\":{\"content\":\", world\"}}]}\n\n\
+                    data: [DONE]\n\n";
+        assert_eq!(accumulate_sse_content(body), "Hello, world");
+        
+        // Bodies without data lines produce nothing
+        assert_eq!(accumulate_sse_content("not an event stream"), "");
+    }
+    
+    #[test]
+    fn test_schema_check() {
+        use crate::api::schema::{check_response, load_schema};
+        use serde_json::json;
+        
+        // A bare schema is wrapped and named after its file
+        let dir = std::env::temp_dir().join(format!("kona_schema_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("person.schema.json");
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "year": { "type": "integer" },
+                "tags": { "type": "array", "items": { "enum": ["math", "poetry"] } }
+            },
+            "required": ["name", "year"],
+            "additionalProperties": false
+        });
+        std::fs::write(&path, schema.to_string()).unwrap();
+        let json_schema = load_schema(&path).unwrap();
+        assert_eq!(json_schema["name"], "personschema");
+        assert_eq!(json_schema["schema"], schema);
+        assert!(json_schema.get("strict").is_none());
+        
+        // A wrapped schema is sent as written, including its own strict setting
+        let wrapped = json!({ "name": "person", "strict": true, "schema": schema });
+        std::fs::write(&path, wrapped.to_string()).unwrap();
+        assert_eq!(load_schema(&path).unwrap(), wrapped);
+        std::fs::remove_dir_all(&dir).ok();
+        
+        assert!(check_response("```json\n{\"name\": \"Ada\", \"year\": 1843}\n```", &json_schema).is_ok());
+        assert!(check_response(r#"{"name": "Ada", "year": 1843, "tags": ["math"]}"#, &json_schema).is_ok());
+        
+        let error = check_response(r#"{"name": "Ada"}"#, &json_schema).unwrap_err();
+        assert!(error.contains("missing required property 'year'"));
+        let error = check_response(r#"{"name": "Ada", "year": "1843"}"#, &json_schema).unwrap_err();
+        assert!(error.contains("$.year should be integer"));
+        let error = check_response(r#"{"name": "Ada", "year": 1843, "tags": ["chess"]}"#, &json_schema).unwrap_err();
+        assert!(error.contains("$.tags[0]"));
+        let error = check_response(r#"{"name": "Ada", "year": 1843, "age": 28}"#, &json_schema).unwrap_err();
+        assert!(error.contains("unexpected property 'age'"));
+        assert!(check_response("Ada Lovelace", &json_schema).unwrap_err().starts_with("not valid JSON"));
+    }
+    
+    #[test]
+    fn test_parse_json_response() {
+        use crate::api::client::parse_json_response;
+        
+        assert!(parse_json_response("{\"a\": 1}").is_ok());
+        
+        // Code fences around the JSON are tolerated
+        let fenced = "```json\n{\"a\": [1, 2]}\n```";
+        assert_eq!(parse_json_response(fenced).unwrap()["a"][1], 2);
+        
+        // Prose is rejected
+        assert!(parse_json_response("Sure! Here is the JSON: {}").is_err());
+    }
+    
+    #[test]
+    fn test_parse_retry_after() {
+        use crate::api::client::parse_retry_after;
+        use std::time::Duration;
+        
+        assert_eq!(parse_retry_after("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after(" 5 "), Some(Duration::from_secs(5)));
+        
+        // Dates in the past mean "retry now"
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+    
+    #[test]
+    fn test_suggest_models() {
+        use crate::api::models::suggest_models;
+        
+        let available: Vec<String> = vec![
+            "anthropic/claude-3-opus".to_string(),
+            "anthropic/claude-3.5-sonnet".to_string(),
+            "openai/gpt-4o".to_string(),
+        ];
+        
+        let suggestions = suggest_models("anthropic/claude-3.5-sonet", &available, 3);
+        assert_eq!(suggestions.first().map(String::as_str), Some("anthropic/claude-3.5-sonnet"));
+        
+        // Nothing close enough to be a typo
+        assert!(suggest_models("mistral-large", &available, 3).is_empty());
+    }
+    
+    #[test]
+    fn test_openrouter_model_id() {
+        use crate::api::client::openrouter_model_id;
+        
+        assert_eq!(openrouter_model_id("claude-3-haiku"), "anthropic/claude-3-haiku");
+        assert_eq!(openrouter_model_id("anthropic/claude-3-haiku"), "anthropic/claude-3-haiku");
+        assert_eq!(openrouter_model_id("openai/gpt-4o"), "openai/gpt-4o");
+        assert_eq!(openrouter_model_id("google/gemini-pro"), "google/gemini-pro");
+        
+        // Other bare names are left alone rather than guessed
+        assert_eq!(openrouter_model_id("gpt-4o"), "gpt-4o");
+        assert_eq!(openrouter_model_id("my-claude-finetune"), "my-claude-finetune");
+    }
+    
+    #[test]
+    fn test_model_name_warning() {
+        use crate::api::client::model_name_warning;
+        
+        assert!(model_name_warning("claude-3-haiku").is_none());
+        assert!(model_name_warning("openai/gpt-4o").is_none());
+        
+        let warning = model_name_warning("gpt-4o").unwrap();
+        assert!(warning.contains("openai/gpt-4o"));
+        assert!(model_name_warning("gemini-pro").unwrap().contains("google/gemini-pro"));
+        assert!(model_name_warning("mystery-model").unwrap().contains("provider/model"));
+    }
+    
+    #[test]
+    fn test_parse_sse_usage() {
+        use crate::api::client::parse_sse_usage;
+        
+        let data = r#"{"choices":[],"usage":{"prompt_tokens":10,"completion_tokens":5,"total_tokens":15}}"#;
+        let usage = parse_sse_usage(data).unwrap();
+        assert_eq!(usage.total_tokens, 15);
+        
+        assert!(parse_sse_usage(r#"{"choices":[{"delta":{"content":"hi"}}]}"#).is_none());
+        assert!(parse_sse_usage(r#"{"usage":null}"#).is_none());
+    }
+    
+    #[test]
+    fn test_sse_decoder_multibyte_split() {
+        use crate::api::client::{parse_sse_delta, SseDecoder};
+        
+        let event = "data: {\"choices\":[{\"delta\":{\"content\":\"hi 🌴\"}}]}\n\n".as_bytes();
+        // Split inside the four-byte emoji
+        let split = event.iter().position(|b| *b == 0xF0).unwrap() + 2;
+        
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(&event[..split]).is_empty());
+        let events = decoder.push(&event[split..]);
+        
+        assert_eq!(events.len(), 1);
+        assert_eq!(parse_sse_delta(&events[0]).unwrap(), Some("hi 🌴".to_string()));
+    }
+    
+    #[test]
+    fn test_drain_utf8_keeps_partial_character() {
+        use crate::api::client::drain_utf8;
+        
+        let bytes = "é".as_bytes();
+        let mut pending = vec![b'a', bytes[0]];
+        assert_eq!(drain_utf8(&mut pending), "a");
+        assert_eq!(pending, vec![bytes[0]]);
+        
+        pending.push(bytes[1]);
+        assert_eq!(drain_utf8(&mut pending), "é");
+        assert!(pending.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_abort_stops_mock_stream() {
+        use crate::api::mock::MockOpenRouterClient;
+        use futures::StreamExt;
+        use std::time::Duration;
+        
+        let client = MockOpenRouterClient::new(Config::default(), "one two three four".to_string())
+            .with_chunking(1, Duration::from_millis(50));
+        
+        let mut stream = client.send_message_streaming("ignored").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "o");
+        
+        // The aborted task drops its sender, so the stream ends instead of hanging
+        stream.abort();
+        let mut remaining = 0;
+        while stream.next().await.is_some() {
+            remaining += 1;
+        }
+        assert!(remaining < 3);
+    }
+    
+    #[test]
+    fn test_invalid_proxy_is_rejected() {
+        
+        let mut config = Config {
+            api_key: "test-key".to_string(),
+            proxy: Some("http://proxy.internal:3128".to_string()),
+            ..Config::default()
+        };
+        assert!(OpenRouterClient::new(config.clone()).is_ok());
+        
+        config.proxy = Some("not a proxy url".to_string());
+        assert!(OpenRouterClient::new(config).is_err());
+    }
+    
+    #[test]
+    fn test_response_cache() {
+        use crate::api::cache::{cache_key, ResponseCache, Sampling};
+        use crate::api::Message;
+        use std::time::Duration;
+        
+        let message = |content: &str| Message::new("user", content);
+        let greedy = Sampling::default();
+        let key = cache_key("anthropic/claude-3-haiku", 1024, &greedy, &[], &[message("Hi")]);
+        
+        // Keys are stable and change with any input
+        assert_eq!(key, cache_key("anthropic/claude-3-haiku", 1024, &greedy, &[], &[message("Hi")]));
+        assert_ne!(key, cache_key("anthropic/claude-3-opus", 1024, &greedy, &[], &[message("Hi")]));
+        assert_ne!(key, cache_key("anthropic/claude-3-haiku", 512, &greedy, &[], &[message("Hi")]));
+        assert_ne!(key, cache_key("anthropic/claude-3-haiku", 1024, &greedy, &[], &[message("Hello")]));
+        
+        // Every sampling setting counts, and an unset one differs from zero
+        let schema = serde_json::json!({"name": "answer", "schema": {"type": "object"}});
+        let variants = [
+            Sampling { temperature: 0.5, ..greedy },
+            Sampling { top_p: Some(0.9), ..greedy },
+            Sampling { top_p: Some(0.0), ..greedy },
+            Sampling { frequency_penalty: Some(0.5), ..greedy },
+            Sampling { presence_penalty: Some(0.5), ..greedy },
+            Sampling { response_format: Some(&schema), ..greedy },
+        ];
+        for sampling in &variants {
+            assert_ne!(key, cache_key("anthropic/claude-3-haiku", 1024, sampling, &[], &[message("Hi")]), "{:?}", sampling);
+        }
+        let frequency = cache_key("anthropic/claude-3-haiku", 1024, &variants[3], &[], &[message("Hi")]);
+        assert_ne!(frequency, cache_key("anthropic/claude-3-haiku", 1024, &variants[4], &[], &[message("Hi")]));
+        
+        // Stop sequences count on their own, apart from the messages
+        let stop = vec!["END".to_string()];
+        let stopped = cache_key("anthropic/claude-3-haiku", 1024, &greedy, &stop, &[message("Hi")]);
+        assert_ne!(key, stopped);
+        assert_ne!(stopped, cache_key("anthropic/claude-3-haiku", 1024, &greedy, &[], &[message("Hi"), Message::new("stop", "END")]));
+        
+        let dir = std::env::temp_dir().join(format!("kona_cache_test_{}", uuid::Uuid::new_v4()));
+        let cache = ResponseCache::with_dir(dir.clone(), Duration::from_secs(60));
+        assert!(cache.get(&key).is_none());
+        
+        cache.put(&key, "anthropic/claude-3-haiku", "Hello!").unwrap();
+        assert_eq!(cache.get(&key).as_deref(), Some("Hello!"));
+        
+        // Expired entries are ignored
+        let expired = ResponseCache::with_dir(dir.clone(), Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(expired.get(&key).is_none());
+        
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert!(cache.get(&key).is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    
+    #[test]
+    fn test_message_content_serialization() {
+        use crate::api::{ContentBlock, ImageUrl, Message};
+        use serde_json::json;
+        
+        // Plain text stays a plain string on the wire
+        let message = Message::new("user", "Hi");
+        assert_eq!(serde_json::to_value(&message).unwrap(), json!({"role": "user", "content": "Hi"}));
+        
+        // Text-only blocks collapse to a string too
+        let message = Message::new("user", vec![
+            ContentBlock::text("a"),
+            ContentBlock::text("b"),
+        ]);
+        assert_eq!(serde_json::to_value(&message).unwrap()["content"], "a\nb");
+        
+        let blocks = vec![
+            ContentBlock::text("What is this?"),
+            ContentBlock::ImageUrl { image_url: ImageUrl { url: "https://example.com/cat.png".to_string() } },
+        ];
+        let message = Message::new("user", blocks);
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["content"][0], json!({"type": "text", "text": "What is this?"}));
+        assert_eq!(value["content"][1]["image_url"]["url"], "https://example.com/cat.png");
+        assert_eq!(message.content.text(), "What is this?");
+        
+        // Both forms read back
+        let parsed: Message = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.content, message.content);
+        let parsed: Message = serde_json::from_str(r#"{"role": "user", "content": "Hi"}"#).unwrap();
+        assert_eq!(parsed.content, "Hi");
+    }
+    
+    #[test]
+    fn test_finish_reason_truncation() {
+        use crate::api::client::{parse_sse_finish_reason, truncation_notice, TRUNCATED_NOTICE};
+        
+        let last = r#"{"choices":[{"delta":{"content":"."},"finish_reason":"length"}]}"#;
+        assert_eq!(parse_sse_finish_reason(last).as_deref(), Some("length"));
+        assert_eq!(parse_sse_finish_reason(r#"{"choices":[{"delta":{"content":"hi"},"finish_reason":null}]}"#), None);
+        
+        assert_eq!(truncation_notice(Some("length")), Some(TRUNCATED_NOTICE));
+        assert_eq!(truncation_notice(Some("stop")), None);
+        assert_eq!(truncation_notice(None), None);
+    }
+    
+    #[test]
+    fn test_redact_api_key() {
+        use crate::api::client::redact_api_key;
+        
+        let key = "sk-or-v1-0123456789abcdef";
+        let redacted = redact_api_key(&format!("{{\"echo\": \"{}\"}}", key), key);
+        assert!(!redacted.contains(key));
+        assert_eq!(redact_api_key("{\"id\": \"gen-1\"}", key), "{\"id\": \"gen-1\"}");
+        assert_eq!(redact_api_key("anything", ""), "anything");
+    }
+    
+    #[test]
+    fn test_throttle_delay() {
+        use crate::api::client::throttle_delay;
+        use std::time::{Duration, Instant};
+        
+        let interval = Duration::from_millis(500);
+        let now = Instant::now();
+        
+        // The first request and requests after the interval go straight out
+        assert_eq!(throttle_delay(None, now, interval), Duration::ZERO);
+        assert_eq!(throttle_delay(Some(now), now + Duration::from_secs(1), interval), Duration::ZERO);
+        
+        // Otherwise wait out the rest of the interval
+        assert_eq!(throttle_delay(Some(now), now + Duration::from_millis(200), interval), Duration::from_millis(300));
+    }
+    
+    #[tokio::test]
+    async fn test_completions_fall_back_to_sequential_requests() {
+        use crate::api::Message;
+        
+        // An upstream that ignores `n` and always answers with one choice
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "gen-1", "choices": [{"index": 0, "message": {"role": "assistant", "content": "Idea"}, "finish_reason": "stop"}], "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}}"#)
+            .expect(3)
+            .create_async()
+            .await;
+        
+        let client = client_for(&server);
+        
+        let (completions, usage) = client.send_message_completions(vec![Message::new("user", "Ideas?")], 3).await.unwrap();
+        assert_eq!(completions, vec!["Idea", "Idea", "Idea"]);
+        assert_eq!(usage.total_tokens, 21);
+        mock.assert_async().await;
+    }
+    
+    #[tokio::test]
+    async fn test_streaming_requests_respect_concurrency_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        
+        // Each response stays open for a while, recording how many are open at once
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut server = mockito::Server::new_async().await;
+        let mock = {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            server
+                .mock("POST", "/chat/completions")
+                .with_header("content-type", "text/event-stream")
+                .with_chunked_body(move |writer| {
+                    let open = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(open, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    let result = writer.write_all(b"data: {\"choices\": [{\"delta\": {\"content\": \"Hi\"}}]}\n\ndata: [DONE]\n\n");
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    result
+                })
+                .expect(5)
+                .create_async()
+                .await
+        };
+        
+        let mut config = config_for(&server);
+        config.max_concurrent_requests = 2;
+        let client = OpenRouterClient::new(config).unwrap();
+        
+        let answers = futures::future::join_all((0..5).map(|_| async {
+            client.send_message_streaming("Hello").await?.collect_string().await
+        }))
+        .await;
+        for answer in answers {
+            assert_eq!(answer.unwrap(), "Hi");
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        mock.assert_async().await;
+    }
+    
+    #[tokio::test]
+    async fn test_completions_retry_when_n_is_rejected() {
+        use crate::api::client::rejects_choice_count;
+        use crate::api::Message;
+        use crate::utils::error::KonaError;
+        use mockito::Matcher;
+        
+        let rejected = |body: &str| KonaError::ApiError(format!("API returned error 400 Bad Request: {}", body));
+        // The provider's `param` decides, whether it comes directly or passed on by OpenRouter
+        assert!(rejects_choice_count(&rejected(r#"{"error": {"message": "Unsupported value", "param": "n", "code": "unsupported_value"}}"#)));
+        assert!(rejects_choice_count(&rejected(
+            r#"{"error": {"code": 400, "message": "Provider returned error", "metadata": {"raw": "{\"error\": {\"param\": \"n\"}}"}}}"#
+        )));
+        assert!(!rejects_choice_count(&rejected(r#"{"error": {"message": "'n' is fine, the max_tokens isn't", "param": "max_tokens"}}"#)));
+        // Without one, only a message quoting the parameter counts
+        assert!(rejects_choice_count(&rejected(r#"{"error": {"message": "Unsupported parameter: 'n'"}}"#)));
+        assert!(!rejects_choice_count(&rejected(r#"{"error": {"message": "n must be 1 for this model"}}"#)));
+        assert!(!rejects_choice_count(&rejected(r#"{"error": {"message": "max_tokens is too large"}}"#)));
+        assert!(!rejects_choice_count(&rejected("Unsupported parameter: 'n'")));
+        assert!(!rejects_choice_count(&KonaError::ApiError(
+            r#"API returned error 500 Internal Server Error: {"error": {"param": "n"}}"#.to_string()
+        )));
+        
+        // The request asking for three choices is refused; the single ones succeed
+        let mut server = mockito::Server::new_async().await;
+        let refused = server
+            .mock("POST", "/chat/completions")
+            .match_body(Matcher::Regex(r#""n":3"#.to_string()))
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error": {"message": "Unsupported parameter", "type": "invalid_request_error", "param": "n"}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let answered = server
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "gen-1", "choices": [{"index": 0, "message": {"role": "assistant", "content": "Idea"}, "finish_reason": "stop"}], "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}}"#)
+            .expect(3)
+            .create_async()
+            .await;
+        
+        let client = client_for(&server);
+        
+        let (completions, usage) = client.send_message_completions(vec![Message::new("user", "Ideas?")], 3).await.unwrap();
+        assert_eq!(completions, vec!["Idea", "Idea", "Idea"]);
+        assert_eq!(usage.total_tokens, 21);
+        refused.assert_async().await;
+        answered.assert_async().await;
+    }
+    
+    #[tokio::test]
+    async fn test_forbidden_keeps_provider_message() {
+        use crate::utils::error::KonaError;
+        
+        let mut server = mockito::Server::new_async().await;
+        let forbidden = server
+            .mock("POST", "/chat/completions")
+            .with_status(403)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error": {"code": 403, "message": "Key limit exceeded"}}"#)
+            .create_async()
+            .await;
+        
+        let client = client_for(&server);
+        
+        match client.send_message("Hello").await {
+            Err(KonaError::ApiError(message)) => assert!(message.contains("Key limit exceeded"), "{}", message),
+            other => panic!("expected an API error, got {:?}", other),
+        }
+        forbidden.assert_async().await;
+    }
+    
+    #[tokio::test]
+    async fn test_send_many_keeps_prompt_order() {
+        use mockito::Matcher;
+        
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for (prompt, answer) in [("first", "One"), ("second", "Two"), ("third", "Three")] {
+            let body = format!(
+                r#"{{"id": "gen-1", "choices": [{{"index": 0, "message": {{"role": "assistant", "content": "{}"}}, "finish_reason": "stop"}}]}}"#,
+                answer
+            );
+            mocks.push(
+                server
+                    .mock("POST", "/chat/completions")
+                    .match_body(Matcher::Regex(format!(r#""content":"{}""#, prompt)))
+                    .with_header("content-type", "application/json")
+                    .with_body(body)
+                    .create_async()
+                    .await,
+            );
+        }
+        
+        let mut config = config_for(&server);
+        config.max_concurrent_requests = 2;
+        let client = OpenRouterClient::new(config).unwrap();
+        
+        let prompts = vec!["first".to_string(), "second".to_string(), "third".to_string()];
+        let answers: Vec<String> = client.send_many(prompts).await.into_iter().map(|r| r.unwrap()).collect();
+        assert_eq!(answers, vec!["One", "Two", "Three"]);
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+    
+    #[test]
+    fn test_error_in_ok_response() {
+        use crate::api::client::{parse_sse_error, MessageResponse};
+        use crate::utils::error::KonaError;
+        
+        let message = |error: KonaError| match error {
+            KonaError::ApiError(message) => message,
+            other => panic!("expected an API error, got {:?}", other),
+        };
+        
+        let response: MessageResponse = serde_json::from_str(
+            r#"{"error": {"code": 403, "message": "Input was flagged by moderation", "metadata": {"reasons": ["violence"]}}}"#,
+        )
+        .unwrap();
+        let error = response.error.unwrap().into_error();
+        assert_eq!(message(error), "OpenRouter error 403: Input was flagged by moderation");
+        
+        let response: MessageResponse =
+            serde_json::from_str(r#"{"id": "gen-1", "choices": [], "error": {"code": "provider_error", "message": "Upstream timed out"}}"#)
+                .unwrap();
+        assert_eq!(message(response.error.unwrap().into_error()), "OpenRouter error provider_error: Upstream timed out");
+        
+        // Ordinary responses have no error
+        let response: MessageResponse = serde_json::from_str(
+            r#"{"id": "gen-1", "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}]}"#,
+        )
+        .unwrap();
+        assert!(response.error.is_none());
+        
+        // Mid-stream failures arrive as an SSE event with an `error` field
+        let event = r#"{"id": "gen-1", "error": {"code": 502, "message": "Provider returned error"}, "choices": [{"index": 0, "delta": {"content": ""}, "finish_reason": "error"}]}"#;
+        assert_eq!(message(parse_sse_error(event).unwrap()), "OpenRouter error 502: Provider returned error");
+        assert!(parse_sse_error(r#"{"choices": [{"delta": {"content": "Hi"}}]}"#).is_none());
+        assert!(parse_sse_error(r#"{"error": null}"#).is_none());
+    }
+    
+    #[test]
+    fn test_reasoning_and_tool_call_deltas() {
+        use crate::api::client::{parse_sse_delta, parse_sse_reasoning, ToolCallAccumulator};
+        
+        let reasoning = r#"{"choices": [{"index": 0, "delta": {"content": "", "reasoning": "Check the units first."}}]}"#;
+        assert_eq!(parse_sse_reasoning(reasoning), Some("Check the units first.".to_string()));
+        assert_eq!(parse_sse_delta(reasoning).unwrap(), None);
+        assert_eq!(parse_sse_reasoning(r#"{"choices": [{"delta": {"content": "Hi"}}]}"#), None);
+        
+        // Names and arguments arrive in pieces, keyed by the call's index
+        let mut tool_calls = ToolCallAccumulator::default();
+        for data in [
+            r#"{"choices": [{"delta": {"tool_calls": [{"index": 0, "id": "call_1", "function": {"name": "get_weather", "arguments": ""}}]}}]}"#,
+            r#"{"choices": [{"delta": {"tool_calls": [{"index": 0, "function": {"arguments": "{\"city\":"}}]}}]}"#,
+            r#"{"choices": [{"delta": {"tool_calls": [{"index": 1, "function": {"name": "get_time", "arguments": "{}"}}]}}]}"#,
+            r#"{"choices": [{"delta": {"tool_calls": [{"index": 0, "function": {"arguments": "\"Oslo\"}"}}]}}]}"#,
+            r#"{"choices": [{"delta": {"content": "Done"}}]}"#,
+            "[DONE]",
+        ] {
+            tool_calls.push(data);
+        }
+        assert_eq!(tool_calls.calls(), vec![r#"get_weather({"city":"Oslo"})"#, "get_time({})"]);
+        assert!(ToolCallAccumulator::default().calls().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_stream_events_keep_reasoning_out_of_the_text() {
+        use crate::api::StreamEvent;
+        
+        let body = [
+            r#"{"choices": [{"delta": {"content": "", "reasoning": "Think."}}]}"#,
+            r#"{"choices": [{"delta": {"content": "Sunny"}}]}"#,
+            r#"{"choices": [{"delta": {"tool_calls": [{"index": 0, "function": {"name": "get_weather", "arguments": "{}"}}]}}]}"#,
+            r#"{"choices": [{"delta": {}, "finish_reason": "stop"}]}"#,
+            "[DONE]",
+        ]
+        .iter()
+        .map(|data| format!("data: {}\n\n", data))
+        .collect::<String>();
+        
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .expect(2)
+            .create_async()
+            .await;
+        
+        let client = client_for(&server);
+        
+        let mut stream = client.send_message_streaming("Weather?").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next_event().await {
+            events.push(event.unwrap());
+        }
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Reasoning("Think.".to_string()),
+                StreamEvent::Text("Sunny".to_string()),
+                StreamEvent::ToolCall("get_weather({})".to_string()),
+            ]
+        );
+        
+        // Plain text consumers only see the answer
+        let stream = client.send_message_streaming("Weather?").await.unwrap();
+        assert_eq!(stream.collect_string().await.unwrap(), "Sunny");
+        mock.assert_async().await;
+    }
+    
+    #[test]
+    fn test_prompt_caching_breakpoints() {
+        use crate::api::{Message, Usage};
+        use serde_json::json;
+        
+        let mut config = Config {
+            api_key: "test-key".to_string(),
+            model: "anthropic/claude-3.5-sonnet".to_string(),
+            system_prompt: Some("You are a careful reviewer.".to_string()),
+            enable_prompt_caching: true,
+            ..Config::default()
+        };
+        let history = vec![
+            Message::new("user", "First"),
+            Message::new("assistant", "Answer"),
+            Message::new("user", "Second"),
+        ];
+        
+        let client = OpenRouterClient::new(config.clone()).unwrap();
+        let request: serde_json::Value = serde_json::from_str(&client.dry_run_request(history.clone()).unwrap()).unwrap();
+        let messages = &request["messages"];
+        assert_eq!(
+            messages[0]["content"],
+            json!([{"type": "text", "text": "You are a careful reviewer.", "cache_control": {"type": "ephemeral"}}])
+        );
+        assert_eq!(messages[2]["content"][0]["cache_control"]["type"], "ephemeral");
+        // The newest message changes every turn, so it stays a plain string
+        assert_eq!(messages[1]["content"], "First");
+        assert_eq!(messages[3]["content"], "Second");
+        
+        // Other providers never see the markers
+        config.model = "openai/gpt-4o".to_string();
+        let client = OpenRouterClient::new(config).unwrap();
+        let request: serde_json::Value = serde_json::from_str(&client.dry_run_request(history).unwrap()).unwrap();
+        assert_eq!(request["messages"][0]["content"], "You are a careful reviewer.");
+        assert_eq!(request["messages"][2]["content"], "Answer");
+        
+        let usage: Usage = serde_json::from_value(json!({
+            "prompt_tokens": 1200, "completion_tokens": 50, "total_tokens": 1250,
+            "prompt_tokens_details": {"cached_tokens": 1024}
+        }))
+        .unwrap();
+        assert_eq!(usage.cached_tokens(), 1024);
+        let mut total = Usage::default();
+        total.add(&usage);
+        total.add(&usage);
+        assert_eq!(total.cached_tokens(), 2048);
+        let plain: Usage = serde_json::from_value(json!({"prompt_tokens": 5, "prompt_tokens_details": null})).unwrap();
+        assert_eq!(plain.cached_tokens(), 0);
+    }
+    
+    #[test]
+    fn test_is_empty_completion() {
+        use crate::api::client::{is_empty_completion, MessageResponse};
+        
+        let parse = |json: &str| serde_json::from_str::<MessageResponse>(json).unwrap();
+        
+        assert!(is_empty_completion(&parse(r#"{"id": "gen-1", "choices": []}"#)));
+        assert!(is_empty_completion(&parse(
+            r#"{"id": "gen-1", "choices": [{"index": 0, "message": {"role": "assistant", "content": ""}, "finish_reason": null}]}"#
+        )));
+        
+        // Empty content that finished normally (e.g. a tiny max_tokens) isn't retried
+        assert!(!is_empty_completion(&parse(
+            r#"{"id": "gen-1", "choices": [{"index": 0, "message": {"role": "assistant", "content": ""}, "finish_reason": "length"}]}"#
+        )));
+        assert!(!is_empty_completion(&parse(
+            r#"{"id": "gen-1", "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}]}"#
+        )));
+    }
+    
+    #[tokio::test]
+    async fn test_effective_max_tokens_clamping() {
+        use crate::utils::error::KonaError;
+        
+        let client = |model: &str, max_tokens: u32, clamp: bool, base_url: &str| {
+            let config = Config {
+                api_key: "test-key".to_string(),
+                model: model.to_string(),
+                max_tokens,
+                clamp_max_tokens: clamp,
+                base_url: base_url.to_string(),
+                ..Config::default()
+            };
+            OpenRouterClient::new(config).unwrap()
+        };
+        
+        // Without the model list, the built-in table applies
+        let offline = "http://127.0.0.1:9";
+        assert_eq!(client("anthropic/claude-3-haiku", 10_000, true, offline).effective_max_tokens().unwrap(), 4_096);
+        assert_eq!(client("anthropic/claude-3-haiku", 1_000, true, offline).effective_max_tokens().unwrap(), 1_000);
+        assert!(matches!(
+            client("anthropic/claude-3-haiku", 10_000, false, offline).effective_max_tokens(),
+            Err(KonaError::ConfigError(_))
+        ));
+        assert_eq!(client("some/unknown-model", 100_000, true, offline).effective_max_tokens().unwrap(), 100_000);
+        
+        // Once fetched, OpenRouter's limits win over the table and cover models it doesn't know
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/models")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"data": [
+                    {"id": "anthropic/claude-3-haiku", "context_length": 200000, "top_provider": {"max_completion_tokens": 2048}},
+                    {"id": "some/unknown-model", "context_length": 32000, "top_provider": {"max_completion_tokens": 8000}},
+                    {"id": "some/unlimited-model", "context_length": 32000, "top_provider": {"max_completion_tokens": null}}
+                ]}"#,
+            )
+            .expect(4)
+            .create_async()
+            .await;
+        for (model, expected) in [
+            ("anthropic/claude-3-haiku", 2_048),
+            ("claude-3-haiku", 2_048),
+            ("some/unknown-model", 8_000),
+            ("some/unlimited-model", 100_000),
+        ] {
+            let client = client(model, 100_000, true, &server.url());
+            client.list_models().await.unwrap();
+            assert_eq!(client.effective_max_tokens().unwrap(), expected, "{}", model);
+        }
+        mock.assert_async().await;
+    }
+    
+    #[tokio::test]
+    async fn test_failed_model_list_is_not_refetched() {
+        use crate::api::Message;
+        
+        // A gateway without the models endpoint
+        let mut server = mockito::Server::new_async().await;
+        let models = server.mock("GET", "/models").with_status(404).expect(1).create_async().await;
+        let chat = server
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "gen-1", "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}]}"#)
+            .expect(3)
+            .create_async()
+            .await;
+        
+        let client = client_for(&server);
+        
+        for _ in 0..3 {
+            assert_eq!(client.send_message("Hello").await.unwrap(), "Hi");
+        }
+        
+        // Context window checks fall back to the built-in table without asking again
+        assert_eq!(client.context_length("anthropic/claude-3-haiku").await, Some(200_000));
+        let (messages, trimmed) = client.fit_context_window(vec![Message::new("user", "Hello")]).await;
+        assert_eq!((messages.len(), trimmed), (1, 0));
+        models.assert_async().await;
+        chat.assert_async().await;
+        
+        // Asking for the list explicitly still reports the failure
+        assert!(client.list_models().await.is_err());
+    }
+}
@@ -1,4 +1,7 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use crate::config::ConfigFormat;
 
 /// Kona - A Claude Code clone for the command line
 #[derive(Parser, Debug)]
@@ -12,10 +15,14 @@ pub struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 
-    /// Enable debug mode (shows sensitive information like API keys)
+    /// Enable debug mode (prints the configuration, with the API key masked)
     #[arg(long)]
     pub debug: bool,
 
+    /// With --debug, print the full API key instead of the masked one
+    #[arg(long, requires = "debug")]
+    pub show_secrets: bool,
+
     /// Enable streaming responses
     #[arg(long, default_value_t = true)]
     pub streaming: bool,
@@ -23,6 +30,79 @@ pub struct Cli {
     /// Disable streaming responses
     #[arg(long, default_value_t = false)]
     pub no_streaming: bool,
+
+    /// Resume a saved conversation by id in interactive mode
+    #[arg(long, value_name = "ID", conflicts_with = "continue_last")]
+    pub resume: Option<String>,
+
+    /// Read input from macOS dialogs instead of the terminal, for terminals where the TUI misbehaves
+    #[arg(long, conflicts_with_all = ["resume", "continue_last"])]
+    pub mac: bool,
+
+    /// Continue the most recently updated conversation (interactively, or one more turn with `ask`)
+    #[arg(short = 'c', long = "continue", global = true)]
+    pub continue_last: bool,
+
+    /// Print only the answer: no query echo, labels or extra blank lines, and only error logs
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Print the request that would be sent as JSON instead of calling the API
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Print the raw API response JSON (or each SSE data line when streaming) to stderr
+    #[arg(long, global = true)]
+    pub raw_response: bool,
+
+    /// While streaming, show reasoning deltas (dimmed) and any tool calls on stderr
+    #[arg(long, global = true)]
+    pub verbose_stream: bool,
+
+    /// Show how long each answer took and, when streaming, its tokens per second
+    #[arg(long, global = true)]
+    pub stats: bool,
+
+    /// Disable colored output and code highlighting (also honours NO_COLOR)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Cap the response length for this run (overrides max_tokens)
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_tokens: Option<u32>,
+
+    /// Sampling temperature for this run, from 0.0 to 2.0 (overrides temperature)
+    #[arg(long, global = true, value_name = "T", value_parser = parse_temperature)]
+    pub temperature: Option<f32>,
+
+    /// Use this config file instead of the default location (overrides KONA_CONFIG)
+    #[arg(long = "config", global = true, value_name = "PATH")]
+    pub config_path: Option<PathBuf>,
+
+    /// Don't look for a project .kona.toml in the current directory or its parents
+    #[arg(long, global = true)]
+    pub no_project_config: bool,
+
+    /// Load settings from the named profile (config.<NAME>.toml) instead of config.toml
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Use this model for this invocation only, without changing the config file
+    /// (e.g. "claude-3-haiku" or "openai/gpt-4o")
+    #[arg(long, global = true, value_name = "NAME")]
+    pub model: Option<String>,
+
+    /// Override the system prompt for this run
+    #[arg(long, global = true, value_name = "TEXT", conflicts_with = "no_system")]
+    pub system: Option<String>,
+
+    /// Send no system prompt for this run
+    #[arg(long, global = true)]
+    pub no_system: bool,
+
+    /// Write logs to this file while the TUI is running
+    #[arg(long, value_name = "PATH")]
+    pub tui_log: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -32,6 +112,64 @@ pub enum Commands {
         /// The question to ask Claude
         #[arg(required = true)]
         query: String,
+
+        /// Save the exchange as a new conversation with this title
+        #[arg(long, value_name = "TITLE")]
+        save_as: Option<String>,
+
+        /// Require the answer to be valid JSON, asking for a correction if it isn't
+        #[arg(long)]
+        json: bool,
+
+        /// Stream the answer as JSON lines: {"delta": ...} per chunk, then {"done": true, "usage": ...}
+        #[arg(long, conflicts_with = "json")]
+        stream_json: bool,
+
+        /// Also write the full response to this file
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Append to the output file instead of overwriting it
+        #[arg(long, requires = "output")]
+        append: bool,
+
+        /// Include a file's contents as context (repeatable)
+        #[arg(short, long = "file", value_name = "PATH")]
+        files: Vec<PathBuf>,
+
+        /// Attach a PNG, JPEG, WebP or GIF image for vision-capable models (repeatable)
+        #[arg(long = "image", value_name = "PATH")]
+        images: Vec<PathBuf>,
+
+        /// Stay in an interactive session afterwards to ask follow-up questions
+        #[arg(short, long, conflicts_with = "stream_json")]
+        interactive: bool,
+
+        /// Ask for output matching this JSON schema file and check the reply against it before printing
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["json", "stream_json", "interactive"])]
+        schema: Option<PathBuf>,
+
+        /// Always call the API, neither reading nor updating the response cache
+        #[arg(long, conflicts_with = "force_cache")]
+        no_cache: bool,
+
+        /// Use the response cache even when it is disabled or the temperature is above 0
+        #[arg(long)]
+        force_cache: bool,
+
+        /// End the answer when the model generates this text (repeatable, overrides stop_sequences)
+        #[arg(long = "stop", value_name = "SEQ")]
+        stop: Vec<String>,
+
+        /// Generate this many answers to the same question and print each one numbered
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..=10),
+            conflicts_with_all = ["json", "stream_json", "interactive", "save_as", "schema"]
+        )]
+        count: u32,
     },
 
     /// Initialize a new configuration file
@@ -39,8 +177,149 @@ pub enum Commands {
         /// Force overwrite of existing config
         #[arg(short, long)]
         force: bool,
+
+        /// File format for the new config: toml or json
+        #[arg(long, default_value = "toml")]
+        format: ConfigFormat,
     },
 
     /// Show current configuration
-    Config,
-}
\ No newline at end of file
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigCommands>,
+
+        /// Output format: text, or json for scripts (the API key is masked)
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Run a local HTTP server exposing POST /chat
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+
+        /// Address to bind; only change this if you trust your network
+        #[arg(long, default_value = "127.0.0.1")]
+        host: std::net::IpAddr,
+    },
+
+    /// Manage stored conversations
+    Conversations {
+        #[command(subcommand)]
+        action: ConversationCommands,
+    },
+
+    /// Import conversations from a JSON file (one conversation or an array)
+    Import {
+        /// File in the stored conversation format
+        path: PathBuf,
+    },
+
+    /// Check the configuration and API key, exiting non-zero if anything is wrong
+    Validate,
+
+    /// Diagnose the environment: config file, API key source, network, terminal and storage
+    Doctor,
+
+    /// Show the API key's usage, limits and remaining credit
+    #[command(alias = "whoami")]
+    Credits,
+
+    /// Manage the response cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommands,
+    },
+
+    /// Search stored conversations for a phrase (case-insensitive)
+    Search {
+        /// Text to look for in message contents
+        query: String,
+
+        /// Treat the query as a regular expression
+        #[arg(long)]
+        regex: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Back up the config file and restore the defaults
+    Reset {
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Also discard the stored API key
+        #[arg(long)]
+        hard: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommands {
+    /// Delete every cached response
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConversationCommands {
+    /// List stored conversations, newest first
+    List {
+        /// Group conversations under Today, Yesterday, This Week and Older
+        #[arg(long)]
+        group_by_date: bool,
+    },
+
+    /// Print the full transcript of a conversation
+    Show {
+        /// The conversation id
+        id: String,
+    },
+
+    /// Export a conversation as a Markdown transcript
+    Export {
+        /// The conversation id
+        id: String,
+
+        /// Write the transcript to this file instead of stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Append to the output file (after a separator) instead of overwriting it
+        #[arg(long, requires = "output")]
+        append: bool,
+
+        /// Keep running and append new turns as they are saved
+        #[arg(long)]
+        watch: bool,
+    },
+
+    /// Delete a conversation
+    Delete {
+        /// The conversation id
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        id: Option<String>,
+
+        /// Delete every stored conversation (asks for confirmation)
+        #[arg(long)]
+        all: bool,
+    },
+}
+// How `kona config` prints the settings
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+// Temperatures outside 0.0-2.0 are rejected by the API, so catch them while parsing
+pub(crate) fn parse_temperature(value: &str) -> Result<f32, String> {
+    let temperature: f32 = value.parse().map_err(|_| format!("'{}' is not a number", value))?;
+    if !(0.0..=2.0).contains(&temperature) {
+        return Err(format!("{} is out of range (expected 0.0 to 2.0)", temperature));
+    }
+    Ok(temperature)
+}
@@ -0,0 +1,448 @@
+// Conversation management subcommands
+
+use chrono::{DateTime, Duration, Local, Utc};
+use colored::*;
+use regex::{Regex, RegexBuilder};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+
+use crate::cli::cli::ConversationCommands;
+use crate::config::Config;
+use crate::api::Message;
+use crate::history::storage::{Conversation, ConversationStorage, ConversationSummary};
+use crate::utils::confirm;
+use crate::utils::error::{KonaError, Result};
+
+// Dispatch a `kona conversations` subcommand
+pub fn handle_conversations_command(action: ConversationCommands, config: &Config) -> Result<()> {
+    let mut storage = ConversationStorage::new()?;
+
+    match action {
+        ConversationCommands::List { group_by_date } => list_conversations(&storage, group_by_date),
+        ConversationCommands::Show { id } => show_conversation(&storage, &id, config),
+        ConversationCommands::Export { id, output, append, watch } => {
+            export_conversation(&storage, &id, config, output.as_deref(), append, watch)
+        }
+        ConversationCommands::Delete { id, all } => {
+            if all {
+                delete_all_conversations(&mut storage)
+            } else if let Some(id) = id {
+                storage.delete_conversation(&id)?;
+                println!("Deleted conversation: {}", id);
+                Ok(())
+            } else {
+                println!("Specify a conversation id or --all");
+                Ok(())
+            }
+        }
+    }
+}
+
+// Shorten a title to fit its table column without splitting characters
+fn truncate_title(title: &str, max_chars: usize) -> String {
+    if title.chars().count() <= max_chars {
+        title.to_string()
+    } else {
+        let truncated: String = title.chars().take(max_chars.saturating_sub(3)).collect();
+        format!("{}...", truncated)
+    }
+}
+
+// Date groups for `list --group-by-date`, in display order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateBucket {
+    Today,
+    Yesterday,
+    ThisWeek,
+    Older,
+}
+
+impl DateBucket {
+    fn label(&self) -> &'static str {
+        match self {
+            DateBucket::Today => "Today",
+            DateBucket::Yesterday => "Yesterday",
+            DateBucket::ThisWeek => "This Week",
+            DateBucket::Older => "Older",
+        }
+    }
+}
+
+// Bucket a timestamp by calendar day in the local timezone
+pub fn date_bucket(timestamp: DateTime<Utc>, now: DateTime<Local>) -> DateBucket {
+    let today = now.date_naive();
+    let day = timestamp.with_timezone(&Local).date_naive();
+
+    if day >= today {
+        DateBucket::Today
+    } else if day == today - Duration::days(1) {
+        DateBucket::Yesterday
+    } else if day > today - Duration::days(7) {
+        DateBucket::ThisWeek
+    } else {
+        DateBucket::Older
+    }
+}
+
+pub(crate) fn print_conversation_row(summary: &ConversationSummary) {
+    println!(
+        "{:<36}  {:<30}  {:>8}  {}",
+        summary.id,
+        truncate_title(&summary.title, 30),
+        summary.message_count,
+        summary.updated_at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    );
+}
+
+fn list_conversations(storage: &ConversationStorage, group_by_date: bool) -> Result<()> {
+    let conversations = storage.get_all_conversations();
+    if conversations.is_empty() {
+        println!("No stored conversations.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<36}  {:<30}  {:>8}  {}",
+        "ID".bold(),
+        "TITLE".bold(),
+        "MESSAGES".bold(),
+        "UPDATED".bold()
+    );
+
+    if !group_by_date {
+        for summary in &conversations {
+            print_conversation_row(summary);
+        }
+        return Ok(());
+    }
+
+    // Conversations are sorted newest first, so buckets come out in order
+    let now = Local::now();
+    let mut current_bucket = None;
+    for summary in &conversations {
+        let bucket = date_bucket(summary.updated_at, now);
+        if current_bucket != Some(bucket) {
+            println!("\n{}", bucket.label().yellow().bold());
+            current_bucket = Some(bucket);
+        }
+        print_conversation_row(summary);
+    }
+
+    Ok(())
+}
+
+fn show_conversation(storage: &ConversationStorage, id: &str, config: &Config) -> Result<()> {
+    let conversation = storage.load_conversation(id)?;
+    let assistant_label = config.assistant_label();
+
+    println!("{}", conversation.title.bold());
+    println!(
+        "Created {} - updated {}\n",
+        conversation.created_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        conversation.updated_at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    );
+
+    for message in &conversation.messages {
+        let label = match message.role.as_str() {
+            "user" => "You:".green().bold(),
+            "assistant" => format!("{}:", message_label(message, &assistant_label)).purple().bold(),
+            other => format!("{}:", other).yellow().bold(),
+        };
+        match message_time(message) {
+            Some(time) => println!("{} {}", label, time.dimmed()),
+            None => println!("{}", label),
+        }
+        println!("{}\n", message.content);
+    }
+
+    Ok(())
+}
+
+// Parse one conversation or an array of them, collecting a message for each entry that doesn't fit the schema
+pub fn parse_conversations(text: &str) -> Result<(Vec<Conversation>, Vec<String>)> {
+    let value: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| KonaError::ConfigError(format!("Import file is not valid JSON: {}", e)))?;
+
+    let entries = match value {
+        serde_json::Value::Array(entries) => entries,
+        other => vec![other],
+    };
+
+    let mut conversations = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let conversation = match serde_json::from_value::<Conversation>(entry) {
+            Ok(conversation) => conversation,
+            Err(err) => {
+                skipped.push(format!("entry {}: {}", index + 1, err));
+                continue;
+            }
+        };
+
+        let bad_role = conversation
+            .messages
+            .iter()
+            .find(|m| !matches!(m.role.as_str(), "user" | "assistant" | "system"));
+        if let Some(message) = bad_role {
+            skipped.push(format!("entry {}: unknown message role '{}'", index + 1, message.role));
+            continue;
+        }
+
+        conversations.push(conversation);
+    }
+
+    Ok((conversations, skipped))
+}
+
+// Store the conversations from a JSON file, giving new ids to any that aren't UUIDs or collide with existing ones
+pub fn import_conversations(path: &Path) -> Result<()> {
+    let text = fs::read_to_string(path)?;
+    let (conversations, skipped) = parse_conversations(&text)?;
+    let mut storage = ConversationStorage::new()?;
+
+    let mut imported = 0;
+    for mut conversation in conversations {
+        // Ids become file names, so only the UUIDs kona itself generates are kept
+        let reason = if uuid::Uuid::parse_str(&conversation.id).is_err() {
+            Some("isn't a valid id")
+        } else if storage.contains(&conversation.id) {
+            Some("already exists")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            let new_id = uuid::Uuid::new_v4().to_string();
+            println!("{:?} {}, importing as {}", conversation.id, reason, new_id);
+            conversation.id = new_id;
+        }
+        storage.save_conversation(&conversation)?;
+        imported += 1;
+    }
+
+    println!("Imported {} conversation(s).", imported);
+    if !skipped.is_empty() {
+        println!("Skipped {} invalid entr{}:", skipped.len(), if skipped.len() == 1 { "y" } else { "ies" });
+        for reason in &skipped {
+            println!("  {}", reason);
+        }
+    }
+
+    Ok(())
+}
+
+// Characters of context shown on each side of a search match
+const SNIPPET_CONTEXT: usize = 40;
+
+// Build the case-insensitive matcher for `kona search`
+pub fn search_pattern(query: &str, use_regex: bool) -> Result<Regex> {
+    let pattern = if use_regex { query.to_string() } else { regex::escape(query) };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| KonaError::ConfigError(format!("Invalid search pattern: {}", e)))
+}
+
+// Split the text around the first match into (before, match, after), trimmed to a snippet on one line
+pub fn match_snippet(content: &str, pattern: &Regex) -> Option<(String, String, String)> {
+    let found = pattern.find(content)?;
+    let flatten = |text: &str| text.replace(['\n', '\r'], " ");
+
+    let before = &content[..found.start()];
+    let skip = before.chars().count().saturating_sub(SNIPPET_CONTEXT);
+    let mut before: String = before.chars().skip(skip).collect();
+    if skip > 0 {
+        before.insert_str(0, "...");
+    }
+
+    let after = &content[found.end()..];
+    let mut after_snippet: String = after.chars().take(SNIPPET_CONTEXT).collect();
+    if after.chars().count() > SNIPPET_CONTEXT {
+        after_snippet.push_str("...");
+    }
+
+    Some((flatten(&before), flatten(found.as_str()), flatten(&after_snippet)))
+}
+
+// Print the id, title and first matching snippet of every conversation containing the query
+pub fn search_conversations(query: &str, use_regex: bool) -> Result<()> {
+    let pattern = search_pattern(query, use_regex)?;
+    let storage = ConversationStorage::new()?;
+
+    let mut matches = 0;
+    for summary in storage.get_all_conversations() {
+        let conversation = match storage.load_conversation(&summary.id) {
+            Ok(conversation) => conversation,
+            Err(err) => {
+                eprintln!("Skipping {}: {}", summary.id, err);
+                continue;
+            }
+        };
+
+        // One hit is enough to list the conversation, so stop at the first matching message
+        let snippet = conversation
+            .messages
+            .iter()
+            .find_map(|message| match_snippet(&message.content.text(), &pattern));
+
+        if let Some((before, matched, after)) = snippet {
+            matches += 1;
+            println!("{}  {}", summary.id.dimmed(), summary.title.bold());
+            println!("    {}{}{}\n", before, matched.yellow().bold(), after);
+        }
+    }
+
+    if matches == 0 {
+        println!("No conversations match \"{}\".", query);
+    }
+
+    Ok(())
+}
+
+// How often `export --watch` checks the conversation file for new turns
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Separates transcripts when appending to an existing export
+const EXPORT_SEPARATOR: &str = "\n---\n\n";
+
+// Assistant label, with the model added when the message records which one wrote it
+fn message_label(message: &Message, assistant_label: &str) -> String {
+    match &message.model {
+        Some(model) => format!("{} ({})", assistant_label, model),
+        None => assistant_label.to_string(),
+    }
+}
+
+// Local time a message was added, for transcripts; older conversations don't record it
+pub(crate) fn message_time(message: &Message) -> Option<String> {
+    message
+        .timestamp
+        .map(|timestamp| timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+}
+
+pub(crate) fn format_messages_markdown(messages: &[Message], assistant_label: &str) -> String {
+    let mut text = String::new();
+    for message in messages {
+        let label = match message.role.as_str() {
+            "user" => "You".to_string(),
+            "assistant" => message_label(message, assistant_label),
+            other => other.to_string(),
+        };
+        let time = message_time(message).map(|time| format!(" _{}_", time)).unwrap_or_default();
+        text.push_str(&format!("**{}:**{}\n\n{}\n\n", label, time, message.content.text().trim_end()));
+    }
+    text
+}
+
+fn format_conversation_markdown(conversation: &Conversation, assistant_label: &str) -> String {
+    format!(
+        "# {}\n\n_Exported {}_\n\n{}",
+        conversation.title,
+        Local::now().format("%Y-%m-%d %H:%M"),
+        format_messages_markdown(&conversation.messages, assistant_label)
+    )
+}
+
+// Write or append `text` in a single call so concurrent appenders don't interleave mid-block
+fn write_export(path: &Path, text: &str, append: bool) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+fn emit_export(output: Option<&Path>, text: &str, append: bool) -> Result<()> {
+    match output {
+        Some(path) => write_export(path, text, append),
+        None => {
+            print!("{}", text);
+            std::io::stdout().flush()?;
+            Ok(())
+        }
+    }
+}
+
+fn export_conversation(
+    storage: &ConversationStorage,
+    id: &str,
+    config: &Config,
+    output: Option<&Path>,
+    append: bool,
+    watch: bool,
+) -> Result<()> {
+    let assistant_label = config.assistant_label();
+    let conversation = storage.load_conversation(id)?;
+
+    let mut transcript = format_conversation_markdown(&conversation, &assistant_label);
+    if let Some(path) = output {
+        // Only separate from earlier transcripts when there is something to separate from
+        let has_content = fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false);
+        if append && has_content {
+            transcript = format!("{}{}", EXPORT_SEPARATOR, transcript);
+        }
+    }
+    emit_export(output, &transcript, append)?;
+    if let Some(path) = output {
+        println!("Exported conversation {} to {:?}", id, path);
+    }
+
+    if !watch {
+        return Ok(());
+    }
+
+    if output.is_some() {
+        println!("Watching for new turns (Ctrl-C to stop)...");
+    }
+    let mut exported = conversation.messages.len();
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+
+        // The session may be mid-write; try again on the next tick
+        let conversation = match storage.load_conversation(id) {
+            Ok(conversation) => conversation,
+            Err(err) => {
+                tracing::debug!("Could not reload conversation {}: {}", id, err);
+                continue;
+            }
+        };
+
+        if conversation.messages.len() > exported {
+            let new_turns = format_messages_markdown(&conversation.messages[exported..], &assistant_label);
+            emit_export(output, &new_turns, true)?;
+            exported = conversation.messages.len();
+        } else if conversation.messages.len() < exported {
+            // The conversation was edited down; resume from its new end
+            exported = conversation.messages.len();
+        }
+    }
+}
+
+fn delete_all_conversations(storage: &mut ConversationStorage) -> Result<()> {
+    let conversations = storage.get_all_conversations();
+    if conversations.is_empty() {
+        println!("No stored conversations.");
+        return Ok(());
+    }
+
+    let prompt = format!("Delete all {} conversations? This cannot be undone.", conversations.len());
+    if !confirm(&prompt)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    for summary in &conversations {
+        storage.delete_conversation(&summary.id)?;
+    }
+    println!("Deleted {} conversations.", conversations.len());
+
+    Ok(())
+}
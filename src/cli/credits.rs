@@ -0,0 +1,64 @@
+// `kona credits`: show the API key's label, usage and remaining balance
+
+use colored::*;
+use tracing::debug;
+
+use crate::api::{Credits, KeyInfo, OpenRouterClient};
+use crate::utils::error::{KonaError, Result};
+
+// Lines describing the key and, when available, the account balance
+pub fn format_account(key: &KeyInfo, credits: Option<&Credits>) -> Vec<String> {
+    let mut lines = vec![format!("Key: {}", key.label.as_deref().unwrap_or("(unlabelled)"))];
+    if key.is_free_tier {
+        lines.push("Tier: free".to_string());
+    }
+    lines.push(format!("Key usage: ${:.2}", key.usage));
+
+    match (key.limit, key.limit_remaining) {
+        (Some(limit), Some(remaining)) => lines.push(format!("Key limit: ${:.2} (${:.2} remaining)", limit, remaining)),
+        (Some(limit), None) => lines.push(format!("Key limit: ${:.2}", limit)),
+        (None, _) => lines.push("Key limit: none".to_string()),
+    }
+
+    if let Some(credits) = credits {
+        lines.push(format!(
+            "Credits: ${:.2} purchased, ${:.2} used, ${:.2} remaining",
+            credits.total_credits,
+            credits.total_usage,
+            credits.total_credits - credits.total_usage
+        ));
+    }
+
+    if let Some(rate_limit) = &key.rate_limit {
+        lines.push(format!("Rate limit: {} requests per {}", rate_limit.requests, rate_limit.interval));
+    }
+
+    lines
+}
+
+pub async fn show_credits(client: &OpenRouterClient) -> Result<()> {
+    let key = match client.key_info().await {
+        Ok(key) => key,
+        Err(KonaError::Unauthorized) => {
+            return Err(KonaError::ConfigError(
+                "OpenRouter rejected the API key. Create one at https://openrouter.ai/keys and run `kona validate` to check your setup.".to_string(),
+            ));
+        }
+        Err(err) => return Err(err),
+    };
+
+    // The balance is extra detail; keys without access to it still show their own usage
+    let credits = match client.credits().await {
+        Ok(credits) => Some(credits),
+        Err(err) => {
+            debug!("Could not fetch credits: {}", err);
+            None
+        }
+    };
+
+    println!("{}", "OpenRouter account:".bold());
+    for line in format_account(&key, credits.as_ref()) {
+        println!("  {}", line);
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+// `kona doctor`: diagnose the environment Kona runs in, printing a PASS/WARN/FAIL report
+
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cli::tui::Tui;
+use crate::config::config::expand_home;
+use crate::config::Config;
+use crate::history::storage::ConversationStorage;
+use crate::utils::error::{KonaError, Result};
+
+// Reachability only needs a response of any kind, so don't wait long for one
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+// Tally of the checks run so far
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    failures: usize,
+}
+
+impl Report {
+    fn add(&mut self, status: Status, name: &str, detail: &str) {
+        let label = match status {
+            Status::Pass => "PASS".green(),
+            Status::Warn => {
+                self.warnings += 1;
+                "WARN".yellow()
+            }
+            Status::Fail => {
+                self.failures += 1;
+                "FAIL".red()
+            }
+        };
+        println!("  {}  {}: {}", label.bold(), name, detail);
+    }
+}
+
+// Run every check; returns false if any of them failed
+pub async fn run_doctor(profile: Option<&str>) -> bool {
+    println!("{}", "Checking Kona's environment:".bold());
+    let mut report = Report::default();
+
+    let (status, detail) = check_config_file(profile);
+    report.add(status, "Config file", &detail);
+
+    if let Some(path) = Config::project_config_path() {
+        report.add(Status::Pass, "Project config", &format!("{} is layered over the user config", path.display()));
+    }
+
+    let config = match Config::with_profile(profile) {
+        Ok(config) => {
+            report.add(Status::Pass, "Configuration", "loads and passes validation");
+            Some(config)
+        }
+        Err(err) => {
+            report.add(Status::Fail, "Configuration", &err.to_string());
+            None
+        }
+    };
+
+    let (status, detail) = api_key_source(config.as_ref());
+    report.add(status, "API key source", &detail);
+
+    let (status, detail) = check_network(config.as_ref()).await;
+    report.add(status, "Network", &detail);
+
+    if Tui::is_valid_terminal_env() {
+        report.add(Status::Pass, "Terminal", "supports the TUI");
+    } else {
+        report.add(Status::Warn, "Terminal", "the TUI can't run here, so `kona` falls back to interactive mode");
+    }
+
+    let storage = storage_dir(config.as_ref()).and_then(|dir| check_writable(&dir).map(|_| dir));
+    match storage {
+        Ok(dir) => report.add(Status::Pass, "Storage directory", &format!("{} is writable", dir.display())),
+        Err(err) => report.add(Status::Fail, "Storage directory", &err.to_string()),
+    }
+
+    println!();
+    if report.failures > 0 {
+        println!("{}", format!("{} check(s) failed.", report.failures).red());
+    } else if report.warnings > 0 {
+        println!("{}", format!("No failures, {} warning(s).", report.warnings).yellow());
+    } else {
+        println!("{}", "Everything looks good.".green());
+    }
+    report.failures == 0
+}
+
+// A missing file is fine (defaults apply), one that doesn't parse is not
+fn check_config_file(profile: Option<&str>) -> (Status, String) {
+    let path = match profile {
+        Some(profile) => Config::get_profile_path(profile).ok(),
+        None => Config::get_config_path(),
+    };
+    let Some(path) = path else {
+        return (Status::Fail, "could not determine the config directory".to_string());
+    };
+
+    if !path.exists() {
+        return (Status::Warn, format!("none at {}, using defaults (create one with `kona init`)", path.display()));
+    }
+    match Config::load_from_file(&path) {
+        Some(_) => (Status::Pass, path.display().to_string()),
+        None => (Status::Fail, format!("{} exists but could not be parsed", path.display())),
+    }
+}
+
+// Where the API key comes from, in the precedence `Config::with_profile` applies
+fn api_key_source(config: Option<&Config>) -> (Status, String) {
+    if let Some(var) = Config::env_api_key_var() {
+        return (Status::Pass, format!("environment variable {}", var));
+    }
+    match config {
+        Some(config) if config.api_key_command.is_some() => (Status::Pass, "api_key_command".to_string()),
+        Some(_) => (Status::Pass, "config file".to_string()),
+        None => (
+            Status::Fail,
+            "no usable API key; set KONA_OPENROUTER_API_KEY or api_key in the config file".to_string(),
+        ),
+    }
+}
+
+// Any HTTP response means the base URL is reachable; authentication is `kona validate`'s job
+async fn check_network(config: Option<&Config>) -> (Status, String) {
+    let defaults = Config::default();
+    let config = config.unwrap_or(&defaults);
+
+    let mut builder = reqwest::Client::builder().timeout(NETWORK_TIMEOUT);
+    if let Some(proxy) = config.proxy.as_deref() {
+        match reqwest::Proxy::all(proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(err) => return (Status::Fail, format!("invalid proxy {}: {}", proxy, err)),
+        }
+    }
+    let client = match builder.build() {
+        Ok(client) => client,
+        Err(err) => return (Status::Fail, format!("could not create an HTTP client: {}", err)),
+    };
+
+    match client.get(&config.base_url).send().await {
+        Ok(response) => (Status::Pass, format!("{} answered ({})", config.base_url, response.status())),
+        Err(err) if err.is_timeout() => (
+            Status::Fail,
+            format!("{} did not answer within {}s", config.base_url, NETWORK_TIMEOUT.as_secs()),
+        ),
+        Err(err) => (Status::Fail, format!("could not reach {}: {}", config.base_url, err)),
+    }
+}
+
+// The configured storage directory, or the default one
+fn storage_dir(config: Option<&Config>) -> Result<PathBuf> {
+    match config.and_then(|config| config.storage_dir.as_deref()) {
+        Some(dir) => Ok(expand_home(dir)),
+        None => ConversationStorage::get_storage_dir(),
+    }
+}
+
+// Create the directory if needed and prove a file can be written in it
+pub(crate) fn check_writable(dir: &Path) -> Result<()> {
+    let probe = dir.join(".kona_doctor");
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b"ok"))
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| KonaError::ConfigError(format!("{} is not writable: {}", dir.display(), e)))
+}
@@ -0,0 +1,50 @@
+// Write prompts in an external editor, for the `/edit` and `/compose` interactive commands
+
+use std::env;
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use std::process::Command;
+
+use crate::utils::error::Result;
+
+// Program and arguments from $EDITOR (e.g. "code --wait"), falling back to the platform's stock editor
+pub fn editor_command(editor: Option<&str>) -> (String, Vec<String>) {
+    let fallback = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = editor.map(str::trim).filter(|e| !e.is_empty()).unwrap_or(fallback);
+
+    let mut parts = editor.split_whitespace().map(str::to_string);
+    let program = parts.next().unwrap_or_else(|| fallback.to_string());
+    (program, parts.collect())
+}
+
+// Open `initial` in the editor and return the saved text; `None` if it was left empty
+pub fn edit_in_editor(initial: &str) -> Result<Option<String>> {
+    let path = env::temp_dir().join(format!("kona-prompt-{}.md", uuid::Uuid::new_v4()));
+    fs::write(&path, initial)?;
+
+    // The temp file is removed whether or not the edit succeeded
+    let edited = run_editor(&path).and_then(|_| Ok(fs::read_to_string(&path)?));
+    let _ = fs::remove_file(&path);
+
+    let edited = edited?;
+    let text = edited.trim();
+    Ok((!text.is_empty()).then(|| text.to_string()))
+}
+
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = env::var("EDITOR").ok();
+    let (program, args) = editor_command(editor.as_deref());
+
+    let status = Command::new(&program)
+        .args(&args)
+        .arg(path)
+        .status()
+        .map_err(|err| Error::new(err.kind(), format!("Could not start editor '{}': {}", program, err)))?;
+
+    // A non-zero exit (e.g. `:cq` in vim) means the user backed out
+    if !status.success() {
+        return Err(Error::other(format!("Editor '{}' exited with {}, nothing sent", program, status)).into());
+    }
+    Ok(())
+}
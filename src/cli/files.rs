@@ -0,0 +1,70 @@
+// File context for `ask --file`: each file is prepended to the query in a labelled code block
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::utils::error::Result;
+
+// Bytes inspected when deciding whether a file is binary
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+// Read the files and format them as context; also returns a warning for each file that was cut short
+pub fn read_context_files(paths: &[PathBuf], max_bytes: usize) -> Result<(String, Vec<String>)> {
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let bytes = fs::read(path)
+            .map_err(|err| Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        files.push((path.clone(), file_text(path, bytes)?));
+    }
+    Ok(format_file_context(&files, max_bytes))
+}
+
+// Reject binary content: a NUL byte near the start or anything that isn't UTF-8
+fn file_text(path: &Path, bytes: Vec<u8>) -> Result<String> {
+    let binary = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{} appears to be a binary file and can't be used as context", path.display()),
+        )
+    };
+
+    if bytes.iter().take(BINARY_SNIFF_BYTES).any(|&b| b == 0) {
+        return Err(binary().into());
+    }
+    String::from_utf8(bytes).map_err(|_| binary().into())
+}
+
+// Wrap each file in a code block labelled with its name, keeping the total content under `max_bytes`
+pub fn format_file_context(files: &[(PathBuf, String)], max_bytes: usize) -> (String, Vec<String>) {
+    let mut remaining = max_bytes;
+    let mut blocks = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (path, content) in files {
+        let name = path.display();
+        if remaining == 0 {
+            warnings.push(format!("Skipped {}: file context limit of {} bytes reached", name, max_bytes));
+            continue;
+        }
+
+        let mut content = content.as_str();
+        if content.len() > remaining {
+            let mut end = remaining;
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            warnings.push(format!(
+                "Truncated {} to {} of {} bytes (file context limit is {} bytes)",
+                name, end, content.len(), max_bytes
+            ));
+            content = &content[..end];
+        }
+        remaining -= content.len();
+
+        let language = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        blocks.push(format!("File: {}\n```{}\n{}\n```", name, language, content.trim_end_matches('\n')));
+    }
+
+    (blocks.join("\n\n"), warnings)
+}
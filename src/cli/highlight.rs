@@ -0,0 +1,120 @@
+// Syntax highlighting for fenced code blocks in terminal output, applied as text streams in
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+const FENCE: &str = "```";
+const THEME: &str = "base16-ocean.dark";
+const RESET: &str = "\x1b[0m";
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    &THEMES.get_or_init(ThemeSet::load_defaults).themes[THEME]
+}
+
+// Highlighting only makes sense on a colour terminal; redirected output keeps the raw text
+pub fn highlighting_enabled(no_color: bool) -> bool {
+    !no_color && env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+}
+
+/// Adds terminal colours to fenced code blocks in streamed markdown
+///
+/// Prose passes straight through; code is held back a line at a time so each line can be highlighted
+/// whole. Blocks whose fence names an unknown language (or none) are left plain.
+pub struct CodeHighlighter {
+    enabled: bool,
+    // Text of the current line not yet printed
+    line: String,
+    // Set while inside a fenced block; `None` inside the block means no known language
+    code: Option<Option<HighlightLines<'static>>>,
+}
+
+impl CodeHighlighter {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, line: String::new(), code: None }
+    }
+
+    /// Feeds a chunk of the response, returning the text that can be printed now
+    pub fn push(&mut self, chunk: &str) -> String {
+        if !self.enabled {
+            return chunk.to_string();
+        }
+
+        let mut output = String::new();
+        for piece in chunk.split_inclusive('\n') {
+            self.line.push_str(piece);
+            if self.line.ends_with('\n') {
+                let line = std::mem::take(&mut self.line);
+                output.push_str(&self.finish_line(&line));
+            } else if self.code.is_none() && !could_be_fence(&self.line) {
+                // Prose is printed as it arrives
+                output.push_str(&std::mem::take(&mut self.line));
+            }
+        }
+        output
+    }
+
+    /// Flushes whatever is left once the response has ended
+    pub fn finish(&mut self) -> String {
+        let line = std::mem::take(&mut self.line);
+        let output = if line.is_empty() { line } else { self.finish_line(&line) };
+        self.code = None;
+        output
+    }
+
+    fn finish_line(&mut self, line: &str) -> String {
+        if let Some(info) = line.trim_start().strip_prefix(FENCE) {
+            if self.code.is_none() {
+                let language = info.split_whitespace().next().unwrap_or("");
+                self.code = Some(highlighter_for(language));
+                return line.to_string();
+            }
+            if info.trim().is_empty() {
+                self.code = None;
+                return line.to_string();
+            }
+        }
+
+        match &mut self.code {
+            Some(Some(highlighter)) => match highlighter.highlight_line(line, syntax_set()) {
+                Ok(ranges) => format!("{}{}", as_24_bit_terminal_escaped(&ranges, false), RESET),
+                Err(_) => line.to_string(),
+            },
+            _ => line.to_string(),
+        }
+    }
+}
+
+// Highlighter for a fence's language hint (e.g. "rust" or "py"), if syntect knows it
+fn highlighter_for(language: &str) -> Option<HighlightLines<'static>> {
+    if language.is_empty() {
+        return None;
+    }
+    syntax_set()
+        .find_syntax_by_token(language)
+        .map(|syntax| HighlightLines::new(syntax, theme()))
+}
+
+// Whether an unfinished prose line might still turn into an opening fence
+fn could_be_fence(partial: &str) -> bool {
+    let trimmed = partial.trim_start();
+    trimmed.starts_with(FENCE) || FENCE.starts_with(trimmed)
+}
+
+/// Highlights the code blocks of a complete response
+pub fn highlight_code_blocks(text: &str, enabled: bool) -> String {
+    let mut highlighter = CodeHighlighter::new(enabled);
+    let mut output = highlighter.push(text);
+    output.push_str(&highlighter.finish());
+    output
+}
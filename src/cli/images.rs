@@ -0,0 +1,73 @@
+// Image attachments for `ask --image`: each file is sent inline as a base64 data URL
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::api::{ContentBlock, ImageUrl, MessageContent};
+use crate::utils::error::Result;
+
+// Largest image accepted, matching the strictest provider limit (Anthropic's 5 MB)
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+// Media type of a supported image, identified by its magic bytes rather than the file extension
+pub fn image_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+// Read and validate each image, encoding it as an image_url block
+pub fn read_images(paths: &[PathBuf]) -> Result<Vec<ContentBlock>> {
+    paths.iter().map(|path| read_image(path)).collect()
+}
+
+fn read_image(path: &Path) -> Result<ContentBlock> {
+    let bytes = fs::read(path)
+        .map_err(|err| Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} is {} bytes; images are limited to {} bytes",
+                path.display(), bytes.len(), MAX_IMAGE_BYTES
+            ),
+        )
+        .into());
+    }
+
+    let media_type = image_media_type(&bytes).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is not a PNG, JPEG, WebP or GIF image", path.display()),
+        )
+    })?;
+
+    Ok(ContentBlock::ImageUrl {
+        image_url: ImageUrl {
+            url: format!("data:{};base64,{}", media_type, STANDARD.encode(&bytes)),
+        },
+    })
+}
+
+// The prompt text followed by any images; plain text when there are none
+pub fn with_images(text: String, images: &[ContentBlock]) -> MessageContent {
+    if images.is_empty() {
+        return MessageContent::Text(text);
+    }
+
+    let mut blocks = vec![ContentBlock::text(text)];
+    blocks.extend_from_slice(images);
+    MessageContent::Blocks(blocks)
+}
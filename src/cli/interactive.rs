@@ -1,75 +1,264 @@
 // Interactive Mode Implementation
 
+use chrono::Utc;
 use colored::*;
+use rustyline::completion::Completer;
 use rustyline::error::ReadlineError;
-use rustyline::DefaultEditor;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::{FileHistory, History};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Editor, Helper};
 // Unused but will be used when implementing streaming
 #[allow(unused_imports)]
 use std::io;
 use tracing::{debug, error, info};
 
-use crate::api::OpenRouterClient;
+use crate::api::{truncation_notice, OpenRouterClient, StreamEvent, Usage};
+use crate::cli::conversations::print_conversation_row;
+use crate::cli::editor::edit_in_editor;
+use crate::cli::highlight::{highlight_code_blocks, highlighting_enabled, CodeHighlighter};
 use crate::history::storage::{Conversation, ConversationStorage};
+use crate::utils::confirm;
 use crate::utils::error::{KonaError, Result};
+use crate::utils::flush::FlushBuffer;
 use crate::utils::mask_api_key;
+use crate::utils::spinner::Spinner;
+use crate::utils::stats::ResponseTimer;
+use crate::utils::tokens::oversized_input_warning;
 
 // Convert rustyline errors to our error type
 impl From<ReadlineError> for KonaError {
     fn from(error: ReadlineError) -> Self {
-        KonaError::IoError(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Readline error: {}", error),
-        ))
+        KonaError::IoError(std::io::Error::other(format!("Readline error: {}", error)))
+    }
+}
+
+// Without storage the session still works, it just isn't persisted
+fn open_storage() -> Option<ConversationStorage> {
+    match ConversationStorage::new() {
+        Ok(storage) => Some(storage),
+        Err(err) => {
+            error!("Conversation storage unavailable, history will not be saved: {}", err);
+            None
+        }
     }
 }
 
 // Main interactive mode function
-pub async fn start_interactive_mode(client: OpenRouterClient) -> Result<()> {
-    // For simplicity, use fallback mode for now
-    // TODO: Implement conversation history when we've fixed the current issues
-    fallback_interactive_mode(client).await
+pub async fn start_interactive_mode(client: OpenRouterClient, resume: Option<String>) -> Result<()> {
+    let storage = open_storage();
+
+    let conversation = match (resume, &storage) {
+        (Some(id), Some(storage)) => storage.load_conversation(&id)?,
+        (Some(_), None) => {
+            return Err(KonaError::ConfigError(
+                "Cannot resume a conversation because conversation storage is unavailable".to_string(),
+            ));
+        }
+        (None, _) => Conversation::new(new_conversation_title()),
+    };
+
+    interactive_mode_with_history(client, storage, conversation).await
+}
+
+// Carry on from a conversation that is already under way, e.g. the exchange from `ask --interactive`
+pub async fn continue_conversation(client: OpenRouterClient, conversation: Conversation) -> Result<()> {
+    interactive_mode_with_history(client, open_storage(), conversation).await
+}
+
+// Input history file, shared with the TUI
+pub(crate) fn history_file_path() -> Option<std::path::PathBuf> {
+    dirs::home_dir().map(|home| home.join(".kona_history"))
+}
+
+// Readline settings with the history capped at `history_size` entries
+pub fn readline_config(history_size: usize) -> Result<rustyline::Config> {
+    let config = rustyline::Config::builder()
+        .max_history_size(history_size.max(1))?
+        .build();
+    Ok(config)
+}
+
+// Input is incomplete while it ends with a backslash or has an unclosed code fence
+pub fn input_is_complete(input: &str) -> bool {
+    if input.trim_end_matches([' ', '\t']).ends_with('\\') {
+        return false;
+    }
+
+    let fences = input
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count();
+    fences % 2 == 0
 }
 
-// Fallback mode without conversation history
-async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
+// Show the reasoning and tool calls of a stream for `--verbose-stream`, on stderr so the answer stays clean
+pub fn print_stream_extra(event: &StreamEvent) {
+    match event {
+        StreamEvent::Reasoning(reasoning) => eprint!("{}", reasoning.dimmed()),
+        StreamEvent::ToolCall(call) => eprintln!("{}", format!("[tool call] {}", call).dimmed()),
+        StreamEvent::Text(_) => {}
+    }
+}
+
+// Drop the trailing backslashes used to continue lines, leaving code inside fences untouched
+pub fn join_continuation_lines(input: &str) -> String {
+    let mut in_fence = false;
+    input
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+                line
+            } else if in_fence {
+                line
+            } else {
+                line.strip_suffix('\\').unwrap_or(line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Keeps the prompt open for multi-line input
+struct MultiLineHelper;
+
+impl Validator for MultiLineHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if input_is_complete(ctx.input()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+}
+
+impl Completer for MultiLineHelper {
+    type Candidate = String;
+}
+
+impl Hinter for MultiLineHelper {
+    type Hint = String;
+}
+
+impl Highlighter for MultiLineHelper {}
+
+impl Helper for MultiLineHelper {}
+
+// Update a sampling parameter from a `/top_p`-style command; an empty value shows it, "off" clears it
+// Warning for a model OpenRouter doesn't list, with close matches; the list can lag behind OpenRouter,
+// so callers still accept the name
+pub async fn model_warning(client: &OpenRouterClient, model: &str) -> Option<String> {
+    match client.validate_model(model).await {
+        Ok(Some(suggestions)) if !suggestions.is_empty() => Some(format!(
+            "{} is not a known OpenRouter model\nDid you mean {}?",
+            model,
+            suggestions.join(", ")
+        )),
+        Ok(Some(_)) => Some(format!("{} is not a known OpenRouter model", model)),
+        Ok(None) => None,
+        Err(err) => {
+            debug!("Could not validate model: {}", err);
+            None
+        }
+    }
+}
+
+fn set_sampling_param(config: &mut crate::config::Config, name: &str, value: &str) -> Result<Option<f32>> {
+    let mut updated = config.clone();
+    let field = match name {
+        "top_p" => &mut updated.top_p,
+        "frequency_penalty" => &mut updated.frequency_penalty,
+        "presence_penalty" => &mut updated.presence_penalty,
+        _ => return Err(KonaError::ConfigError(format!("Unknown sampling parameter: {}", name))),
+    };
+
+    match value {
+        "" => return Ok(*field),
+        "off" | "none" => *field = None,
+        _ => {
+            let parsed = value
+                .parse::<f32>()
+                .map_err(|_| KonaError::ConfigError(format!("Invalid value for {}: {}", name, value)))?;
+            *field = Some(parsed);
+        }
+    }
+    let new_value = *field;
+
+    updated.validate_sampling()?;
+    *config = updated;
+    Ok(new_value)
+}
+
+// Default title for a freshly started session
+fn new_conversation_title() -> String {
+    format!("Interactive session {}", Utc::now().format("%Y-%m-%d %H:%M"))
+}
+
+// Persist the conversation if storage is available and there is something to save
+fn save_conversation(storage: &mut Option<ConversationStorage>, conversation: &Conversation) {
+    if conversation.messages.is_empty() {
+        return;
+    }
+
+    if let Some(storage) = storage.as_mut() {
+        match storage.save_conversation(conversation) {
+            Ok(_) => debug!("Saved conversation {}", conversation.id),
+            Err(err) => error!("Failed to save conversation: {}", err),
+        }
+    }
+}
+
+// Interactive REPL that records each exchange in a stored conversation
+async fn interactive_mode_with_history(
+    mut client: OpenRouterClient,
+    mut storage: Option<ConversationStorage>,
+    mut conversation: Conversation,
+) -> Result<()> {
     println!("{}", format!("🌴 {} v{}", "Kona", env!("CARGO_PKG_VERSION")).green().bold());
     println!("Enter your message (use {} for help, {} to exit)", "/help".blue(), "/exit".blue());
-    println!("Press Enter to send, Shift+Enter for new line");
+    println!("Press Enter to send, end a line with \\ to continue on the next one");
     println!();
 
-    let history_file = match dirs::home_dir() {
-        Some(mut path) => {
-            path.push(".kona_history");
-            Some(path)
-        }
-        None => None,
-    };
+    let history_file = history_file_path();
 
-    // Initialize rustyline with the simpler DefaultEditor
-    let mut rl = DefaultEditor::new()?;
-    
     // Set max history entries based on config
     let history_size = client.config.history_size;
     debug!("Setting history size to {}", history_size);
 
+    // The editor's history is capped, so older entries are dropped on load and on save
+    let mut rl: Editor<MultiLineHelper, FileHistory> = Editor::with_config(readline_config(history_size)?)?;
+    rl.set_helper(Some(MultiLineHelper));
+
     // Load history if available
     if let Some(ref history_path) = history_file {
         match rl.load_history(history_path) {
-            Ok(_) => debug!("Successfully loaded history"),
+            Ok(_) => debug!("Successfully loaded history ({} entries)", rl.history().len()),
             Err(err) => debug!("No previous history: {}", err),
         }
-        
-        // Note: DefaultEditor doesn't have a history_len method
-        // In a full implementation, we would manage history size differently
     }
 
-    // Main REPL loop
-    let mut conversation_history = Vec::new();
+    // Tokens reported by non-streaming responses during this session
+    let mut session_usage = Usage::default();
+
+    // New conversations are named after their first message unless renamed with /title
+    let mut auto_title = conversation.messages.is_empty();
+
+    if !conversation.messages.is_empty() {
+        println!(
+            "{} {} ({} messages)",
+            "Resuming conversation:".yellow(),
+            conversation.title,
+            conversation.messages.len()
+        );
+        println!();
+    }
 
     // Show instructions
     println!("Type a message and press Enter to send.");
     println!("To enter a command, type / followed by the command (e.g., /help)");
-    println!("Note: Shift+Enter for multiline input doesn't work in this mode.\n");
+    println!("For multi-line input, end a line with \\ or open a ``` code block; the message is sent once it's closed.\n");
 
     loop {
         let prompt = format!("{} ", "You:".green().bold());
@@ -77,6 +266,7 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
 
         match readline {
             Ok(line) => {
+                let line = join_continuation_lines(&line);
                 let trimmed_line = line.trim();
                 if trimmed_line.is_empty() {
                     continue;
@@ -85,6 +275,11 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
                 // Add valid input to history
                 rl.add_history_entry(line.clone())?;
 
+                // Set by /switch: answer the last question again instead of sending new input
+                let mut regenerate = false;
+                // Set by /edit and /compose: text written in the editor, sent in place of the typed line
+                let mut composed: Option<String> = None;
+
                 // Process commands
                 if trimmed_line.starts_with('/') {
                     let command = trimmed_line.split_whitespace().next().unwrap_or(trimmed_line);
@@ -93,19 +288,114 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
                             println!("\n{}", "Available commands:".yellow());
                             println!("  {} - Show this help", "/help".blue());
                             println!("  {} - Clear the conversation", "/clear".blue());
+                            println!("  {} - Rename the conversation", "/title <text>".blue());
+                            println!("  {} - Save the conversation now", "/save [title]".blue());
+                            println!("  {} - List stored conversations", "/list".blue());
+                            println!("  {} - Save this conversation and continue another", "/open <id>".blue());
                             println!("  {} - Show current configuration", "/config".blue());
                             println!("  {} - Create default config file", "/init".blue());
                             println!("  {} - Change the current model", "/model [model_name]".blue());
+                            println!("  {} - Change model and re-ask the last question", "/switch <model_name>".blue());
+                            println!("  {} - Edit the last message in $EDITOR and send it", "/edit".blue());
+                            println!("  {} - Write a new message in $EDITOR", "/compose".blue());
                             println!("  {} - Toggle streaming mode", "/stream".blue());
+                            println!(
+                                "  {} - Set a sampling parameter",
+                                "/top_p, /frequency_penalty, /presence_penalty [value|off]".blue()
+                            );
                             println!("  {} - Exit Kona", "/exit".blue());
                             println!();
                             continue;
                         }
                         "/clear" => {
-                            conversation_history.clear();
+                            // Start a fresh conversation; the previous one stays saved
+                            conversation = Conversation::new(new_conversation_title());
+                            auto_title = true;
                             println!("\n{}\n", "Conversation cleared.".yellow());
                             continue;
                         }
+                        "/save" => {
+                            let title = trimmed_line["/save".len()..].trim();
+                            if !title.is_empty() {
+                                conversation.rename(title.to_string());
+                                auto_title = false;
+                            }
+                            match storage.as_mut() {
+                                Some(storage) => match storage.save_conversation(&conversation) {
+                                    Ok(_) => {
+                                        println!("\n{} {}", "Saved conversation:".yellow(), conversation.id);
+                                        if let Ok(path) = storage.get_conversation_path(&conversation.id) {
+                                            println!("File: {}\n", path.display());
+                                        }
+                                    }
+                                    Err(err) => println!("\n{} {}\n", "Error:".red(), err),
+                                },
+                                None => println!("\n{} conversation storage is unavailable\n", "Error:".red()),
+                            }
+                            continue;
+                        }
+                        "/list" => {
+                            let Some(storage) = storage.as_ref() else {
+                                println!("\n{} conversation storage is unavailable\n", "Error:".red());
+                                continue;
+                            };
+                            let conversations = storage.get_all_conversations();
+                            if conversations.is_empty() {
+                                println!("\nNo stored conversations.\n");
+                                continue;
+                            }
+                            println!();
+                            for summary in &conversations {
+                                print_conversation_row(summary);
+                            }
+                            println!("\n{} {}\n", "Current conversation:".yellow(), conversation.id);
+                            continue;
+                        }
+                        "/open" => {
+                            let Some(id) = trimmed_line.split_whitespace().nth(1) else {
+                                println!("\nUsage: /open <id> (see /list)\n");
+                                continue;
+                            };
+                            let Some(found) = storage.as_ref().and_then(|storage| storage.resolve_id(id)) else {
+                                println!("\n{} no stored conversation matches '{}'; use /list to see them\n", "Error:".red(), id);
+                                continue;
+                            };
+                            if found == conversation.id {
+                                println!("\n{}\n", "That conversation is already open.".yellow());
+                                continue;
+                            }
+
+                            // The current conversation is saved before switching, so nothing is lost
+                            save_conversation(&mut storage, &conversation);
+                            let loaded = storage.as_ref().map(|storage| storage.load_conversation(&found));
+                            match loaded {
+                                Some(Ok(loaded)) => {
+                                    conversation = loaded;
+                                    auto_title = false;
+                                    println!(
+                                        "\n{} {} ({} messages)\n",
+                                        "Opened conversation:".yellow(),
+                                        conversation.title,
+                                        conversation.messages.len()
+                                    );
+                                }
+                                Some(Err(err)) => println!("\n{} {}\n", "Error:".red(), err),
+                                None => {}
+                            }
+                            continue;
+                        }
+                        "/title" => {
+                            let title = trimmed_line["/title".len()..].trim();
+                            if title.is_empty() {
+                                println!("\n{} {}\n", "Title:".yellow(), conversation.title);
+                            } else {
+                                conversation.rename(title.to_string());
+                                auto_title = false;
+                                save_conversation(&mut storage, &conversation);
+                                println!("\n{} {}\n", "Conversation renamed:".yellow(), conversation.title);
+                            }
+                            continue;
+                        }
                         "/config" => {
                             // Show configuration
                             println!("\n{}", "Current configuration:".yellow());
@@ -115,6 +405,13 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
                             println!("  System Prompt: {:?}", client.config.system_prompt);
                             println!("  History Size: {}", client.config.history_size);
                             println!("  Streaming: {}", if client.config.use_streaming { "enabled".green() } else { "disabled".yellow() });
+                            println!(
+                                "  Tokens Used (this session): {} ({} prompt + {} completion)",
+                                session_usage.total_tokens, session_usage.prompt_tokens, session_usage.completion_tokens
+                            );
+                            if session_usage.cached_tokens() > 0 {
+                                println!("  Cached Prompt Tokens: {}", session_usage.cached_tokens());
+                            }
 
                             if let Some(path) = crate::config::Config::get_config_path() {
                                 println!("\n  Config file: {:?}", path);
@@ -131,7 +428,7 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
                         "/init" => {
                             // Create default config
                             println!("\n{}", "Creating default config file...".yellow());
-                            match crate::config::Config::create_default_config_file() {
+                            match crate::config::Config::create_default_config_file(crate::config::ConfigFormat::Toml) {
                                 Ok(path) => {
                                     println!("  Created default config file at: {:?}", path);
                                     println!("  Please edit this file to add your API key and other settings");
@@ -149,6 +446,9 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
                             if parts.len() >= 2 {
                                 // Change the model
                                 let new_model = parts[1].to_string();
+                                if let Some(warning) = model_warning(&client, &new_model).await {
+                                    println!("\n{} {}", "Warning:".yellow(), warning);
+                                }
                                 println!("\n{} {} -> {}", "Changing model:".yellow(), client.config.model.blue(), new_model.green());
                                 client.config.model = new_model;
                             } else {
@@ -165,6 +465,60 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
                             println!();
                             continue;
                         },
+                        "/switch" => {
+                            // Re-ask on another model; the earlier answer stays for comparison but is no longer sent as context
+                            let Some(new_model) = trimmed_line.split_whitespace().nth(1).map(str::to_string) else {
+                                println!("\nUsage: /switch <model_name>\n");
+                                continue;
+                            };
+                            if conversation.last_user_message().is_none() {
+                                println!("\n{}\n", "No question to re-ask yet.".yellow());
+                                continue;
+                            }
+                            if let Some(warning) = model_warning(&client, &new_model).await {
+                                println!("\n{} {}", "Warning:".yellow(), warning);
+                            }
+                            println!("\n{} {} -> {}", "Switching model:".yellow(), client.config.model.blue(), new_model.green());
+                            client.config.model = new_model;
+                            regenerate = true;
+                        }
+                        "/edit" | "/compose" => {
+                            let initial = if command == "/edit" {
+                                match conversation.last_user_message() {
+                                    Some(message) => message.content.text().into_owned(),
+                                    None => {
+                                        println!("\n{}\n", "No message to edit yet.".yellow());
+                                        continue;
+                                    }
+                                }
+                            } else {
+                                String::new()
+                            };
+                            match edit_in_editor(&initial) {
+                                Ok(Some(text)) => {
+                                    println!("\n{}\n{}", "You:".green().bold(), text);
+                                    composed = Some(text);
+                                }
+                                Ok(None) => {
+                                    println!("\n{}\n", "Empty message, nothing sent.".yellow());
+                                    continue;
+                                }
+                                Err(err) => {
+                                    println!("\n{} {}\n", "Error:".red(), err);
+                                    continue;
+                                }
+                            }
+                        }
+                        "/top_p" | "/frequency_penalty" | "/presence_penalty" => {
+                            let name = &command[1..];
+                            let value = trimmed_line[command.len()..].trim();
+                            match set_sampling_param(&mut client.config, name, value) {
+                                Ok(Some(value)) => println!("\n{} {} = {}\n", "Sampling:".yellow(), name, value),
+                                Ok(None) => println!("\n{} {} = off\n", "Sampling:".yellow(), name),
+                                Err(err) => println!("\n{} {}\n", "Error:".red(), err),
+                            }
+                            continue;
+                        }
                         "/stream" => {
                             // Toggle streaming mode
                             client.config.use_streaming = !client.config.use_streaming;
@@ -183,62 +537,184 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
                     }
                 }
 
-                // Store user message
-                conversation_history.push(line.clone());
+                let message = composed.as_deref().unwrap_or(trimmed_line);
+
+                // A huge paste is usually a mistake, and an expensive one
+                if !regenerate
+                    && let Some(warning) = oversized_input_warning(message, client.config.max_input_chars)
+                {
+                    println!("\n{}", warning.yellow());
+                    if !confirm("Send it anyway?").unwrap_or(false) {
+                        println!("{}\n", "Not sent.".yellow());
+                        continue;
+                    }
+                }
+
+                let context = if regenerate {
+                    // Checked by /switch before setting the flag
+                    conversation.regenerate_context(client.config.history_size).unwrap_or_default()
+                } else {
+                    // Untitled conversations keep their timestamp title if the message has no usable text
+                    if auto_title {
+                        if let Some(title) = Conversation::title_from_message(message) {
+                            conversation.rename(title);
+                        }
+                        auto_title = false;
+                    }
+
+                    // Store user message and build the context sent to the API
+                    conversation.add_user_message(message.to_string());
+                    conversation.recent_messages(client.config.history_size)
+                };
+
+                // Only a newly added user message is dropped when no answer arrives
+                let discard_question = |conversation: &mut Conversation| {
+                    if !regenerate {
+                        conversation.messages.pop();
+                    }
+                };
+
+                // Show what would be sent; the message stays out of the conversation
+                if client.config.dry_run {
+                    match client.dry_run_request(context) {
+                        Ok(request) => println!("\n{}\n{}\n", "Dry run, request not sent:".yellow(), request),
+                        Err(err) => println!("\n{}: {}\n", "Error".red().bold(), err),
+                    }
+                    discard_question(&mut conversation);
+                    continue;
+                }
+
+                // Long chats are cut down to what the model can take, oldest turns first
+                let (context, trimmed) = client.fit_context_window(context).await;
+                if trimmed > 0 {
+                    println!(
+                        "\n{}",
+                        format!("[dropped the {} oldest turn(s) to fit the model's context window]", trimmed).yellow()
+                    );
+                }
 
-                // Send message to API
-                println!("\n{} ", "Claude:".purple().bold());
+                // Send message to API; a re-asked answer is labelled with its model for comparison
+                let label = if regenerate {
+                    format!("{} ({}):", client.config.assistant_label(), client.config.model)
+                } else {
+                    format!("{}:", client.config.assistant_label())
+                };
+                println!("\n{} ", label.purple().bold());
+                let model = client.config.model.clone();
 
                 // Use streaming or non-streaming based on config
                 if client.config.use_streaming {
                     // Use the streaming API
-                    use futures::StreamExt;
                     use std::io::{self, Write};
 
-                    match client.send_message_streaming(trimmed_line).await {
+                    let mut timer = ResponseTimer::start();
+                    match client.send_message_streaming_with_history(context).await {
                         Ok(mut stream) => {
                             let mut full_response = String::new();
+                            let mut complete = true;
+                            let mut highlighter = CodeHighlighter::new(highlighting_enabled(client.config.no_color));
+                            let mut flusher = FlushBuffer::new(client.config.stream_flush_chars);
+
+                            // Process the stream; Ctrl-C cancels the response but keeps the session
+                            loop {
+                                let event = tokio::select! {
+                                    event = stream.next_event() => match event {
+                                        Some(event) => event,
+                                        None => break,
+                                    },
+                                    _ = tokio::signal::ctrl_c() => {
+                                        println!("\n{}", "[interrupted]".yellow());
+                                        complete = false;
+                                        break;
+                                    }
+                                };
 
-                            // Process the stream
-                            while let Some(chunk_result) = stream.next().await {
-                                match chunk_result {
-                                    Ok(chunk) => {
-                                        print!("{}", chunk);
-                                        io::stdout().flush().ok(); // Ensure text appears immediately
+                                match event {
+                                    Ok(StreamEvent::Text(chunk)) => {
+                                        timer.chunk();
+                                        if let Some(text) = flusher.push(&highlighter.push(&chunk)) {
+                                            print!("{}", text);
+                                            io::stdout().flush().ok();
+                                        }
                                         full_response.push_str(&chunk);
                                     }
+                                    Ok(extra) => {
+                                        if client.config.verbose_stream {
+                                            print_stream_extra(&extra);
+                                        }
+                                    }
                                     Err(err) => {
                                         error!("Stream error: {}", err);
                                         println!("\n{}: {}", "Error".red().bold(), err);
+                                        complete = false;
                                         break;
                                     }
                                 }
                             }
 
+                            print!("{}{}", flusher.finish().unwrap_or_default(), highlighter.finish());
                             println!("\n"); // Add newline after response
-                            conversation_history.push(full_response);
+                            if let Some(usage) = stream.usage() {
+                                session_usage.add(&usage);
+                            }
+                            if let Some(notice) = truncation_notice(stream.finish_reason().as_deref()) {
+                                println!("{}\n", notice.yellow());
+                            }
+                            if client.config.show_stats {
+                                let tokens = stream.usage().map(|usage| usage.completion_tokens).unwrap_or(0);
+                                println!("{}\n", format!("({})", timer.footer(tokens, &full_response)).dimmed());
+                            }
+                            // Partial answers are kept (and marked) so nothing streamed is lost from history
+                            if conversation.add_streamed_assistant_message(full_response, &model, complete) {
+                                save_conversation(&mut storage, &conversation);
+                            } else {
+                                discard_question(&mut conversation);
+                            }
                         }
                         Err(err) => {
                             error!("API error: {}", err);
                             println!("{}: {}\n", "Error".red().bold(), err);
+                            discard_question(&mut conversation);
                         }
                     }
                 } else {
                     // Standard non-streaming mode
-                    match client.send_message(trimmed_line).await {
-                        Ok(response) => {
-                            println!("{}\n", response);
-                            conversation_history.push(response);
+                    let mut spinner = Spinner::start("Waiting for response...");
+                    let timer = ResponseTimer::start();
+                    let result = tokio::select! {
+                        result = client.send_message_with_usage(context) => Some(result),
+                        _ = tokio::signal::ctrl_c() => None,
+                    };
+                    spinner.stop();
+
+                    match result {
+                        // Nothing arrived before Ctrl-C, so drop the unanswered message
+                        None => {
+                            println!("{}\n", "[interrupted]".yellow());
+                            discard_question(&mut conversation);
                         }
-                        Err(err) => {
+                        Some(Ok((response, usage))) => {
+                            session_usage.add(&usage);
+                            println!("{}\n", highlight_code_blocks(&response, highlighting_enabled(client.config.no_color)));
+                            if let Some(notice) = truncation_notice(client.last_finish_reason().as_deref()) {
+                                println!("{}\n", notice.yellow());
+                            }
+                            if client.config.show_stats {
+                                println!("{}\n", format!("({})", timer.footer(usage.completion_tokens, &response)).dimmed());
+                            }
+                            conversation.add_assistant_message_with_model(response, &model);
+                            save_conversation(&mut storage, &conversation);
+                        }
+                        Some(Err(err)) => {
                             error!("API error: {}", err);
                             println!("{}: {}\n", "Error".red().bold(), err);
+                            discard_question(&mut conversation);
                         }
                     }
                 }
             }
             Err(ReadlineError::Interrupted) => {
-                // Ctrl-C
+                // Ctrl-C at the prompt; during a response it only cancels the response
                 println!("\n{}\n", "Interrupted. Goodbye!".green());
                 break;
             }
@@ -255,6 +731,11 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
         }
     }
 
+    save_conversation(&mut storage, &conversation);
+    if storage.is_some() && !conversation.messages.is_empty() {
+        println!("Conversation saved. Resume it with: kona --resume {}", conversation.id);
+    }
+
     // Save history
     if let Some(ref history_path) = history_file {
         match rl.save_history(history_path) {
@@ -266,14 +747,3 @@ async fn fallback_interactive_mode(mut client: OpenRouterClient) -> Result<()> {
     info!("Interactive mode exited");
     Ok(())
 }
-
-// Interactive mode with conversation history - TODO for future implementation
-#[allow(dead_code)]
-async fn interactive_mode_with_history(
-    _client: OpenRouterClient,
-    _storage: &mut ConversationStorage,
-    _conversation: &mut Conversation,
-) -> Result<()> {
-    // To be implemented
-    Ok(())
-}
\ No newline at end of file
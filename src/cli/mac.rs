@@ -1,14 +1,29 @@
 // Special Mac-friendly interactive mode
 
 use colored::*;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 use std::process::Command;
 use tracing::{debug, error, info};
 
-use crate::api::OpenRouterClient;
+use crate::api::{truncation_notice, OpenRouterClient};
+use crate::cli::highlight::{highlight_code_blocks, highlighting_enabled, CodeHighlighter};
 use crate::utils::error::Result;
+use crate::utils::flush::FlushBuffer;
 use crate::utils::mask_api_key;
 
+// Input comes from osascript dialogs, so this mode only works on macOS
+pub fn is_available() -> bool {
+    cfg!(target_os = "macos")
+}
+
+// Whether a dialog would reach someone: a local macOS session with output going to a terminal,
+// rather than SSH, CI or cron, where stdin also isn't a terminal but nobody can answer
+pub fn has_gui_session() -> bool {
+    is_available()
+        && io::stdout().is_terminal()
+        && ["SSH_CONNECTION", "SSH_TTY", "CI"].iter().all(|var| std::env::var_os(var).is_none())
+}
+
 // Main interactive mode function for Mac
 pub async fn start_mac_mode(mut client: OpenRouterClient) -> Result<()> {
     println!("{}", format!("🌴 {} v{}", "Kona", env!("CARGO_PKG_VERSION")).green().bold());
@@ -83,7 +98,7 @@ pub async fn start_mac_mode(mut client: OpenRouterClient) -> Result<()> {
                 "/init" => {
                     // Create default config
                     println!("\n{}", "Creating default config file...".yellow());
-                    match crate::config::Config::create_default_config_file() {
+                    match crate::config::Config::create_default_config_file(crate::config::ConfigFormat::Toml) {
                         Ok(path) => {
                             println!("  Created default config file at: {:?}", path);
                             println!("  Please edit this file to add your API key and other settings");
@@ -139,34 +154,37 @@ pub async fn start_mac_mode(mut client: OpenRouterClient) -> Result<()> {
         conversation_history.push(input.clone());
         
         // Send message to API
-        println!("\n{} ", "Claude:".purple().bold());
+        println!("\n{} ", format!("{}:", client.config.assistant_label()).purple().bold());
         
         // Use streaming or non-streaming based on config
         if client.config.use_streaming {
             // Use the streaming API
-            use futures::StreamExt;
-            
             match client.send_message_streaming(trimmed_input).await {
                 Ok(mut stream) => {
                     let mut full_response = String::new();
+                    let mut highlighter = CodeHighlighter::new(highlighting_enabled(client.config.no_color));
+                    let mut flusher = FlushBuffer::new(client.config.stream_flush_chars);
                     
-                    // Process the stream
-                    while let Some(chunk_result) = stream.next().await {
-                        match chunk_result {
-                            Ok(chunk) => {
-                                print!("{}", chunk);
-                                io::stdout().flush().ok(); // Ensure text appears immediately
-                                full_response.push_str(&chunk);
+                    // Process the stream, keeping what arrived before any error
+                    let result = stream
+                        .for_each_chunk(|chunk| {
+                            if let Some(text) = flusher.push(&highlighter.push(chunk)) {
+                                print!("{}", text);
+                                io::stdout().flush().ok();
                             }
-                            Err(err) => {
-                                error!("Stream error: {}", err);
-                                println!("\n{}: {}", "Error".red().bold(), err);
-                                break;
-                            }
-                        }
+                            full_response.push_str(chunk);
+                        })
+                        .await;
+                    if let Err(err) = result {
+                        error!("Stream error: {}", err);
+                        println!("\n{}: {}", "Error".red().bold(), err);
                     }
                     
+                    print!("{}{}", flusher.finish().unwrap_or_default(), highlighter.finish());
                     println!("\n"); // Add newline after response
+                    if let Some(notice) = truncation_notice(stream.finish_reason().as_deref()) {
+                        println!("{}\n", notice.yellow());
+                    }
                     conversation_history.push(full_response);
                 }
                 Err(err) => {
@@ -178,7 +196,10 @@ pub async fn start_mac_mode(mut client: OpenRouterClient) -> Result<()> {
             // Standard non-streaming mode
             match client.send_message(trimmed_input).await {
                 Ok(response) => {
-                    println!("{}\n", response);
+                    println!("{}\n", highlight_code_blocks(&response, highlighting_enabled(client.config.no_color)));
+                    if let Some(notice) = truncation_notice(client.last_finish_reason().as_deref()) {
+                        println!("{}\n", notice.yellow());
+                    }
                     conversation_history.push(response);
                 }
                 Err(err) => {
@@ -204,7 +225,7 @@ fn get_mac_input() -> Result<String> {
         .arg("-e")
         .arg(script)
         .output()
-        .map_err(|e| crate::utils::error::KonaError::IoError(e))?;
+        .map_err(crate::utils::error::KonaError::IoError)?;
     
     let input = String::from_utf8_lossy(&output.stdout).to_string();
     Ok(input.trim().to_string())
@@ -0,0 +1,109 @@
+// Minimal Markdown styling for assistant messages in the TUI
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+fn code_style() -> Style {
+    Style::default().fg(Color::White).bg(Color::DarkGray)
+}
+
+// Convert Markdown into styled lines: fenced code blocks, headings, bullet lists,
+// and inline **bold** / `code` spans. Anything else is shown as plain text.
+pub fn markdown_to_lines(content: &str) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+
+    for raw_line in content.lines() {
+        let trimmed = raw_line.trim_start();
+
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            let language = trimmed.trim_start_matches('`').trim();
+            if in_code_block && !language.is_empty() {
+                lines.push(Line::from(Span::styled(
+                    format!(" {} ", language),
+                    code_style().add_modifier(Modifier::ITALIC),
+                )));
+            }
+            continue;
+        }
+
+        if in_code_block {
+            lines.push(Line::from(Span::styled(format!(" {} ", raw_line), code_style())));
+            continue;
+        }
+
+        // Headings
+        if trimmed.starts_with('#') {
+            let heading = trimmed.trim_start_matches('#').trim();
+            lines.push(Line::from(Span::styled(
+                heading.to_string(),
+                Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            )));
+            continue;
+        }
+
+        // Bullet lists, indented by nesting level
+        let bullet = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+            .or_else(|| trimmed.strip_prefix("+ "));
+        if let Some(item) = bullet {
+            let depth = (raw_line.len() - trimmed.len()) / 2;
+            let mut spans = vec![Span::raw(format!("{}• ", "  ".repeat(depth + 1)))];
+            spans.extend(inline_spans(item));
+            lines.push(Line::from(spans));
+            continue;
+        }
+
+        lines.push(Line::from(inline_spans(raw_line)));
+    }
+
+    lines
+}
+
+// Split a line into spans, styling **bold** and `code` segments
+pub fn inline_spans(text: &str) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut bold = false;
+    let mut code = false;
+    let mut rest = text;
+
+    let style_for = |bold: bool, code: bool| {
+        if code {
+            code_style()
+        } else if bold {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        }
+    };
+
+    while let Some(c) = rest.chars().next() {
+        if !code && rest.starts_with("**") {
+            if !current.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut current), style_for(bold, code)));
+            }
+            bold = !bold;
+            rest = &rest[2..];
+        } else if c == '`' {
+            if !current.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut current), style_for(bold, code)));
+            }
+            code = !code;
+            rest = &rest[1..];
+        } else {
+            current.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    if !current.is_empty() {
+        spans.push(Span::styled(current, style_for(bold, code)));
+    }
+
+    spans
+}
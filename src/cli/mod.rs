@@ -1,6 +1,18 @@
 pub mod basic;
 pub mod cli;
+pub mod conversations;
+pub mod credits;
+pub mod doctor;
+pub mod editor;
+pub mod files;
+pub mod highlight;
+pub mod images;
 pub mod interactive;
 pub mod mac;
+pub mod markdown;
+pub mod pipe;
 pub mod simple;
-pub mod tui;
\ No newline at end of file
+pub mod tui;
+pub mod validate;
+#[cfg(test)]
+mod tests;
\ No newline at end of file
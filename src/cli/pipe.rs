@@ -0,0 +1,77 @@
+// Pipe mode: answer a prompt read from stdin, e.g. `cat bug.log | kona`
+
+use std::io::{self, IsTerminal, Read, Write};
+
+use crate::api::{truncation_notice, Message, OpenRouterClient};
+use crate::utils::error::{KonaError, Result};
+use crate::utils::flush::FlushBuffer;
+use crate::utils::tokens::oversized_input_warning;
+
+// Read all of stdin when it is redirected; `None` for a terminal or empty input
+pub fn read_piped_stdin() -> Result<Option<String>> {
+    let mut stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Ok(None);
+    }
+
+    let mut input = String::new();
+    stdin.read_to_string(&mut input)?;
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(input))
+}
+
+// Combine an instruction with piped input: the instruction comes first, stdin follows as context
+pub fn piped_prompt(instruction: Option<&str>, input: &str) -> String {
+    match instruction.map(str::trim).filter(|i| !i.is_empty()) {
+        Some(instruction) => format!("{}\n\n```\n{}\n```", instruction, input.trim_end()),
+        None => input.trim().to_string(),
+    }
+}
+
+// Send the prompt and print only the answer, so the output can be piped on
+pub async fn run_pipe_mode(client: &OpenRouterClient, prompt: &str) -> Result<()> {
+    let mut stdout = io::stdout();
+
+    if client.config.dry_run {
+        let messages = vec![Message::new("user", prompt)];
+        writeln!(stdout, "{}", client.dry_run_request(messages)?)?;
+        return Ok(());
+    }
+
+    if let Some(warning) = oversized_input_warning(prompt, client.config.max_input_chars) {
+        return Err(KonaError::ConfigError(format!(
+            "{}; raise max_input_chars (or KONA_MAX_INPUT_CHARS) to send it",
+            warning
+        )));
+    }
+
+    if client.config.use_streaming {
+        let mut stream = client.send_message_streaming(prompt).await?;
+        let mut flusher = FlushBuffer::new(client.config.stream_flush_chars);
+        // The first write error (e.g. a closed pipe) is kept and reported once the stream ends
+        let mut written = Ok(());
+        stream
+            .for_each_chunk(|chunk| {
+                if let Some(text) = flusher.push(chunk).filter(|_| written.is_ok()) {
+                    written = write!(stdout, "{}", text).and_then(|_| stdout.flush());
+                }
+            })
+            .await?;
+        written?;
+        writeln!(stdout, "{}", flusher.finish().unwrap_or_default())?;
+        // Notices go to stderr so the piped answer stays clean
+        if let Some(notice) = truncation_notice(stream.finish_reason().as_deref()) {
+            eprintln!("{}", notice);
+        }
+    } else {
+        let response = client.send_message(prompt).await?;
+        writeln!(stdout, "{}", response)?;
+        if let Some(notice) = truncation_notice(client.last_finish_reason().as_deref()) {
+            eprintln!("{}", notice);
+        }
+    }
+
+    Ok(())
+}
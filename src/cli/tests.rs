@@ -0,0 +1,427 @@
+#[cfg(test)]
+mod tests {
+    use crate::cli::conversations::{date_bucket, DateBucket};
+    use chrono::{Duration, Local, TimeZone, Utc};
+    
+    #[test]
+    fn test_date_bucket() {
+        // Fixed local noon keeps the offsets away from day boundaries
+        let now = Local.with_ymd_and_hms(2024, 3, 15, 12, 0, 0).unwrap();
+        let at = |days: i64| (now - Duration::days(days)).with_timezone(&Utc);
+        
+        assert_eq!(date_bucket(at(0), now), DateBucket::Today);
+        assert_eq!(date_bucket(at(1), now), DateBucket::Yesterday);
+        assert_eq!(date_bucket(at(3), now), DateBucket::ThisWeek);
+        assert_eq!(date_bucket(at(6), now), DateBucket::ThisWeek);
+        assert_eq!(date_bucket(at(7), now), DateBucket::Older);
+        assert_eq!(date_bucket(at(30), now), DateBucket::Older);
+    }
+    
+    #[test]
+    fn test_readline_history_is_capped() {
+        use crate::cli::interactive::readline_config;
+        use rustyline::history::{FileHistory, History};
+        use std::fs;
+        
+        let path = std::env::temp_dir().join(format!("kona_history_test_{}", uuid::Uuid::new_v4()));
+        let entries: Vec<String> = (1..=10).map(|i| format!("entry {}", i)).collect();
+        fs::write(&path, entries.join("\n") + "\n").unwrap();
+        
+        // Loading an oversized file keeps only the newest entries
+        let mut history = FileHistory::with_config(readline_config(3).unwrap());
+        history.load(&path).unwrap();
+        assert_eq!(history.len(), 3);
+        
+        // Saving after a new entry rewrites the file with only the capped history
+        history.add("entry 11").unwrap();
+        history.save(&path).unwrap();
+        let mut reloaded = FileHistory::with_config(readline_config(100).unwrap());
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.len(), 3);
+        assert_eq!(reloaded.iter().last().map(String::as_str), Some("entry 11"));
+        
+        fs::remove_file(&path).ok();
+    }
+    
+    #[test]
+    fn test_markdown_inline_spans() {
+        use crate::cli::markdown::inline_spans;
+        use ratatui::style::Modifier;
+        
+        let spans = inline_spans("plain **bold** and `code`");
+        let texts: Vec<&str> = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(texts, vec!["plain ", "bold", " and ", "code"]);
+        assert!(spans[1].style.add_modifier.contains(Modifier::BOLD));
+        assert!(!spans[2].style.add_modifier.contains(Modifier::BOLD));
+    }
+    
+    #[test]
+    fn test_markdown_code_blocks_and_lists() {
+        use crate::cli::markdown::markdown_to_lines;
+        
+        let lines = markdown_to_lines("Intro\n```rust\nfn main() {}\n```\n- item\n  - nested");
+        let rendered: Vec<String> = lines
+            .iter()
+            .map(|line| line.spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+        
+        // Fences are dropped, with the language shown as a label
+        assert_eq!(rendered[0], "Intro");
+        assert_eq!(rendered[1], " rust ");
+        assert_eq!(rendered[2], " fn main() {} ");
+        assert_eq!(rendered[3], "  • item");
+        assert_eq!(rendered[4], "    • nested");
+    }
+    
+    #[test]
+    fn test_multiline_input() {
+        use crate::cli::interactive::{input_is_complete, join_continuation_lines};
+        
+        assert!(input_is_complete("hello"));
+        assert!(!input_is_complete("first line \\"));
+        assert!(!input_is_complete("look at this:\n```rust\nfn main() {}"));
+        assert!(input_is_complete("look at this:\n```rust\nfn main() {}\n```"));
+        
+        assert_eq!(join_continuation_lines("one\\\ntwo"), "one\ntwo");
+        
+        // Backslashes inside a code fence are part of the code
+        let code = "fix this:\\\n```sh\ncargo build \\\n  --release\n```\nthanks";
+        assert_eq!(join_continuation_lines(code), "fix this:\n```sh\ncargo build \\\n  --release\n```\nthanks");
+    }
+    
+    #[test]
+    fn test_input_window_scrolls_with_cursor() {
+        use crate::cli::tui::input_window;
+        
+        // Short text fits without scrolling
+        assert_eq!(input_window("hello", 5, 0, 10), (0, "hello".to_string(), 5));
+        
+        // Cursor at the end of a long line keeps the tail visible
+        let (offset, visible, cursor) = input_window("abcdefghijkl", 12, 0, 5);
+        assert_eq!(offset, 8);
+        assert_eq!(visible, "ijkl");
+        assert_eq!(cursor, 4);
+        
+        // Moving back to the start scrolls left again
+        let (offset, visible, cursor) = input_window("abcdefghijkl", 0, 8, 5);
+        assert_eq!((offset, visible.as_str(), cursor), (0, "abcde", 0));
+    }
+    
+    #[test]
+    fn test_input_window_wide_characters() {
+        use crate::cli::tui::input_window;
+        
+        // Each CJK character takes two columns
+        let (_, visible, cursor) = input_window("日本語", 2, 0, 10);
+        assert_eq!(visible, "日本語");
+        assert_eq!(cursor, 4);
+    }
+    
+    #[test]
+    fn test_search_snippet() {
+        use crate::cli::conversations::{match_snippet, search_pattern};
+        
+        let pattern = search_pattern("borrow CHECKER", false).unwrap();
+        let (before, matched, after) = match_snippet("How does the\nborrow checker work?", &pattern).unwrap();
+        assert_eq!(before, "How does the ");
+        assert_eq!(matched, "borrow checker");
+        assert_eq!(after, " work?");
+        
+        // Plain queries are escaped, regex queries are not
+        assert!(match_snippet("a+b", &search_pattern("a+b", false).unwrap()).is_some());
+        assert!(match_snippet("aab", &search_pattern("a+b", true).unwrap()).is_some());
+        assert!(search_pattern("(", true).is_err());
+        
+        let long = format!("{}needle{}", "x".repeat(100), "y".repeat(100));
+        let (before, _, after) = match_snippet(&long, &search_pattern("needle", false).unwrap()).unwrap();
+        assert!(before.starts_with("...") && after.ends_with("..."));
+    }
+    
+    #[test]
+    fn test_piped_prompt() {
+        use crate::cli::pipe::piped_prompt;
+        
+        assert_eq!(piped_prompt(None, "explain this\n"), "explain this");
+        assert_eq!(
+            piped_prompt(Some("What failed?"), "error: boom\n"),
+            "What failed?\n\n```\nerror: boom\n```"
+        );
+        assert_eq!(piped_prompt(Some("  "), "just stdin"), "just stdin");
+    }
+    
+    #[test]
+    fn test_wrap_line() {
+        use crate::cli::tui::wrap_line;
+        use ratatui::style::{Modifier, Style};
+        use ratatui::text::{Line, Span};
+        
+        let text_of = |line: &Line| line.spans.iter().map(|s| s.content.as_ref()).collect::<String>();
+        
+        // Short lines are untouched
+        assert_eq!(wrap_line(Line::from("short"), 20).len(), 1);
+        
+        let wrapped = wrap_line(Line::from("the quick brown fox jumps"), 10);
+        let texts: Vec<String> = wrapped.iter().map(text_of).collect();
+        assert_eq!(texts, vec!["the quick ", "brown fox ", "jumps"]);
+        
+        // Indented code keeps its indentation on continuation lines
+        let wrapped = wrap_line(Line::from("    let value = compute(a, b);"), 16);
+        assert!(wrapped.len() > 1);
+        assert!(wrapped.iter().all(|l| text_of(l).starts_with("    ")));
+        
+        // Long words are broken and styles survive the split
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let wrapped = wrap_line(Line::from(Span::styled("abcdefghij", bold)), 4);
+        assert_eq!(wrapped.len(), 3);
+        assert!(wrapped.iter().all(|l| l.spans.iter().all(|s| s.style == bold)));
+    }
+    
+    #[test]
+    fn test_parse_conversations_for_import() {
+        use crate::cli::conversations::parse_conversations;
+        use crate::history::storage::Conversation;
+        
+        let mut conversation = Conversation::new("Imported".to_string());
+        conversation.add_user_message("Hi".to_string());
+        let single = serde_json::to_string(&conversation).unwrap();
+        
+        let (parsed, skipped) = parse_conversations(&single).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert!(skipped.is_empty());
+        
+        // Arrays are imported entry by entry, skipping the ones that don't match the schema
+        let array = format!(r#"[{}, {{"title": "missing fields"}}]"#, single);
+        let (parsed, skipped) = parse_conversations(&array).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        
+        assert!(parse_conversations("not json").is_err());
+    }
+    
+    #[test]
+    fn test_code_highlighting() {
+        use crate::cli::highlight::{highlight_code_blocks, CodeHighlighter};
+        
+        let text = "Try this:\n```rust\nfn main() {}\n```\nDone.";
+        
+        // Disabled output is the raw text
+        assert_eq!(highlight_code_blocks(text, false), text);
+        
+        // Only the code line gains colours
+        let highlighted = highlight_code_blocks(text, true);
+        assert!(highlighted.starts_with("Try this:\n```rust\n\x1b["));
+        assert!(highlighted.ends_with("```\nDone."));
+        
+        // Unknown languages are left plain
+        let unknown = "```klingon\nqapla'\n```\n";
+        assert_eq!(highlight_code_blocks(unknown, true), unknown);
+        
+        // A fence split across chunks is still recognised, and prose isn't held back
+        let mut highlighter = CodeHighlighter::new(true);
+        assert_eq!(highlighter.push("Hello "), "Hello ");
+        assert_eq!(highlighter.push("world\n`"), "world\n");
+        assert_eq!(highlighter.push("``py\nx = 1"), "```py\n");
+        assert!(highlighter.finish().contains("\x1b["));
+    }
+    
+    #[test]
+    fn test_parse_temperature() {
+        use crate::cli::cli::parse_temperature;
+        
+        assert_eq!(parse_temperature("0"), Ok(0.0));
+        assert_eq!(parse_temperature("1.5"), Ok(1.5));
+        assert_eq!(parse_temperature("2.0"), Ok(2.0));
+        assert!(parse_temperature("2.1").is_err());
+        assert!(parse_temperature("-0.5").is_err());
+        assert!(parse_temperature("NaN").is_err());
+        assert!(parse_temperature("warm").is_err());
+    }
+    
+    #[test]
+    fn test_image_attachments() {
+        use crate::api::{ContentBlock, MessageContent};
+        use crate::cli::images::{image_media_type, with_images};
+        
+        assert_eq!(image_media_type(b"\x89PNG\r\n\x1a\n\0\0"), Some("image/png"));
+        assert_eq!(image_media_type(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(image_media_type(b"GIF89a..."), Some("image/gif"));
+        assert_eq!(image_media_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        
+        // The extension doesn't matter, only the content
+        assert_eq!(image_media_type(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(image_media_type(b"RIFF\0\0\0\0WAVEfmt "), None);
+        
+        assert_eq!(with_images("Hi".to_string(), &[]), MessageContent::Text("Hi".to_string()));
+        
+        let image = ContentBlock::ImageUrl { image_url: crate::api::ImageUrl { url: "data:image/png;base64,AA==".to_string() } };
+        match with_images("What is this?".to_string(), std::slice::from_ref(&image)) {
+            MessageContent::Blocks(blocks) => assert_eq!(blocks[1], image),
+            other => panic!("expected blocks, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_editor_command() {
+        use crate::cli::editor::editor_command;
+        
+        let fallback = if cfg!(windows) { "notepad" } else { "vi" };
+        assert_eq!(editor_command(None), (fallback.to_string(), vec![]));
+        assert_eq!(editor_command(Some("  ")), (fallback.to_string(), vec![]));
+        assert_eq!(editor_command(Some("nano")), ("nano".to_string(), vec![]));
+        assert_eq!(
+            editor_command(Some("code --wait")),
+            ("code".to_string(), vec!["--wait".to_string()])
+        );
+    }
+    
+    #[test]
+    fn test_doctor_storage_check() {
+        use crate::cli::doctor::check_writable;
+        
+        // Missing directories are created, and the probe file is cleaned up
+        let dir = std::env::temp_dir().join(format!("kona_doctor_test_{}", uuid::Uuid::new_v4())).join("conversations");
+        assert!(check_writable(&dir).is_ok());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        
+        // A path below a regular file can never be created
+        let file = dir.join("not-a-dir");
+        std::fs::write(&file, "x").unwrap();
+        assert!(check_writable(&file.join("storage")).is_err());
+        
+        std::fs::remove_dir_all(dir.parent().unwrap()).ok();
+    }
+    
+    #[test]
+    fn test_format_file_context() {
+        use crate::cli::files::format_file_context;
+        use std::path::PathBuf;
+        
+        let files = vec![
+            (PathBuf::from("main.rs"), "fn main() {}\n".to_string()),
+            (PathBuf::from("notes.txt"), "héllo world".to_string()),
+            (PathBuf::from("extra.rs"), "unused".to_string()),
+        ];
+        
+        let (context, warnings) = format_file_context(&files, 1000);
+        assert!(context.starts_with("File: main.rs\n```rs\nfn main() {}\n```"));
+        assert!(context.contains("File: notes.txt\n```txt\nhéllo world\n```"));
+        assert!(warnings.is_empty());
+        
+        // The cap truncates on a char boundary and skips files past it
+        let (context, warnings) = format_file_context(&files, 14);
+        assert!(context.contains("```txt\nh\n```"));
+        assert!(!context.contains("extra.rs"));
+        assert_eq!(warnings.len(), 2);
+    }
+    
+    #[test]
+    fn test_format_account() {
+        use crate::api::{Credits, KeyInfo};
+        use crate::cli::credits::format_account;
+        
+        let key = KeyInfo {
+            label: Some("sk-or-v1-abc...xyz".to_string()),
+            usage: 1.5,
+            limit: Some(10.0),
+            limit_remaining: Some(8.5),
+            ..KeyInfo::default()
+        };
+        let credits = Credits { total_credits: 20.0, total_usage: 4.25 };
+        
+        let lines = format_account(&key, Some(&credits));
+        assert_eq!(lines[0], "Key: sk-or-v1-abc...xyz");
+        assert!(lines.contains(&"Key limit: $10.00 ($8.50 remaining)".to_string()));
+        assert!(lines.contains(&"Credits: $20.00 purchased, $4.25 used, $15.75 remaining".to_string()));
+        
+        // Without a balance or limit the key details still print
+        let lines = format_account(&KeyInfo::default(), None);
+        assert!(lines.contains(&"Key limit: none".to_string()));
+        assert!(!lines.iter().any(|line| line.starts_with("Credits")));
+    }
+    
+    #[test]
+    fn test_fits_terminal() {
+        use crate::cli::tui::fits_terminal;
+        use ratatui::layout::Rect;
+        
+        assert!(fits_terminal(Rect::new(0, 0, 80, 24)));
+        assert!(fits_terminal(Rect::new(0, 0, 20, 10)));
+        assert!(!fits_terminal(Rect::new(0, 0, 19, 24)));
+        assert!(!fits_terminal(Rect::new(0, 0, 80, 9)));
+        assert!(!fits_terminal(Rect::new(0, 0, 0, 0)));
+    }
+    
+    #[test]
+    fn test_normalize_paste() {
+        use crate::cli::tui::normalize_paste;
+        
+        assert_eq!(normalize_paste("one\r\ntwo\rthree\nfour"), "one\ntwo\nthree\nfour");
+        assert_eq!(normalize_paste("plain text"), "plain text");
+    }
+    
+    #[test]
+    fn test_input_history() {
+        use crate::cli::tui::InputHistory;
+        
+        let mut history = InputHistory::new(vec!["first".to_string(), "second".to_string()]);
+        assert_eq!(history.next(), None);
+        
+        // Browsing back keeps the draft and stops at the oldest entry
+        assert_eq!(history.previous("draft"), Some("second"));
+        assert_eq!(history.previous("second"), Some("first"));
+        assert_eq!(history.previous("first"), None);
+        assert_eq!(history.next(), Some("second"));
+        assert_eq!(history.next(), Some("draft"));
+        assert_eq!(history.next(), None);
+        
+        // Sent messages are appended once and browsing restarts from the newest
+        history.push("third");
+        history.push("third");
+        history.push("  ");
+        assert_eq!(history.previous(""), Some("third"));
+        assert_eq!(history.previous("third"), Some("second"));
+        
+        let mut empty = InputHistory::new(Vec::new());
+        assert_eq!(empty.previous("draft"), None);
+    }
+    
+    #[test]
+    fn test_collapse_lines() {
+        use crate::cli::tui::collapse_lines;
+        use ratatui::text::Line;
+        
+        let text_of = |line: &Line| line.spans.iter().map(|s| s.content.as_ref()).collect::<String>();
+        let reply: Vec<Line<'static>> = (1..=10).map(|n| Line::from(format!("line {}", n))).collect();
+        
+        // Short replies and a zero limit are left alone
+        assert_eq!(collapse_lines(reply.clone(), 10, true).len(), 10);
+        assert_eq!(collapse_lines(reply.clone(), 0, true).len(), 10);
+        
+        let collapsed = collapse_lines(reply.clone(), 4, true);
+        assert_eq!(collapsed.len(), 5);
+        assert_eq!(text_of(&collapsed[3]), "line 4");
+        assert_eq!(text_of(&collapsed[4]), "[… +6 more lines, press Ctrl+E to expand]");
+        
+        // Expanded replies keep every line plus a hint to collapse again
+        let expanded = collapse_lines(reply, 4, false);
+        assert_eq!(expanded.len(), 11);
+        assert_eq!(text_of(&expanded[10]), "[press Ctrl+E to collapse]");
+    }
+    
+    #[test]
+    fn test_global_overrides_after_subcommand() {
+        use crate::cli::cli::Cli;
+        use clap::Parser;
+        
+        let cli = Cli::try_parse_from(["kona", "ask", "--model", "openai/gpt-4o", "What is Rust?"]).unwrap();
+        assert_eq!(cli.model.as_deref(), Some("openai/gpt-4o"));
+        
+        let cli = Cli::try_parse_from(["kona", "ask", "--system", "Answer tersely.", "What is Rust?"]).unwrap();
+        assert_eq!(cli.system.as_deref(), Some("Answer tersely."));
+        assert!(Cli::try_parse_from(["kona", "ask", "--no-system", "What is Rust?"]).unwrap().no_system);
+        assert!(Cli::try_parse_from(["kona", "ask", "--system", "x", "--no-system", "q"]).is_err());
+        
+        assert!(Cli::try_parse_from(["kona", "ask", "--continue", "What next?"]).unwrap().continue_last);
+        assert!(Cli::try_parse_from(["kona", "--continue", "--resume", "abc"]).is_err());
+    }
+}
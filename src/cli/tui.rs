@@ -1,30 +1,140 @@
 // Terminal UI Implementation with ratatui
 
-use crate::api::OpenRouterClient;
+use crate::api::{truncation_notice, OpenRouterClient, ResponseStream, Usage};
+use crate::cli::interactive::{history_file_path, model_warning, readline_config};
+use crate::cli::markdown::markdown_to_lines;
+use crate::history::storage::{mark_incomplete, Conversation, ConversationStorage};
 use crate::utils::error::Result;
+use crate::utils::flush::FlushBuffer;
 use crate::utils::mask_api_key;
+use crate::utils::tokens::oversized_input_warning;
 
+use chrono::{DateTime, Local, Utc};
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event, KeyCode, KeyEvent,
+        KeyModifiers, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
+use rustyline::history::{FileHistory, History, SearchDirection};
 use ratatui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
     Frame, Terminal,
 };
 use std::io::{self, Stdout};
+use std::path::PathBuf;
 use std::time::Duration;
+use tracing::debug;
+use unicode_width::UnicodeWidthChar;
+
+// Lines moved per mouse wheel notch
+const MOUSE_SCROLL_LINES: usize = 3;
+
+// How often the UI loop redraws while a streamed response is arriving
+const STREAM_REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+// Smallest terminal the layout is drawn in; below this a notice is shown instead
+const MIN_TERMINAL_WIDTH: u16 = 20;
+const MIN_TERMINAL_HEIGHT: u16 = 10;
+
+// Dim local time shown after a turn's label
+fn timestamp_span(at: &DateTime<Utc>) -> Span<'static> {
+    Span::styled(at.with_timezone(&Local).format("%H:%M").to_string(), Style::default().fg(Color::DarkGray))
+}
+
+// Keep the first `limit` lines of a long reply when collapsed, with a footer saying how to toggle it
+pub(crate) fn collapse_lines(mut lines: Vec<Line<'static>>, limit: usize, collapsed: bool) -> Vec<Line<'static>> {
+    if limit == 0 || lines.len() <= limit {
+        return lines;
+    }
+
+    let footer_style = Style::default().fg(Color::DarkGray);
+    if collapsed {
+        let hidden = lines.len() - limit;
+        lines.truncate(limit);
+        lines.push(Line::from(Span::styled(
+            format!("[… +{} more lines, press Ctrl+E to expand]", hidden),
+            footer_style,
+        )));
+    } else {
+        lines.push(Line::from(Span::styled("[press Ctrl+E to collapse]", footer_style)));
+    }
+    lines
+}
+
+// Whether the conversation and input boxes (plus margins) fit in `area`
+pub(crate) fn fits_terminal(area: Rect) -> bool {
+    area.width >= MIN_TERMINAL_WIDTH && area.height >= MIN_TERMINAL_HEIGHT
+}
+
+/// Previously sent messages, browsed with Up/Down like a shell history
+pub(crate) struct InputHistory {
+    entries: Vec<String>,
+    // Entry currently shown; `None` while editing the draft
+    position: Option<usize>,
+    // Unsent input, restored when browsing returns past the newest entry
+    draft: String,
+}
+
+impl InputHistory {
+    pub(crate) fn new(entries: Vec<String>) -> Self {
+        Self {
+            entries,
+            position: None,
+            draft: String::new(),
+        }
+    }
+
+    // Record a sent message and go back to an empty draft
+    pub(crate) fn push(&mut self, entry: &str) {
+        if !entry.trim().is_empty() && self.entries.last().map(String::as_str) != Some(entry) {
+            self.entries.push(entry.to_string());
+        }
+        self.position = None;
+        self.draft.clear();
+    }
+
+    // Step to an older entry; `current` is kept as the draft when browsing starts
+    pub(crate) fn previous(&mut self, current: &str) -> Option<&str> {
+        let index = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(index) => index - 1,
+        };
+        self.position = Some(index);
+        Some(&self.entries[index])
+    }
+
+    // Step to a newer entry, ending back at the draft
+    pub(crate) fn next(&mut self) -> Option<&str> {
+        let index = self.position?;
+        if index + 1 < self.entries.len() {
+            self.position = Some(index + 1);
+            Some(&self.entries[index + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+}
 
 // Message type for our UI
 enum UiMessage {
-    User(String),
-    Assistant(String),
+    // Text and when it was sent
+    User(String, DateTime<Utc>),
+    // Long replies start collapsed; Ctrl+E toggles the one in view. `at` is when the reply started arriving
+    Assistant { content: String, collapsed: bool, at: DateTime<Utc> },
     Status(String),
     Command(String, String), // Command and its result
 }
@@ -32,10 +142,56 @@ enum UiMessage {
 // Custom implementation of a text input widget
 struct TextInput {
     text: String,
+    // Cursor position in characters, not bytes
     cursor_position: usize,
+    // First visible display column when the text is wider than the box
     scroll_offset: usize,
 }
 
+/// Computes the visible part of a single-line input so the cursor stays inside a box `width` columns wide
+///
+/// Returns the updated scroll offset, the visible text and the cursor column relative to the box.
+pub(crate) fn input_window(text: &str, cursor: usize, scroll_offset: usize, width: usize) -> (usize, String, usize) {
+    if width == 0 {
+        return (scroll_offset, String::new(), 0);
+    }
+
+    let char_width = |c: char| c.width().unwrap_or(0);
+    let cursor_col: usize = text.chars().take(cursor).map(char_width).sum();
+
+    // Scroll just far enough to keep the cursor (and the cell it sits on) visible
+    let mut offset = scroll_offset.min(cursor_col);
+    if cursor_col >= offset + width {
+        offset = cursor_col + 1 - width;
+    }
+
+    let mut visible = String::new();
+    let mut visible_start = None;
+    let mut col = 0;
+    for c in text.chars() {
+        let start = col;
+        col += char_width(c);
+        // Wide characters straddling the left edge are skipped entirely
+        if start < offset {
+            continue;
+        }
+        let first = *visible_start.get_or_insert(start);
+        if col - first > width {
+            break;
+        }
+        visible.push(c);
+    }
+
+    let visible_start = visible_start.unwrap_or(cursor_col);
+    let cursor_in_box = (cursor_col - visible_start).min(width - 1);
+    (offset, visible, cursor_in_box)
+}
+
+// Pasted text with Windows and old Mac line endings turned into `\n`
+pub(crate) fn normalize_paste(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
 impl TextInput {
     fn new() -> Self {
         Self {
@@ -45,38 +201,54 @@ impl TextInput {
         }
     }
 
+    // Byte index of the character at `position`
+    fn byte_index(&self, position: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(position)
+            .map(|(index, _)| index)
+            .unwrap_or(self.text.len())
+    }
+
+    fn char_count(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    // Insert a whole pasted block at the cursor in one edit
+    fn insert_str(&mut self, text: &str) {
+        let text = normalize_paste(text);
+        let index = self.byte_index(self.cursor_position);
+        self.text.insert_str(index, &text);
+        self.cursor_position += text.chars().count();
+    }
+
     fn handle_key_event(&mut self, key: KeyEvent) {
         match key.code {
             KeyCode::Char(c) => {
-                self.text.insert(self.cursor_position, c);
+                let index = self.byte_index(self.cursor_position);
+                self.text.insert(index, c);
                 self.cursor_position += 1;
             }
-            KeyCode::Backspace => {
-                if self.cursor_position > 0 {
-                    self.cursor_position -= 1;
-                    self.text.remove(self.cursor_position);
-                }
+            KeyCode::Backspace if self.cursor_position > 0 => {
+                self.cursor_position -= 1;
+                let index = self.byte_index(self.cursor_position);
+                self.text.remove(index);
             }
-            KeyCode::Delete => {
-                if self.cursor_position < self.text.len() {
-                    self.text.remove(self.cursor_position);
-                }
+            KeyCode::Delete if self.cursor_position < self.char_count() => {
+                let index = self.byte_index(self.cursor_position);
+                self.text.remove(index);
             }
-            KeyCode::Left => {
-                if self.cursor_position > 0 {
-                    self.cursor_position -= 1;
-                }
+            KeyCode::Left if self.cursor_position > 0 => {
+                self.cursor_position -= 1;
             }
-            KeyCode::Right => {
-                if self.cursor_position < self.text.len() {
-                    self.cursor_position += 1;
-                }
+            KeyCode::Right if self.cursor_position < self.char_count() => {
+                self.cursor_position += 1;
             }
             KeyCode::Home => {
                 self.cursor_position = 0;
             }
             KeyCode::End => {
-                self.cursor_position = self.text.len();
+                self.cursor_position = self.char_count();
             }
             _ => {}
         }
@@ -86,21 +258,46 @@ impl TextInput {
         &self.text
     }
 
+    // Replace the contents, with the cursor at the end
+    fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor_position = self.char_count();
+        self.scroll_offset = 0;
+    }
+
+    // Up/Down only browse the history from the first/last line of pasted multi-line input
+    fn cursor_on_first_line(&self) -> bool {
+        !self.text[..self.byte_index(self.cursor_position)].contains('\n')
+    }
+
+    fn cursor_on_last_line(&self) -> bool {
+        !self.text[self.byte_index(self.cursor_position)..].contains('\n')
+    }
+
     fn clear(&mut self) {
         self.text.clear();
         self.cursor_position = 0;
         self.scroll_offset = 0;
     }
 
-    fn render(&self, frame: &mut Frame, area: Rect) {
+    fn render(&mut self, frame: &mut Frame, area: Rect) {
         let input_block = Block::default()
             .borders(Borders::ALL)
             .title("Input (Shift+Enter to send, Esc to exit)");
 
         let inner_area = input_block.inner(area);
 
+        // Only the part of the line around the cursor fits in the box
+        let (scroll_offset, visible, cursor_column) = input_window(
+            &self.text,
+            self.cursor_position,
+            self.scroll_offset,
+            inner_area.width as usize,
+        );
+        self.scroll_offset = scroll_offset;
+
         let mut text = Text::default();
-        let content = Span::raw(&self.text);
+        let content = Span::raw(visible);
         text.lines.push(Line::from(content));
 
         let input = Paragraph::new(text)
@@ -112,22 +309,170 @@ impl TextInput {
         // Show cursor
         if inner_area.width > 0 && inner_area.height > 0 {
             frame.set_cursor_position(
-                (inner_area.x + self.cursor_position as u16, inner_area.y)
+                (inner_area.x + cursor_column as u16, inner_area.y)
             );
         }
     }
 }
 
+/// Wraps a styled line to `width` display columns at word boundaries, keeping span styles
+///
+/// Continuation lines repeat the line's leading whitespace so wrapped code stays aligned.
+/// Words longer than the width are broken across lines.
+pub(crate) fn wrap_line(line: Line<'_>, width: usize) -> Vec<Line<'static>> {
+    let line_style = line.style;
+    let cells: Vec<(char, Style)> = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |c| (c, span.style)))
+        .collect();
+
+    let char_width = |c: char| c.width().unwrap_or(0);
+    let total: usize = cells.iter().map(|(c, _)| char_width(*c)).sum();
+    if width == 0 || total <= width {
+        return vec![cells_to_line(&cells, line_style)];
+    }
+
+    // Keep at least half the width for text when indenting continuation lines
+    let indent: Vec<(char, Style)> = cells
+        .iter()
+        .take_while(|(c, _)| *c == ' ' || *c == '\t')
+        .map(|(_, style)| (' ', *style))
+        .take(width / 2)
+        .collect();
+
+    let mut wrapped = Vec::new();
+    let mut current: Vec<(char, Style)> = Vec::new();
+    let mut current_width = 0;
+    let mut at_line_start = false;
+
+    // Alternate runs of whitespace and words
+    let mut rest = &cells[..];
+    while !rest.is_empty() {
+        let is_space = rest[0].0.is_whitespace();
+        let len = rest.iter().take_while(|(c, _)| c.is_whitespace() == is_space).count();
+        let (token, remaining) = rest.split_at(len);
+        rest = remaining;
+
+        if is_space {
+            // Whitespace at a wrap point is dropped
+            if at_line_start {
+                continue;
+            }
+            for &(c, style) in token {
+                if current_width + char_width(c) > width {
+                    break;
+                }
+                current.push((c, style));
+                current_width += char_width(c);
+            }
+            continue;
+        }
+
+        let token_width: usize = token.iter().map(|(c, _)| char_width(*c)).sum();
+        if current_width + token_width > width && current_width > indent.len() {
+            wrapped.push(cells_to_line(&current, line_style));
+            current = indent.clone();
+            current_width = indent.len();
+        }
+
+        for &(c, style) in token {
+            if current_width + char_width(c) > width && current_width > indent.len() {
+                wrapped.push(cells_to_line(&current, line_style));
+                current = indent.clone();
+                current_width = indent.len();
+            }
+            current.push((c, style));
+            current_width += char_width(c);
+        }
+        at_line_start = false;
+
+        // The next whitespace run may start a continuation line
+        if current_width >= width {
+            wrapped.push(cells_to_line(&current, line_style));
+            current = indent.clone();
+            current_width = indent.len();
+            at_line_start = true;
+        }
+    }
+
+    if current_width > indent.len() || wrapped.is_empty() {
+        wrapped.push(cells_to_line(&current, line_style));
+    }
+    wrapped
+}
+
+// Group consecutive cells with the same style back into spans
+fn cells_to_line(cells: &[(char, Style)], line_style: Style) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut text = String::new();
+    let mut style = None;
+
+    for &(c, cell_style) in cells {
+        if style.is_some() && style != Some(cell_style) {
+            spans.push(Span::styled(std::mem::take(&mut text), style.unwrap_or_default()));
+        }
+        style = Some(cell_style);
+        text.push(c);
+    }
+    if !text.is_empty() {
+        spans.push(Span::styled(text, style.unwrap_or_default()));
+    }
+
+    Line::from(spans).style(line_style)
+}
+
 pub struct Tui {
     client: OpenRouterClient,
     terminal: Terminal<CrosstermBackend<Stdout>>,
     input_area: TextInput,
     messages: Vec<UiMessage>,
     should_quit: bool,
+    // Tokens reported by non-streaming responses during this session
+    session_usage: Usage,
+    // Set once the terminal has been handed back, so cleanup only runs once
+    terminal_restored: bool,
+    // Style assistant Markdown; toggled off with /raw for debugging
+    render_markdown: bool,
+    // Show when each turn happened next to its label; toggled with /timestamps
+    show_timestamps: bool,
+    // Oversized message already warned about, which is sent if it's submitted again
+    oversized_confirmed: Option<String>,
+    // First visible line of the conversation pane; `None` follows the newest output
+    scroll_position: Option<usize>,
+    // Largest valid scroll position and visible height, as of the last draw
+    max_scroll: usize,
+    page_height: usize,
+    // Message index and first/last+1 pane row of each assistant reply, as of the last draw
+    assistant_rows: Vec<(usize, usize, usize)>,
+    // Id of the stored conversation, from `--resume` or the first save, so later saves update it
+    saved_conversation_id: Option<String>,
+    // Opened on first /copy and kept alive, since on X11 the contents vanish with the owner
+    clipboard: Option<arboard::Clipboard>,
+    // Streamed reply still arriving; the UI loop drains it so input stays live meanwhile
+    active_stream: Option<ActiveStream>,
+    // Sent messages for Up/Down, and the ~/.kona_history file they are also saved to
+    input_history: InputHistory,
+    history_file: Option<(FileHistory, PathBuf)>,
+}
+
+struct ActiveStream {
+    stream: ResponseStream,
+    text: String,
+    // Chunks not shown yet, released every `stream_flush_chars` characters
+    pending: FlushBuffer,
+    // Position of the reply in `messages`, once its first chunk has arrived
+    index: Option<usize>,
 }
 
 impl Tui {
-    pub fn new(client: OpenRouterClient) -> Result<Self> {
+    pub fn new(client: OpenRouterClient, resume: Option<String>) -> Result<Self> {
+        // Load a resumed conversation before touching the terminal, so a bad id is reported plainly
+        let resumed = match resume {
+            Some(id) => Some(ConversationStorage::new()?.load_conversation(&id)?),
+            None => None,
+        };
+
         // Try to detect if the terminal is compatible
         // Check if we're in a valid terminal by testing basic operations
         if !Self::is_valid_terminal_env() {
@@ -142,15 +487,13 @@ impl Tui {
         let mut stdout = io::stdout();
 
         // Use a more defensive approach with terminal operations
-        match execute!(stdout, EnterAlternateScreen, EnableMouseCapture) {
+        // Bracketed paste delivers a paste as one event instead of a key event per character
+        match execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste) {
             Ok(_) => {},
             Err(e) => {
                 // Make sure to clean up if we failed
                 let _ = disable_raw_mode();
-                return Err(crate::utils::error::KonaError::IoError(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Failed to set up terminal: {}", e),
-                )));
+                return Err(crate::utils::error::KonaError::IoError(io::Error::other(format!("Failed to set up terminal: {}", e))));
             }
         }
 
@@ -162,29 +505,42 @@ impl Tui {
                 // Clean up on failure
                 let _ = disable_raw_mode();
                 let mut stdout = io::stdout();
-                let _ = execute!(stdout, LeaveAlternateScreen, DisableMouseCapture);
+                let _ = execute!(stdout, LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste);
 
-                return Err(crate::utils::error::KonaError::IoError(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Failed to create terminal: {}", e),
-                )));
+                return Err(crate::utils::error::KonaError::IoError(io::Error::other(format!("Failed to create terminal: {}", e))));
             }
         };
 
         // Setup input area
         let input_area = TextInput::new();
+        let (input_history, history_file) = load_input_history(client.config.history_size);
+        let messages = resumed.as_ref().map(ui_messages).unwrap_or_default();
 
         Ok(Self {
             client,
             terminal,
             input_area,
-            messages: Vec::new(),
+            messages,
             should_quit: false,
+            session_usage: Usage::default(),
+            terminal_restored: false,
+            render_markdown: true,
+            show_timestamps: true,
+            oversized_confirmed: None,
+            scroll_position: None,
+            max_scroll: 0,
+            page_height: 0,
+            assistant_rows: Vec::new(),
+            saved_conversation_id: resumed.map(|conversation| conversation.id),
+            clipboard: None,
+            active_stream: None,
+            input_history,
+            history_file,
         })
     }
 
     // Helper method to check if we're in a valid terminal environment
-    fn is_valid_terminal_env() -> bool {
+    pub(crate) fn is_valid_terminal_env() -> bool {
         // Try to get terminal size - this is a good indicator of terminal compatibility
         if let Err(_) = crossterm::terminal::size() {
             return false;
@@ -222,14 +578,17 @@ impl Tui {
     // Main UI loop
     async fn run_ui_loop(&mut self) -> Result<()> {
         while !self.should_quit {
+            self.poll_stream();
+
             if let Err(e) = self.draw() {
                 // Try to restore terminal and bubble up the error
                 self.restore_terminal();
                 return Err(e);
             }
 
-            // Poll for events with error handling
-            match crossterm::event::poll(Duration::from_millis(100)) {
+            // Poll for events with error handling, waking sooner while a reply streams in
+            let timeout = if self.active_stream.is_some() { STREAM_REDRAW_INTERVAL } else { Duration::from_millis(100) };
+            match crossterm::event::poll(timeout) {
                 Ok(true) => {
                     match crossterm::event::read() {
                         Ok(Event::Key(key)) => {
@@ -238,11 +597,25 @@ impl Tui {
                                 return Err(e);
                             }
                         },
+                        Ok(Event::Mouse(mouse)) => match mouse.kind {
+                            MouseEventKind::ScrollUp => self.scroll_up(MOUSE_SCROLL_LINES),
+                            MouseEventKind::ScrollDown => self.scroll_down(MOUSE_SCROLL_LINES),
+                            _ => {}
+                        },
+                        // Inserted in one go, so the whole paste costs a single redraw
+                        Ok(Event::Paste(text)) => self.input_area.insert_str(&text),
+                        Ok(Event::Resize(width, height)) => {
+                            // Repaint everything so nothing from the old size is left behind
+                            debug!("Terminal resized to {}x{}", width, height);
+                            if let Err(e) = self.terminal.clear() {
+                                debug!("Failed to clear terminal after resize: {}", e);
+                            }
+                        },
                         Ok(_) => {}, // Other events are ignored
                         Err(e) => {
                             self.restore_terminal();
                             return Err(crate::utils::error::KonaError::IoError(
-                                io::Error::new(io::ErrorKind::Other, format!("Event read error: {}", e))
+                                io::Error::other(format!("Event read error: {}", e))
                             ));
                         }
                     }
@@ -251,7 +624,7 @@ impl Tui {
                 Err(e) => {
                     self.restore_terminal();
                     return Err(crate::utils::error::KonaError::IoError(
-                        io::Error::new(io::ErrorKind::Other, format!("Event poll error: {}", e))
+                        io::Error::other(format!("Event poll error: {}", e))
                     ));
                 }
             }
@@ -262,11 +635,17 @@ impl Tui {
 
     // Helper method to safely restore terminal state
     fn restore_terminal(&mut self) {
+        if self.terminal_restored {
+            return;
+        }
+        self.terminal_restored = true;
+
         let _ = disable_raw_mode();
         let _ = execute!(
             self.terminal.backend_mut(),
             LeaveAlternateScreen,
-            DisableMouseCapture
+            DisableMouseCapture,
+            DisableBracketedPaste
         );
         let _ = self.terminal.show_cursor();
     }
@@ -274,11 +653,33 @@ impl Tui {
     fn draw(&mut self) -> Result<()> {
         // Create a copy of references to avoid borrowing issues
         let messages = &self.messages;
-        let input_area = &self.input_area;
+        let input_area = &mut self.input_area;
+        let assistant_label = format!("{}: ", self.client.config.assistant_label());
+        let render_markdown = self.render_markdown;
+        let show_timestamps = self.show_timestamps;
+        let scroll_position = self.scroll_position;
+        let collapse_limit = self.client.config.tui_collapse_lines;
+        let mut max_scroll = 0;
+        let mut page_height = 0;
+        let mut assistant_rows = Vec::new();
+        let mut too_small = false;
 
         self.terminal.draw(|frame| {
             let area = frame.area();
 
+            // The layout can't fit, so wait for the terminal to grow rather than drawing it garbled
+            if !fits_terminal(area) {
+                too_small = true;
+                let notice = Paragraph::new(format!(
+                    "Terminal too small ({}x{}). Resize to at least {}x{}.",
+                    area.width, area.height, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT
+                ))
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true });
+                frame.render_widget(notice, area);
+                return;
+            }
+
             // Create the layout
             let main_chunks = Layout::default()
                 .direction(Direction::Vertical)
@@ -289,13 +690,14 @@ impl Tui {
             // Messages area
             let messages_area = main_chunks[0];
 
-            // Draw messages
-            let mut items: Vec<ListItem> = Vec::new();
+            // Draw messages, noting which source lines belong to each assistant reply
+            let mut lines: Vec<Line> = Vec::new();
+            let mut assistant_lines = Vec::new();
 
-            for message in messages {
+            for (index, message) in messages.iter().enumerate() {
                 match message {
-                    UiMessage::User(content) => {
-                        let header = Line::from(vec![
+                    UiMessage::User(content, at) => {
+                        let mut header = Line::from(vec![
                             Span::styled(
                                 "You: ",
                                 Style::default()
@@ -303,30 +705,41 @@ impl Tui {
                                     .add_modifier(Modifier::BOLD),
                             ),
                         ]);
-                        items.push(ListItem::new(vec![header]));
+                        if show_timestamps {
+                            header.push_span(timestamp_span(at));
+                        }
+                        lines.push(header);
 
                         // Split content into lines for better display
                         for line in content.lines() {
-                            items.push(ListItem::new(line));
+                            lines.push(Line::from(line));
                         }
-                        items.push(ListItem::new("")); // Add spacing
+                        lines.push(Line::from("")); // Add spacing
                     }
-                    UiMessage::Assistant(content) => {
-                        let header = Line::from(vec![
+                    UiMessage::Assistant { content, collapsed, at } => {
+                        let start = lines.len();
+                        let mut header = Line::from(vec![
                             Span::styled(
-                                "Claude: ",
+                                assistant_label.clone(),
                                 Style::default()
                                     .fg(Color::Magenta)
                                     .add_modifier(Modifier::BOLD),
                             ),
                         ]);
-                        items.push(ListItem::new(vec![header]));
+                        if show_timestamps {
+                            header.push_span(timestamp_span(at));
+                        }
+                        lines.push(header);
 
                         // Split content into lines for better display
-                        for line in content.lines() {
-                            items.push(ListItem::new(line));
-                        }
-                        items.push(ListItem::new("")); // Add spacing
+                        let body: Vec<Line<'static>> = if render_markdown {
+                            markdown_to_lines(content)
+                        } else {
+                            content.lines().map(|line| Line::from(line.to_string())).collect()
+                        };
+                        lines.extend(collapse_lines(body, collapse_limit, *collapsed));
+                        lines.push(Line::from("")); // Add spacing
+                        assistant_lines.push((index, start, lines.len()));
                     }
                     UiMessage::Status(content) => {
                         let text = Line::from(vec![
@@ -335,7 +748,7 @@ impl Tui {
                                 Style::default().fg(Color::Yellow),
                             ),
                         ]);
-                        items.push(ListItem::new(vec![text]));
+                        lines.push(text);
                     }
                     UiMessage::Command(cmd, result) => {
                         let header = Line::from(vec![
@@ -344,19 +757,45 @@ impl Tui {
                                 Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
                             ),
                         ]);
-                        items.push(ListItem::new(vec![header]));
+                        lines.push(header);
 
                         // Split result into lines
                         for line in result.lines() {
-                            items.push(ListItem::new(line));
+                            lines.push(Line::from(line));
                         }
-                        items.push(ListItem::new("")); // Add spacing
+                        lines.push(Line::from("")); // Add spacing
                     }
                 }
             }
 
-            let messages_list = List::new(items)
-                .block(Block::default().borders(Borders::ALL).title("Conversation"))
+            // Wrap to the pane width inside the borders; recomputed every frame, so resizes reflow
+            let wrap_width = messages_area.width.saturating_sub(2) as usize;
+            let mut items: Vec<ListItem> = Vec::new();
+            let mut line_rows = Vec::with_capacity(lines.len() + 1);
+            for line in lines {
+                line_rows.push(items.len());
+                items.extend(wrap_line(line, wrap_width).into_iter().map(ListItem::new));
+            }
+            line_rows.push(items.len());
+            assistant_rows = assistant_lines
+                .into_iter()
+                .map(|(index, start, end)| (index, line_rows[start], line_rows[end]))
+                .collect();
+
+            // Show a window of the conversation, clamped to the available lines
+            let height = messages_area.height.saturating_sub(2) as usize;
+            max_scroll = items.len().saturating_sub(height);
+            page_height = height;
+            let top = scroll_position.map(|p| p.min(max_scroll)).unwrap_or(max_scroll);
+            let title = if top < max_scroll {
+                format!("Conversation (scrolled up {} lines, PgDn to return)", max_scroll - top)
+            } else {
+                "Conversation".to_string()
+            };
+            let visible_items: Vec<ListItem> = items.into_iter().skip(top).take(height).collect();
+
+            let messages_list = List::new(visible_items)
+                .block(Block::default().borders(Borders::ALL).title(title))
                 .highlight_style(Style::default().add_modifier(Modifier::BOLD))
                 .highlight_symbol("> ");
 
@@ -367,9 +806,46 @@ impl Tui {
             input_area.render(frame, input_area_rect);
         })?;
 
+        // Keep the scroll state from the last full layout until there is room again
+        if !too_small {
+            self.max_scroll = max_scroll;
+            self.page_height = page_height;
+            self.assistant_rows = assistant_rows;
+        }
+
         Ok(())
     }
 
+    fn scroll_up(&mut self, lines: usize) {
+        let top = self.scroll_position.unwrap_or(self.max_scroll).min(self.max_scroll);
+        self.scroll_position = Some(top.saturating_sub(lines));
+    }
+
+    fn scroll_down(&mut self, lines: usize) {
+        if let Some(top) = self.scroll_position {
+            let top = top + lines;
+            // Reaching the bottom resumes following new output
+            self.scroll_position = if top >= self.max_scroll { None } else { Some(top) };
+        }
+    }
+
+    // Expand or collapse the newest assistant reply that is at least partly on screen
+    fn toggle_collapsed(&mut self) {
+        let top = self.scroll_position.unwrap_or(self.max_scroll).min(self.max_scroll);
+        let bottom = top + self.page_height;
+        let target = self
+            .assistant_rows
+            .iter()
+            .rev()
+            .find(|(_, start, end)| *start < bottom && *end > top)
+            .or_else(|| self.assistant_rows.last())
+            .map(|(index, _, _)| *index);
+
+        if let Some(UiMessage::Assistant { collapsed, .. }) = target.and_then(|index| self.messages.get_mut(index)) {
+            *collapsed = !*collapsed;
+        }
+    }
+
     // This function is no longer needed as it's inlined in the draw function
     // to avoid borrowing issues
 
@@ -389,6 +865,37 @@ impl Tui {
             } => {
                 self.send_message().await?;
             }
+            KeyEvent {
+                code: KeyCode::PageUp, ..
+            } => {
+                self.scroll_up(self.page_height.max(1));
+            }
+            KeyEvent {
+                code: KeyCode::PageDown, ..
+            } => {
+                self.scroll_down(self.page_height.max(1));
+            }
+            KeyEvent {
+                code: KeyCode::Char('e'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                self.toggle_collapsed();
+            }
+            KeyEvent {
+                code: KeyCode::Up, ..
+            } if self.input_area.cursor_on_first_line() => {
+                if let Some(entry) = self.input_history.previous(self.input_area.get_text()) {
+                    self.input_area.set_text(entry);
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Down, ..
+            } if self.input_area.cursor_on_last_line() => {
+                if let Some(entry) = self.input_history.next() {
+                    self.input_area.set_text(entry);
+                }
+            }
             // Normal input
             _ => {
                 self.input_area.handle_key_event(key);
@@ -397,6 +904,205 @@ impl Tui {
         Ok(())
     }
 
+    // The user/assistant turns shown on screen, as a conversation with no id or title yet
+    fn to_conversation(&self) -> Conversation {
+        let mut conversation = Conversation::new(String::new());
+        for message in &self.messages {
+            match message {
+                UiMessage::User(content, _) => conversation.add_user_message(content.clone()),
+                UiMessage::Assistant { content, .. } => conversation.add_assistant_message(content.clone()),
+                UiMessage::Status(_) | UiMessage::Command(_, _) => continue,
+            }
+            // Keep when the turn happened on screen, not when it was saved
+            if let (Some(saved), UiMessage::User(_, at) | UiMessage::Assistant { at, .. }) =
+                (conversation.messages.last_mut(), message)
+            {
+                saved.timestamp = Some(*at);
+            }
+        }
+        conversation
+    }
+
+    // Store the user/assistant turns shown on screen, returning the id and file path
+    fn save_conversation(&mut self, title: &str) -> Result<(String, std::path::PathBuf)> {
+        let mut storage = ConversationStorage::new()?;
+        let mut conversation = self.to_conversation();
+
+        if let Some(id) = &self.saved_conversation_id
+            && let Ok(previous) = storage.load_conversation(id)
+        {
+            conversation.id = previous.id;
+            conversation.created_at = previous.created_at;
+            conversation.title = previous.title;
+        }
+
+        // An explicit title wins, then the existing one, then the first message, then a timestamp
+        if !title.is_empty() {
+            conversation.title = title.to_string();
+        } else if conversation.title.is_empty() {
+            conversation.title = conversation
+                .messages
+                .first()
+                .and_then(|m| Conversation::title_from_message(&m.content.text()))
+                .unwrap_or_else(|| format!("TUI session {}", chrono::Utc::now().format("%Y-%m-%d %H:%M")));
+        }
+
+        storage.save_conversation(&conversation)?;
+        self.saved_conversation_id = Some(conversation.id.clone());
+        let path = storage.get_conversation_path(&conversation.id)?;
+        Ok((conversation.id, path))
+    }
+
+    // Record the session after each reply, like interactive mode; failures are shown but don't end it
+    fn autosave(&mut self) {
+        if !self.messages.iter().any(|message| matches!(message, UiMessage::User(..))) {
+            return;
+        }
+        if let Err(err) = self.save_conversation("") {
+            debug!("Failed to save conversation: {}", err);
+            self.messages.push(UiMessage::Status(format!("Failed to save conversation: {}", err)));
+        }
+    }
+
+    // The last response, or with `all` the whole transcript, as plain text
+    fn copy_text(&self, all: bool) -> Option<String> {
+        if !all {
+            return self.messages.iter().rev().find_map(|message| match message {
+                UiMessage::Assistant { content, .. } => Some(content.clone()),
+                _ => None,
+            });
+        }
+
+        let assistant_label = self.client.config.assistant_label();
+        let turns: Vec<String> = self
+            .messages
+            .iter()
+            .filter_map(|message| match message {
+                UiMessage::User(content, _) => Some(format!("You: {}", content)),
+                UiMessage::Assistant { content, .. } => Some(format!("{}: {}", assistant_label, content)),
+                UiMessage::Status(_) | UiMessage::Command(_, _) => None,
+            })
+            .collect();
+        if turns.is_empty() { None } else { Some(turns.join("\n\n")) }
+    }
+
+    // Headless sessions have no clipboard, which is reported rather than treated as fatal
+    fn set_clipboard(&mut self, text: String) -> std::result::Result<(), arboard::Error> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new()?);
+        }
+        match self.clipboard.as_mut() {
+            Some(clipboard) => clipboard.set_text(text),
+            None => Err(arboard::Error::ClipboardNotSupported),
+        }
+    }
+
+    // Take whatever chunks have arrived without waiting, finishing the reply once the stream ends
+    fn poll_stream(&mut self) {
+        let Some(active) = self.active_stream.as_mut() else {
+            return;
+        };
+
+        let mut received = false;
+        let mut ended = None;
+        while let Some(item) = active.stream.next().now_or_never() {
+            match item {
+                Some(Ok(chunk)) => {
+                    if let Some(text) = active.pending.push(&chunk) {
+                        active.text.push_str(&text);
+                        received = true;
+                    }
+                }
+                Some(Err(err)) => {
+                    ended = Some(Some(err));
+                    break;
+                }
+                None => {
+                    ended = Some(None);
+                    break;
+                }
+            }
+        }
+
+        // Whatever is still held back is shown once the stream ends
+        if ended.is_some()
+            && let Some(text) = active.pending.finish()
+        {
+            active.text.push_str(&text);
+            received = true;
+        }
+
+        // Update the reply in place, keeping its collapsed state as it grows
+        if received {
+            match active.index {
+                Some(index) => {
+                    if let Some(UiMessage::Assistant { content, .. }) = self.messages.get_mut(index) {
+                        content.clone_from(&active.text);
+                    }
+                }
+                None => {
+                    active.index = Some(self.messages.len());
+                    self.messages.push(UiMessage::Assistant {
+                        content: active.text.clone(),
+                        collapsed: true,
+                        at: Utc::now(),
+                    });
+                }
+            }
+        }
+
+        if let Some(error) = ended {
+            let finish_reason = active.stream.finish_reason();
+            if let Some(usage) = active.stream.usage() {
+                self.session_usage.add(&usage);
+            }
+            self.active_stream = None;
+            match error {
+                Some(err) => self.messages.push(UiMessage::Status(format!("Error: {}", err))),
+                None => {
+                    if let Some(notice) = truncation_notice(finish_reason.as_deref()) {
+                        self.messages.push(UiMessage::Status(notice.to_string()));
+                    }
+                }
+            }
+            self.autosave();
+        }
+    }
+
+    // Stop a reply that is still streaming, keeping what arrived marked as partial
+    fn interrupt_stream(&mut self) {
+        let Some(mut active) = self.active_stream.take() else {
+            return;
+        };
+
+        active.stream.abort();
+        if let Some(text) = active.pending.finish() {
+            active.text.push_str(&text);
+        }
+        match active.index.and_then(|index| self.messages.get_mut(index)) {
+            Some(UiMessage::Assistant { content, .. }) => *content = mark_incomplete(&active.text),
+            // Everything that arrived was still held back, so the reply isn't on screen yet
+            _ if !active.text.is_empty() => self.messages.push(UiMessage::Assistant {
+                content: mark_incomplete(&active.text),
+                collapsed: true,
+                at: Utc::now(),
+            }),
+            _ => {}
+        }
+        self.autosave();
+    }
+
+    // Add sent input to the history and save it right away, so interactive mode sees it too
+    fn remember_input(&mut self, input: &str) {
+        self.input_history.push(input);
+        if let Some((store, path)) = self.history_file.as_mut() {
+            let saved = store.add(input).and_then(|_| store.save(path));
+            if let Err(err) = saved {
+                debug!("Failed to save input history: {}", err);
+            }
+        }
+    }
+
     async fn send_message(&mut self) -> Result<()> {
         let message = self.input_area.get_text();
         if message.is_empty() {
@@ -405,6 +1111,7 @@ impl Tui {
 
         let message = message.to_string();
         self.input_area.clear();
+        self.remember_input(&message);
 
         // Process commands
         if message.starts_with('/') {
@@ -416,25 +1123,35 @@ impl Tui {
                         "Available commands:
   /help - Show this help
   /clear - Clear the conversation
+  /save [title] - Save the conversation
+  /copy [all] - Copy the last response (or the transcript) to the clipboard
   /config - Show current configuration
   /model [name] - Show or change the model
   /stream - Toggle streaming mode
-  /quit - Exit the application"
+  /raw - Toggle Markdown rendering
+  /timestamps - Toggle message times
+  /quit - Exit the application
+
+Long replies are collapsed; press Ctrl+E to expand or collapse the one in view."
                             .to_string(),
                     ));
                 }
                 "/clear" => {
+                    // Dropping the stream aborts it; its reply is being cleared anyway
+                    self.active_stream = None;
                     self.messages.clear();
+                    self.saved_conversation_id = None;
                     self.messages.push(UiMessage::Status("Conversation cleared.".to_string()));
                 }
                 "/config" => {
-                    let config_info = format!(
+                    let mut config_info = format!(
                         "API Key: {}
 Model: {}
 Max Tokens: {}
 System Prompt: {:?}
 History Size: {}
-Streaming: {}",
+Streaming: {}
+Tokens Used (this session): {} ({} prompt + {} completion)",
                         mask_api_key(&self.client.config.api_key),
                         self.client.config.model,
                         self.client.config.max_tokens,
@@ -444,8 +1161,14 @@ Streaming: {}",
                             "enabled"
                         } else {
                             "disabled"
-                        }
+                        },
+                        self.session_usage.total_tokens,
+                        self.session_usage.prompt_tokens,
+                        self.session_usage.completion_tokens
                     );
+                    if self.session_usage.cached_tokens() > 0 {
+                        config_info.push_str(&format!("\nCached Prompt Tokens: {}", self.session_usage.cached_tokens()));
+                    }
                     self.messages
                         .push(UiMessage::Command("/config".to_string(), config_info));
                 }
@@ -455,11 +1178,14 @@ Streaming: {}",
                         // Change the model
                         let old_model = self.client.config.model.clone();
                         let new_model = parts[1].to_string();
-                        self.client.config.model = new_model.clone();
-                        self.messages.push(UiMessage::Command(
-                            "/model".to_string(),
-                            format!("Model changed from {} to {}", old_model, new_model),
-                        ));
+                        let mut notice = format!("Model changed from {} to {}", old_model, new_model);
+
+                        if let Some(warning) = model_warning(&self.client, &new_model).await {
+                            notice.push_str(&format!("\n\nWarning: {}", warning));
+                        }
+
+                        self.client.config.model = new_model;
+                        self.messages.push(UiMessage::Command("/model".to_string(), notice));
                     } else {
                         // Show current model
                         self.messages.push(UiMessage::Command(
@@ -492,6 +1218,41 @@ To change models, use /model <model_name>",
                         format!("Streaming mode: {}", status),
                     ));
                 }
+                cmd if cmd == "/save" || cmd.starts_with("/save ") => {
+                    let title = cmd["/save".len()..].trim();
+                    let result = match self.save_conversation(title) {
+                        Ok((id, path)) => format!("Saved conversation {}\nFile: {}", id, path.display()),
+                        Err(err) => format!("Failed to save conversation: {}", err),
+                    };
+                    self.messages.push(UiMessage::Command("/save".to_string(), result));
+                }
+                "/copy" | "/copy all" => {
+                    let result = match self.copy_text(cmd == "/copy all") {
+                        Some(text) => match self.set_clipboard(text) {
+                            Ok(()) if cmd == "/copy all" => "Copied the conversation to the clipboard".to_string(),
+                            Ok(()) => "Copied the last response to the clipboard".to_string(),
+                            Err(err) => format!("Clipboard unavailable: {}", err),
+                        },
+                        None => "Nothing to copy yet".to_string(),
+                    };
+                    self.messages.push(UiMessage::Status(result));
+                }
+                "/raw" => {
+                    self.render_markdown = !self.render_markdown;
+                    let status = if self.render_markdown { "enabled" } else { "disabled" };
+                    self.messages.push(UiMessage::Command(
+                        "/raw".to_string(),
+                        format!("Markdown rendering: {}", status),
+                    ));
+                }
+                "/timestamps" => {
+                    self.show_timestamps = !self.show_timestamps;
+                    let status = if self.show_timestamps { "shown" } else { "hidden" };
+                    self.messages.push(UiMessage::Command(
+                        "/timestamps".to_string(),
+                        format!("Message times: {}", status),
+                    ));
+                }
                 "/quit" => {
                     self.should_quit = true;
                 }
@@ -505,57 +1266,47 @@ To change models, use /model <model_name>",
             return Ok(());
         }
 
-        // Regular message
-        self.messages.push(UiMessage::User(message.clone()));
+        // A huge paste is usually a mistake, so it's only sent once it's submitted a second time
+        if let Some(warning) = oversized_input_warning(&message, self.client.config.max_input_chars)
+            && self.oversized_confirmed.as_deref() != Some(message.as_str())
+        {
+            self.messages.push(UiMessage::Status(format!("{}. Press Enter again to send it anyway.", warning)));
+            self.input_area.set_text(&message);
+            self.oversized_confirmed = Some(message);
+            return Ok(());
+        }
+        self.oversized_confirmed = None;
+
+        // A new question replaces any reply still arriving
+        self.interrupt_stream();
+
+        // Regular message; jump back to the newest output
+        self.scroll_position = None;
+        self.messages.push(UiMessage::User(message.clone(), Utc::now()));
         self.draw()?; // Update UI to show user message
 
+        // Earlier turns go along as context, cut down to what the model can take
+        let context = self.to_conversation().recent_messages(self.client.config.history_size);
+        let (context, trimmed) = self.client.fit_context_window(context).await;
+        if trimmed > 0 {
+            self.messages.push(UiMessage::Status(format!(
+                "Dropped the {} oldest turn(s) to fit the model's context window",
+                trimmed
+            )));
+        }
+
         // Use streaming or non-streaming based on config
         if self.client.config.use_streaming {
             // Use the streaming API
-            match self.client.send_message_streaming(&message).await {
-                Ok(mut stream) => {
-                    let mut full_response = String::new();
-                    let mut current_response = String::new();
-
-                    // Process the stream
-                    while let Some(chunk_result) = stream.next().await {
-                        match chunk_result {
-                            Ok(chunk) => {
-                                full_response.push_str(&chunk);
-                                current_response.push_str(&chunk);
-
-                                // Update the UI every few characters or when we get a newline
-                                if chunk.contains('\n') || current_response.len() > 10 {
-                                    // Add or update assistant message
-                                    if let Some(last_msg) = self.messages.last() {
-                                        if matches!(last_msg, UiMessage::Assistant(_)) {
-                                            self.messages.pop();
-                                        }
-                                    }
-                                    self.messages.push(UiMessage::Assistant(full_response.clone()));
-                                    current_response.clear();
-                                    self.draw()?;
-                                }
-                            }
-                            Err(err) => {
-                                self.messages.push(UiMessage::Status(format!("Error: {}", err)));
-                                self.draw()?;
-                                break;
-                            }
-                        }
-                    }
-
-                    // Final update if needed
-                    if !current_response.is_empty() {
-                        // Add or update assistant message
-                        if let Some(last_msg) = self.messages.last() {
-                            if matches!(last_msg, UiMessage::Assistant(_)) {
-                                self.messages.pop();
-                            }
-                        }
-                        self.messages.push(UiMessage::Assistant(full_response));
-                        self.draw()?;
-                    }
+            match self.client.send_message_streaming_with_history(context).await {
+                Ok(stream) => {
+                    // Chunks are picked up by the UI loop
+                    self.active_stream = Some(ActiveStream {
+                        stream,
+                        text: String::new(),
+                        pending: FlushBuffer::new(self.client.config.stream_flush_chars),
+                        index: None,
+                    });
                 }
                 Err(err) => {
                     self.messages
@@ -565,9 +1316,24 @@ To change models, use /model <model_name>",
             }
         } else {
             // Standard non-streaming mode
-            match self.client.send_message(&message).await {
-                Ok(response) => {
-                    self.messages.push(UiMessage::Assistant(response));
+            // Placeholder until the complete response arrives
+            self.messages.push(UiMessage::Status(format!(
+                "{} is typing…",
+                self.client.config.assistant_label()
+            )));
+            self.draw()?;
+
+            let result = self.client.send_message_with_usage(context).await;
+            self.messages.pop();
+
+            match result {
+                Ok((response, usage)) => {
+                    self.session_usage.add(&usage);
+                    self.messages.push(UiMessage::Assistant { content: response, collapsed: true, at: Utc::now() });
+                    if let Some(notice) = truncation_notice(self.client.last_finish_reason().as_deref()) {
+                        self.messages.push(UiMessage::Status(notice.to_string()));
+                    }
+                    self.autosave();
                     self.draw()?;
                 }
                 Err(err) => {
@@ -582,8 +1348,58 @@ To change models, use /model <model_name>",
     }
 }
 
+// The turns of a stored conversation as shown on screen, with their original times where known
+fn ui_messages(conversation: &Conversation) -> Vec<UiMessage> {
+    conversation
+        .messages
+        .iter()
+        .filter_map(|message| {
+            let at = message.timestamp.unwrap_or(conversation.updated_at);
+            match message.role.as_str() {
+                "user" => Some(UiMessage::User(message.content.text().into_owned(), at)),
+                "assistant" => Some(UiMessage::Assistant {
+                    content: message.content.text().into_owned(),
+                    collapsed: true,
+                    at,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Entries from ~/.kona_history, capped like interactive mode's, plus the file to save new ones to
+fn load_input_history(history_size: usize) -> (InputHistory, Option<(FileHistory, PathBuf)>) {
+    let Some(path) = history_file_path() else {
+        return (InputHistory::new(Vec::new()), None);
+    };
+    let mut store = match readline_config(history_size) {
+        Ok(config) => FileHistory::with_config(config),
+        Err(err) => {
+            debug!("Input history disabled: {}", err);
+            return (InputHistory::new(Vec::new()), None);
+        }
+    };
+    if let Err(err) = store.load(&path) {
+        debug!("No previous history: {}", err);
+    }
+
+    let entries = (0..store.len())
+        .filter_map(|index| store.get(index, SearchDirection::Forward).ok().flatten())
+        .map(|result| result.entry.into_owned())
+        .collect();
+    (InputHistory::new(entries), Some((store, path)))
+}
+
+// Restore the terminal even if a panic unwinds through the UI loop
+impl Drop for Tui {
+    fn drop(&mut self) {
+        self.restore_terminal();
+    }
+}
+
 // Main function to start the TUI mode
-pub async fn start_tui_mode(client: OpenRouterClient) -> Result<()> {
-    let mut tui = Tui::new(client)?;
+pub async fn start_tui_mode(client: OpenRouterClient, resume: Option<String>) -> Result<()> {
+    let mut tui = Tui::new(client, resume)?;
     tui.run().await
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+// `kona validate`: check the configuration and API access, printing a checklist
+
+use colored::*;
+
+use crate::api::models::lookup_model;
+use crate::api::OpenRouterClient;
+use crate::config::Config;
+use crate::utils::error::{KonaError, Result};
+
+// Record and print one check, returning whether it passed
+fn report(passed: &mut bool, name: &str, result: Result<String>) {
+    match result {
+        Ok(detail) if detail.is_empty() => println!("  {} {}", "✓".green(), name),
+        Ok(detail) => println!("  {} {} ({})", "✓".green(), name, detail),
+        Err(err) => {
+            *passed = false;
+            println!("  {} {}: {}", "✗".red(), name, err);
+        }
+    }
+}
+
+// Run every check; returns false if any of them failed
+pub async fn run_validation(profile: Option<&str>) -> bool {
+    println!("{}", "Checking Kona configuration:".bold());
+    let mut passed = true;
+
+    // Only a missing profile, a broken .kona.toml or an unusable environment value stops the rest;
+    // everything else is checked separately below so every problem shows up in one run
+    let mut config = match Config::load_unchecked(profile) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("  {} Configuration loads: {}", "✗".red(), err);
+            return false;
+        }
+    };
+    let source = match config.config_file_path().filter(|path| path.exists()) {
+        Some(path) => Config::check_config_file(&path).map(|_| path.display().to_string()),
+        None => Ok("defaults and environment".to_string()),
+    };
+    report(&mut passed, "Configuration loads", source);
+
+    if config.system_prompt_file.is_some() {
+        let prompt = config.resolve_system_prompt().map(|_| String::new());
+        report(&mut passed, "System prompt file", prompt);
+    }
+    if config.api_key_command.is_some() {
+        report(&mut passed, "api_key_command", config.resolve_api_key().map(|_| String::new()));
+    }
+
+    let key = config.validate_api_key().map(|_| {
+        if config.api_key.starts_with("sk-or-") {
+            String::new()
+        } else {
+            "doesn't look like an OpenRouter key (sk-or-...)".to_string()
+        }
+    });
+    let key_usable = key.is_ok();
+    report(&mut passed, "API key is set", key);
+
+    let base_url = config.validate_base_url().map(|_| config.base_url.clone());
+    let base_url_usable = base_url.is_ok();
+    report(&mut passed, "Base URL", base_url);
+    report(&mut passed, "Sampling parameters", config.validate_sampling().map(|_| String::new()));
+
+    let max_tokens = match lookup_model(&config.model) {
+        Some(info) if config.max_tokens > info.max_output_tokens && !config.clamp_max_tokens => {
+            Err(KonaError::ConfigError(format!(
+                "{} exceeds the model's output limit of {}",
+                config.max_tokens, info.max_output_tokens
+            )))
+        }
+        Some(info) if config.max_tokens > info.max_output_tokens => {
+            Ok(format!("{}, clamped to {}", config.max_tokens, info.max_output_tokens))
+        }
+        _ if config.max_tokens == 0 => Err(KonaError::ConfigError(
+            "must be greater than zero".to_string(),
+        )),
+        _ => Ok(config.max_tokens.to_string()),
+    };
+    report(&mut passed, "max_tokens", max_tokens);
+
+    // Without a key or a valid URL the remote checks can only fail for the same reason
+    if key_usable && base_url_usable {
+        check_openrouter(&mut passed, &config).await;
+    } else {
+        println!("  {} OpenRouter checks skipped until the API key and base URL are fixed", "-".yellow());
+    }
+
+    println!();
+    if passed {
+        println!("{}", "All checks passed.".green());
+    } else {
+        println!("{}", "Some checks failed.".red());
+    }
+    passed
+}
+
+// Confirm the key and model with OpenRouter
+async fn check_openrouter(passed: &mut bool, config: &Config) {
+    let client = match OpenRouterClient::new(config.clone()) {
+        Ok(client) => client,
+        Err(err) => {
+            report(passed, "HTTP client", Err(err));
+            return;
+        }
+    };
+
+    // A rejected key makes the model check fail too, so authenticate first
+    report(passed, "API key accepted by OpenRouter", client.check_auth().await.map(|_| String::new()));
+
+    let model = match client.validate_model(&config.model).await {
+        Ok(None) => Ok(config.model.clone()),
+        Ok(Some(suggestions)) if suggestions.is_empty() => Err(KonaError::ConfigError(
+            format!("{} is not a known OpenRouter model", config.model),
+        )),
+        Ok(Some(suggestions)) => Err(KonaError::ConfigError(format!(
+            "{} is not a known OpenRouter model. Did you mean {}?",
+            config.model,
+            suggestions.join(", ")
+        ))),
+        Err(err) => Err(err),
+    };
+    report(passed, "Model", model);
+}
@@ -1,76 +1,471 @@
 use std::env;
 use std::fs;
 use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::utils::error::{KonaError, Result};
+use crate::utils::mask_api_key;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub api_key: String,
+    /// Shell command whose output is used as the API key (e.g. `pass show openrouter`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_command: Option<String>,
     pub model: String,
     pub max_tokens: u32,
     pub system_prompt: Option<String>,
+    /// File whose contents replace `system_prompt`, for prompts too long for TOML (`~` is expanded)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt_file: Option<PathBuf>,
     pub history_size: usize,
     pub use_streaming: bool,
+    /// Where saved conversations are kept instead of the data directory (`~` is expanded)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_dir: Option<PathBuf>,
+    /// Label shown for assistant replies; derived from the model when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assistant_name: Option<String>,
+    /// Upper bound in seconds for a complete non-streaming request
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Maximum gap in seconds between chunks of a streaming response
+    #[serde(default = "default_stream_idle_timeout_secs")]
+    pub stream_idle_timeout_secs: u64,
+    /// Lower max_tokens to the model's output limit instead of failing
+    #[serde(default = "default_clamp_max_tokens")]
+    pub clamp_max_tokens: bool,
+    /// Send a generated X-Request-Id header with each request
+    #[serde(default = "default_send_request_id")]
+    pub send_request_id: bool,
+    /// Root of the OpenRouter-compatible API, e.g. a proxy or local gateway
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    /// HTTP(S) proxy for all requests; HTTPS_PROXY/NO_PROXY are used when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Sent as `HTTP-Referer` for OpenRouter app attribution; empty to omit
+    #[serde(default = "default_http_referer")]
+    pub http_referer: Option<String>,
+    /// Sent as `X-Title`, the app name shown in OpenRouter's dashboard; empty to omit
+    #[serde(default = "default_app_title")]
+    pub app_title: Option<String>,
+    /// Sampling temperature, between 0 and 2
+    #[serde(default = "default_temperature")]
+    pub temperature: f32,
+    /// Nucleus sampling cutoff, between 0 and 1; omitted from requests when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Penalty for frequently repeated tokens, between -2 and 2; omitted when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Penalty for tokens that already appeared, between -2 and 2; omitted when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Strings that end the response when generated (at most MAX_STOP_SEQUENCES); omitted when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+    /// Print the response time (and streaming throughput) after each answer
+    #[serde(default)]
+    pub show_stats: bool,
+    /// Reuse stored answers for repeated `ask` requests (only at temperature 0 unless forced)
+    #[serde(default)]
+    pub cache_enabled: bool,
+    /// How long a cached answer stays valid, in seconds
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// Minimum gap between API requests in milliseconds, to stay under rate limits; 0 disables pacing
+    #[serde(default)]
+    pub min_request_interval_ms: u64,
+    /// Mark the system prompt and earlier history for Anthropic prompt caching (Claude models only)
+    #[serde(default)]
+    pub enable_prompt_caching: bool,
+    /// Most API requests the client runs at once, e.g. in `send_many`
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    /// Characters of a streamed reply to collect before flushing or redrawing; newlines always flush
+    #[serde(default = "default_stream_flush_chars")]
+    pub stream_flush_chars: usize,
+    /// Replies longer than this many lines start collapsed in the TUI; 0 never collapses
+    #[serde(default = "default_tui_collapse_lines")]
+    pub tui_collapse_lines: usize,
+    /// Longest message, in characters, sent without confirmation (an error in `ask` and pipe mode); 0 disables the check
+    #[serde(default = "default_max_input_chars")]
+    pub max_input_chars: usize,
+    /// Total bytes of file content `ask --file` may add to a prompt
+    #[serde(default = "default_max_file_context_bytes")]
+    pub max_file_context_bytes: usize,
+    /// Profile this configuration was loaded from, if any
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// Project `.kona.toml` layered over the user config, if one was found
+    #[serde(skip)]
+    pub project_config: Option<PathBuf>,
+    /// Print requests instead of sending them (set by `--dry-run`)
+    #[serde(skip)]
+    pub dry_run: bool,
+    /// Print each raw API response to stderr (set by `--raw-response`)
+    #[serde(skip)]
+    pub raw_response: bool,
+    /// Show reasoning and tool-call deltas while streaming (set by `--verbose-stream`)
+    #[serde(skip)]
+    pub verbose_stream: bool,
+    /// Plain output without colors or code highlighting (set by `--no-color`)
+    #[serde(skip)]
+    pub no_color: bool,
+    /// JSON schema the reply must follow, sent as `response_format` (set by `ask --schema`)
+    #[serde(skip)]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+// Walk up from `dir` to the first directory containing a project config file
+pub(crate) fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|dir| dir.join(PROJECT_CONFIG_FILE)).find(|path| path.is_file())
+}
+
+// Keys in `overlay` replace those in `base`, except that nested tables are merged key by key
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(nested)) => merge_tables(existing, nested),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// A project's prompt file must stay inside the project: no absolute paths, `~` or `..`, and no symlinks out of it
+fn project_prompt_file(project_file: &Path, file: &str) -> Option<PathBuf> {
+    let dir = project_file.parent()?;
+    let relative = Path::new(file);
+    let plain = relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !plain || file.starts_with('~') {
+        return None;
+    }
+
+    // A missing file is left for the usual read error; one that exists is checked after following symlinks
+    let joined = dir.join(relative);
+    match (joined.canonicalize(), dir.canonicalize()) {
+        (Ok(resolved), Ok(dir)) if resolved.starts_with(&dir) => Some(resolved),
+        (Ok(_), Ok(_)) => None,
+        _ => Some(joined),
+    }
+}
+
+// `~` or `~/...` relative to the home directory; other paths are returned unchanged
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+// Config file given with `--config`, which beats KONA_CONFIG and the default location
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+// Set by `--no-project-config` to skip looking for `.kona.toml`
+static PROJECT_CONFIG_DISABLED: OnceLock<bool> = OnceLock::new();
+
+// Name of the project-local config file, looked up from the working directory upwards
+pub const PROJECT_CONFIG_FILE: &str = ".kona.toml";
+
+// Settings a checked-out repository must not control: they could leak the API key or run commands
+const PROJECT_CONFIG_DENIED_KEYS: &[&str] = &["api_key", "api_key_command", "base_url", "proxy", "storage_dir"];
+
+fn default_request_timeout_secs() -> u64 {
+    120
+}
+
+fn default_stream_idle_timeout_secs() -> u64 {
+    60
+}
+
+fn default_clamp_max_tokens() -> bool {
+    true
+}
+
+fn default_send_request_id() -> bool {
+    true
+}
+
+fn default_temperature() -> f32 {
+    0.7
+}
+
+// The OpenAI-compatible API accepts up to four stop sequences
+pub const MAX_STOP_SEQUENCES: usize = 4;
+
+fn default_cache_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_tui_collapse_lines() -> usize {
+    40
+}
+
+fn default_max_concurrent_requests() -> usize {
+    4
+}
+
+fn default_stream_flush_chars() -> usize {
+    16
+}
+
+fn default_max_input_chars() -> usize {
+    200_000
+}
+
+fn default_max_file_context_bytes() -> usize {
+    100_000
+}
+
+fn default_base_url() -> String {
+    "https://openrouter.ai/api/v1".to_string()
+}
+
+fn default_http_referer() -> Option<String> {
+    Some("https://github.com/alanwilhelm/kona".to_string())
+}
+
+fn default_app_title() -> Option<String> {
+    Some("Kona".to_string())
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             api_key: String::new(),
+            api_key_command: None,
             model: "anthropic/claude-3-sonnet".to_string(),
             max_tokens: 1024,
             system_prompt: Some("You are Claude, an AI assistant by Anthropic. You are helping the user via the Kona CLI interface.".to_string()),
+            system_prompt_file: None,
+            storage_dir: None,
             history_size: 100,
             use_streaming: true,  // Enable streaming by default for a better experience
+            assistant_name: None,
+            request_timeout_secs: default_request_timeout_secs(),
+            stream_idle_timeout_secs: default_stream_idle_timeout_secs(),
+            clamp_max_tokens: default_clamp_max_tokens(),
+            send_request_id: default_send_request_id(),
+            base_url: default_base_url(),
+            proxy: None,
+            http_referer: default_http_referer(),
+            app_title: default_app_title(),
+            temperature: default_temperature(),
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: Vec::new(),
+            show_stats: false,
+            cache_enabled: false,
+            cache_ttl_secs: default_cache_ttl_secs(),
+            min_request_interval_ms: 0,
+            max_concurrent_requests: default_max_concurrent_requests(),
+            enable_prompt_caching: false,
+            stream_flush_chars: default_stream_flush_chars(),
+            tui_collapse_lines: default_tui_collapse_lines(),
+            max_input_chars: default_max_input_chars(),
+            max_file_context_bytes: default_max_file_context_bytes(),
+            profile: None,
+            project_config: None,
+            dry_run: false,
+            raw_response: false,
+            verbose_stream: false,
+            no_color: false,
+            response_schema: None,
         }
     }
 }
 
 impl Config {
     pub fn new() -> Result<Self> {
+        Self::with_profile(None)
+    }
+
+    // Load the configuration, using the named profile (or `KONA_PROFILE`) instead of the main config file
+    pub fn with_profile(profile: Option<&str>) -> Result<Self> {
+        let mut config = Self::load_unchecked(profile)?;
+        config.resolve_system_prompt()?;
+        config.resolve_api_key()?;
+        config.validate_api_key()?;
+        config.validate_sampling()?;
+        config.validate_base_url()?;
+        Ok(config)
+    }
+
+    // Layer the config file (or profile), the project config and the environment without checking the
+    // result, so `kona validate` can report every problem rather than only the first
+    pub fn load_unchecked(profile: Option<&str>) -> Result<Self> {
+        let profile = profile
+            .map(|p| p.to_string())
+            .or_else(|| env::var("KONA_PROFILE").ok())
+            .filter(|p| !p.trim().is_empty());
+
         let mut config = Config::default();
 
-        // Try to load from config file first
-        if let Some(config_from_file) = Self::load_from_file() {
+        if let Some(name) = &profile {
+            // A profile that doesn't exist is almost certainly a typo, so don't fall back silently
+            let path = Self::get_profile_path(name)?;
+            if !path.exists() {
+                return Err(KonaError::ConfigError(format!(
+                    "Profile '{}' not found. Create it at {:?}",
+                    name, path
+                )));
+            }
+            config = Self::load_from_file(&path).ok_or_else(|| {
+                KonaError::ConfigError(format!("Failed to load profile '{}' from {:?}", name, path))
+            })?;
+            debug!("Loaded configuration from profile {}", name);
+        } else if let Some(config_from_file) = Self::get_config_path().and_then(|path| Self::load_from_file(&path)) {
+            // Try to load from config file first
             debug!("Loaded configuration from file");
             config = config_from_file;
         } else {
             debug!("No config file found or error reading it, using default config");
         }
+        config.profile = profile;
+
+        // A project's `.kona.toml` sits between the user config and the environment
+        if let Some(path) = Self::project_config_path() {
+            config = Self::apply_project_config(config, &path)?;
+            debug!("Applied project configuration from {:?}", path);
+        }
 
         // Environment variables override config file settings
         Self::apply_env_overrides(&mut config)?;
 
-        // API key is required
-        if config.api_key.trim().is_empty() {
+        Ok(config)
+    }
+
+    // A prompt file replaces the inline prompt
+    pub fn resolve_system_prompt(&mut self) -> Result<()> {
+        if let Some(path) = self.system_prompt_file.clone() {
+            self.system_prompt = Some(Self::read_system_prompt_file(&path)?);
+        }
+        Ok(())
+    }
+
+    // A secret command beats the literal key, but not an explicit environment variable
+    pub fn resolve_api_key(&mut self) -> Result<()> {
+        if let Some(command) = self.api_key_command.clone().filter(|_| Self::env_api_key().is_none()) {
+            debug!("Loading API key from api_key_command");
+            self.api_key = Self::run_api_key_command(&command)?;
+        }
+        Ok(())
+    }
+
+    // The API key must be set, and not left as a placeholder
+    pub fn validate_api_key(&self) -> Result<()> {
+        if self.api_key.trim().is_empty() {
             return Err(KonaError::ConfigError(
                 "API key is required. Set it in the config file or with KONA_OPENROUTER_API_KEY environment variable.".to_string(),
             ));
         }
 
-        // Validate API key
-        if config.api_key == "your_api_key_here" ||
-           (config.api_key.starts_with("sk-ant-api") && config.api_key.contains("not-a-real-key")) {
+        if self.api_key == "your_api_key_here" ||
+           (self.api_key.starts_with("sk-ant-api") && self.api_key.contains("not-a-real-key")) {
             return Err(KonaError::ConfigError(
                 "Invalid API key. Please set a valid API key in the config file or as an environment variable.".to_string(),
             ));
         }
 
-        Ok(config)
+        Ok(())
+    }
+
+    // Loading skips a config file that doesn't parse, so this reports why
+    pub fn check_config_file(path: &Path) -> Result<()> {
+        let content = fs::read_to_string(path)?;
+        ConfigFormat::from_path(path)
+            .parse::<Config>(&content)
+            .map(|_| ())
+            .map_err(|e| KonaError::ConfigError(format!("{} in {:?}", e, path)))
+    }
+
+    // The base URL must be an absolute http(s) URL
+    pub fn validate_base_url(&self) -> Result<()> {
+        let url = reqwest::Url::parse(&self.base_url).map_err(|e| {
+            KonaError::ConfigError(format!("Invalid base_url '{}': {}", self.base_url, e))
+        })?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(KonaError::ConfigError(format!(
+                "Invalid base_url '{}': expected an http or https URL",
+                self.base_url
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Check the optional sampling parameters against the ranges the API accepts
+    pub fn validate_sampling(&self) -> Result<()> {
+        let checks = [
+            ("temperature", Some(self.temperature), 0.0, 2.0),
+            ("top_p", self.top_p, 0.0, 1.0),
+            ("frequency_penalty", self.frequency_penalty, -2.0, 2.0),
+            ("presence_penalty", self.presence_penalty, -2.0, 2.0),
+        ];
+
+        for (name, value, min, max) in checks {
+            if let Some(value) = value.filter(|value| !(min..=max).contains(value)) {
+                return Err(KonaError::ConfigError(format!(
+                    "{} must be between {} and {}, got {}",
+                    name, min, max, value
+                )));
+            }
+        }
+
+        if self.stop_sequences.len() > MAX_STOP_SEQUENCES {
+            return Err(KonaError::ConfigError(format!(
+                "At most {} stop sequences are allowed, got {}",
+                MAX_STOP_SEQUENCES,
+                self.stop_sequences.len()
+            )));
+        }
+        if self.stop_sequences.iter().any(String::is_empty) {
+            return Err(KonaError::ConfigError("Stop sequences can't be empty".to_string()));
+        }
+
+        Ok(())
+    }
+
+    // Name used to label assistant output, falling back to one derived from the model
+    pub fn assistant_label(&self) -> String {
+        if let Some(name) = self.assistant_name.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+            return name.to_string();
+        }
+
+        let model = self.model.to_lowercase();
+        if model.contains("claude") {
+            "Claude".to_string()
+        } else if model.contains("gpt") || model.starts_with("openai/") {
+            "GPT".to_string()
+        } else if model.contains("gemini") {
+            "Gemini".to_string()
+        } else if model.contains("llama") {
+            "Llama".to_string()
+        } else if model.contains("mistral") || model.contains("mixtral") {
+            "Mistral".to_string()
+        } else {
+            "Assistant".to_string()
+        }
     }
 
-    // Load configuration from a TOML file
-    fn load_from_file() -> Option<Self> {
-        let config_path = Self::get_config_path()?;
+    // Load configuration from a TOML or JSON file, chosen by its extension
+    pub(crate) fn load_from_file(config_path: &Path) -> Option<Self> {
         debug!("Looking for config file at: {:?}", config_path);
 
-        match fs::read_to_string(&config_path) {
+        match fs::read_to_string(config_path) {
             Ok(content) => {
-                match toml::from_str::<Config>(&content) {
+                match ConfigFormat::from_path(config_path).parse::<Config>(&content) {
                     Ok(config) => Some(config),
                     Err(e) => {
                         debug!("Error parsing config file: {}", e);
@@ -89,27 +484,206 @@ impl Config {
 
     // Get the path to the configuration file
     pub fn get_config_path() -> Option<PathBuf> {
+        // An explicit path is used as given; only the default directory is created on demand
+        if let Some(path) = Self::explicit_config_path() {
+            return Some(path);
+        }
+
         if let Some(mut config_dir) = dirs::config_dir() {
             config_dir.push("kona");
             fs::create_dir_all(&config_dir).ok()?;
             config_dir.push("config.toml");
-            Some(config_dir)
+            Some(existing_format(config_dir))
         } else {
             None
         }
     }
 
+    // Use `path` as the config file for the rest of the process (set once, from `--config`)
+    pub fn set_config_path(path: PathBuf) {
+        if CONFIG_PATH_OVERRIDE.set(path).is_err() {
+            debug!("Config path override already set");
+        }
+    }
+
+    fn explicit_config_path() -> Option<PathBuf> {
+        CONFIG_PATH_OVERRIDE
+            .get()
+            .cloned()
+            .or_else(|| env::var_os("KONA_CONFIG").filter(|path| !path.is_empty()).map(PathBuf::from))
+    }
+
+    // Stop `.kona.toml` files from being picked up for the rest of the process (from `--no-project-config`)
+    pub fn disable_project_config() {
+        PROJECT_CONFIG_DISABLED.set(true).ok();
+    }
+
+    // The nearest `.kona.toml` in the working directory or its ancestors, unless discovery is disabled
+    pub fn project_config_path() -> Option<PathBuf> {
+        if PROJECT_CONFIG_DISABLED.get().copied().unwrap_or(false) {
+            return None;
+        }
+        env::current_dir().ok().and_then(|dir| find_project_config(&dir))
+    }
+
+    // Layer the settings in a project file over `config`; the prompt file is resolved against the project and must stay in it
+    pub(crate) fn apply_project_config(config: Config, path: &Path) -> Result<Config> {
+        let content = fs::read_to_string(path)?;
+        let mut overlay: toml::Table = ConfigFormat::Toml
+            .parse(&content)
+            .map_err(|e| KonaError::ConfigError(format!("{} in {:?}", e, path)))?;
+
+        for key in PROJECT_CONFIG_DENIED_KEYS {
+            if overlay.remove(*key).is_some() {
+                warn!("Ignoring '{}' in {:?}; set it in your user config instead", key, path);
+            }
+        }
+        if let Some(toml::Value::String(file)) = overlay.get("system_prompt_file").cloned() {
+            match project_prompt_file(path, &file) {
+                Some(resolved) => {
+                    overlay.insert("system_prompt_file".to_string(), resolved.display().to_string().into());
+                }
+                None => {
+                    warn!("Ignoring system_prompt_file {:?} in {:?}; it must be a relative path inside the project", file, path);
+                    overlay.remove("system_prompt_file");
+                }
+            }
+        }
+
+        let invalid = |e: String| KonaError::ConfigError(format!("Failed to apply {:?}: {}", path, e));
+        let mut merged = match toml::Value::try_from(&config).map_err(|e| invalid(e.to_string()))? {
+            toml::Value::Table(table) => table,
+            _ => return Err(invalid("the configuration is not a table".to_string())),
+        };
+        merge_tables(&mut merged, overlay);
+
+        let mut layered: Config = toml::Value::Table(merged).try_into().map_err(|e| invalid(e.to_string()))?;
+        // Runtime-only settings don't survive the round trip through TOML
+        layered.profile = config.profile;
+        layered.project_config = Some(path.to_path_buf());
+        Ok(layered)
+    }
+
+    // Get the path of a named profile, `config.<profile>.toml` next to the main config file
+    pub fn get_profile_path(profile: &str) -> Result<PathBuf> {
+        let valid = !profile.is_empty()
+            && profile.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(KonaError::ConfigError(format!(
+                "Invalid profile name '{}': use letters, digits, '-' and '_'",
+                profile
+            )));
+        }
+
+        let config_path = Self::get_config_path()
+            .ok_or_else(|| KonaError::ConfigError("Could not determine config directory".to_string()))?;
+        Ok(existing_format(config_path.with_file_name(format!("config.{}.toml", profile))))
+    }
+
+    // Where `init` writes a new config file: `--config` as given, otherwise `config.<format>` in the config directory
+    pub fn new_config_path(format: ConfigFormat) -> Result<PathBuf> {
+        if let Some(path) = Self::explicit_config_path() {
+            return Ok(path);
+        }
+        let config_path = Self::get_config_path()
+            .ok_or_else(|| KonaError::ConfigError("Could not determine config directory".to_string()))?;
+        Ok(config_path.with_file_name(format!("config.{}", format.extension())))
+    }
+
+    // The file this configuration was loaded from (or would be)
+    pub fn config_file_path(&self) -> Option<PathBuf> {
+        match &self.profile {
+            Some(profile) => Self::get_profile_path(profile).ok(),
+            None => Self::get_config_path(),
+        }
+    }
+
+    // The resolved settings as JSON for `kona config --format json`, with the API key masked
+    pub fn to_masked_json(&self) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)
+            .map_err(|e| KonaError::ConfigError(format!("Failed to serialize config: {}", e)))?;
+
+        if let Some(settings) = value.as_object_mut() {
+            settings.insert("api_key".to_string(), mask_api_key(&self.api_key).into());
+            settings.insert("profile".to_string(), self.profile.clone().into());
+            settings.insert("assistant_label".to_string(), self.assistant_label().into());
+
+            let path = self.config_file_path();
+            let exists = path.as_ref().is_some_and(|path| path.exists());
+            settings.insert("config_path".to_string(), path.map(|path| path.display().to_string()).into());
+            settings.insert("config_exists".to_string(), exists.into());
+            settings.insert(
+                "project_config".to_string(),
+                self.project_config.as_ref().map(|path| path.display().to_string()).into(),
+            );
+        }
+        Ok(value)
+    }
+
+    // API key from environment, if any variable is set
+    fn env_api_key() -> Option<String> {
+        Self::env_api_key_var().and_then(|var| env::var(var).ok())
+    }
+
+    // Name of the environment variable the API key is taken from, if any
+    pub(crate) fn env_api_key_var() -> Option<&'static str> {
+        // KONA_OPENROUTER_API_KEY is preferred; OPENROUTER_API_KEY is kept for backward compatibility
+        ["KONA_OPENROUTER_API_KEY", "KONA_API_KEY", "OPENROUTER_API_KEY"]
+            .into_iter()
+            .find(|var| env::var(var).is_ok())
+    }
+
+    // Read the system prompt from a file, expanding a leading `~` to the home directory
+    pub(crate) fn read_system_prompt_file(path: &Path) -> Result<String> {
+        let path = expand_home(path);
+        let prompt = fs::read_to_string(&path).map_err(|e| {
+            KonaError::ConfigError(format!("Failed to read system_prompt_file {}: {}", path.display(), e))
+        })?;
+
+        let prompt = prompt.trim();
+        if prompt.is_empty() {
+            return Err(KonaError::ConfigError(format!(
+                "system_prompt_file {} is empty",
+                path.display()
+            )));
+        }
+        Ok(prompt.to_string())
+    }
+
+    // Run the configured secret command through the shell and use its trimmed stdout as the key
+    pub(crate) fn run_api_key_command(command: &str) -> Result<String> {
+        #[cfg(windows)]
+        let output = Command::new("cmd").args(["/C", command]).output();
+        #[cfg(not(windows))]
+        let output = Command::new("sh").args(["-c", command]).output();
+
+        let output = output.map_err(|e| {
+            KonaError::ConfigError(format!("Failed to run api_key_command: {}", e))
+        })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(KonaError::ConfigError(format!(
+                "api_key_command exited with {}: {}",
+                output.status,
+                stderr.trim()
+            )));
+        }
+
+        let api_key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if api_key.is_empty() {
+            return Err(KonaError::ConfigError(
+                "api_key_command produced no output".to_string(),
+            ));
+        }
+
+        Ok(api_key)
+    }
+
     // Apply environment variable overrides to the configuration
     fn apply_env_overrides(config: &mut Self) -> Result<()> {
         // API key from environment (highest priority)
-        // First try KONA_OPENROUTER_API_KEY (preferred)
-        let api_key = env::var("KONA_OPENROUTER_API_KEY").ok()
-            // Then try KONA_API_KEY as second option
-            .or_else(|| env::var("KONA_API_KEY").ok())
-            // Then try OPENROUTER_API_KEY as fallback for backward compatibility
-            .or_else(|| env::var("OPENROUTER_API_KEY").ok());
-
-        if let Some(api_key) = api_key {
+        if let Some(api_key) = Self::env_api_key() {
             // Clean the API key to remove any whitespace
             let cleaned_api_key = api_key.trim().to_string();
             config.api_key = cleaned_api_key;
@@ -132,6 +706,16 @@ impl Config {
         // System prompt override
         if let Ok(system_prompt) = env::var("KONA_SYSTEM_PROMPT") {
             config.system_prompt = Some(system_prompt);
+            // A prompt file from the config files would otherwise replace it; `KONA_SYSTEM_PROMPT_FILE` still wins
+            config.system_prompt_file = None;
+        }
+
+        if let Ok(path) = env::var("KONA_SYSTEM_PROMPT_FILE") {
+            config.system_prompt_file = Some(PathBuf::from(path)).filter(|p| !p.as_os_str().is_empty());
+        }
+
+        if let Ok(dir) = env::var("KONA_STORAGE_DIR") {
+            config.storage_dir = Some(PathBuf::from(dir)).filter(|p| !p.as_os_str().is_empty());
         }
 
         // History size override
@@ -150,14 +734,185 @@ impl Config {
                                   streaming_str.to_lowercase() == "yes";
         }
 
+        // Assistant name override
+        if let Ok(assistant_name) = env::var("KONA_ASSISTANT_NAME") {
+            config.assistant_name = Some(assistant_name);
+        }
+
+        // API endpoint override
+        if let Ok(base_url) = env::var("KONA_BASE_URL") {
+            config.base_url = base_url;
+        }
+
+        // Proxy override
+        if let Ok(proxy) = env::var("KONA_PROXY") {
+            config.proxy = Some(proxy).filter(|p| !p.trim().is_empty());
+        }
+
+        // Attribution overrides; an empty value omits the header
+        if let Ok(referer) = env::var("KONA_HTTP_REFERER") {
+            config.http_referer = Some(referer);
+        }
+        if let Ok(title) = env::var("KONA_APP_TITLE") {
+            config.app_title = Some(title);
+        }
+
+        // Request timeout override
+        if let Ok(timeout_str) = env::var("KONA_REQUEST_TIMEOUT") {
+            if let Ok(timeout) = timeout_str.parse::<u64>() {
+                config.request_timeout_secs = timeout;
+            } else {
+                debug!("Invalid KONA_REQUEST_TIMEOUT value: {}", timeout_str);
+            }
+        }
+
+        // Stream idle timeout override
+        if let Ok(timeout_str) = env::var("KONA_STREAM_IDLE_TIMEOUT") {
+            if let Ok(timeout) = timeout_str.parse::<u64>() {
+                config.stream_idle_timeout_secs = timeout;
+            } else {
+                debug!("Invalid KONA_STREAM_IDLE_TIMEOUT value: {}", timeout_str);
+            }
+        }
+
+        // Temperature override
+        if let Ok(temperature_str) = env::var("KONA_TEMPERATURE") {
+            if let Ok(temperature) = temperature_str.parse::<f32>() {
+                config.temperature = temperature;
+            } else {
+                debug!("Invalid KONA_TEMPERATURE value: {}", temperature_str);
+            }
+        }
+
+        if let Ok(stats_str) = env::var("KONA_SHOW_STATS") {
+            config.show_stats = stats_str.to_lowercase() == "true" ||
+                                stats_str == "1" ||
+                                stats_str.to_lowercase() == "yes";
+        }
+
+        // Response cache overrides
+        if let Ok(cache_str) = env::var("KONA_CACHE") {
+            config.cache_enabled = cache_str.to_lowercase() == "true" ||
+                                   cache_str == "1" ||
+                                   cache_str.to_lowercase() == "yes";
+        }
+
+        if let Ok(ttl_str) = env::var("KONA_CACHE_TTL") {
+            if let Ok(ttl) = ttl_str.parse::<u64>() {
+                config.cache_ttl_secs = ttl;
+            } else {
+                debug!("Invalid KONA_CACHE_TTL value: {}", ttl_str);
+            }
+        }
+
+        // Request pacing override
+        if let Ok(interval_str) = env::var("KONA_MIN_REQUEST_INTERVAL_MS") {
+            if let Ok(interval) = interval_str.parse::<u64>() {
+                config.min_request_interval_ms = interval;
+            } else {
+                debug!("Invalid KONA_MIN_REQUEST_INTERVAL_MS value: {}", interval_str);
+            }
+        }
+
+        // Prompt caching override
+        if let Ok(caching_str) = env::var("KONA_PROMPT_CACHING") {
+            config.enable_prompt_caching = caching_str.to_lowercase() == "true" ||
+                                           caching_str == "1" ||
+                                           caching_str.to_lowercase() == "yes";
+        }
+
+        // Concurrency limit override
+        if let Ok(limit_str) = env::var("KONA_MAX_CONCURRENT_REQUESTS") {
+            if let Ok(limit) = limit_str.parse::<usize>() {
+                config.max_concurrent_requests = limit;
+            } else {
+                debug!("Invalid KONA_MAX_CONCURRENT_REQUESTS value: {}", limit_str);
+            }
+        }
+
+        // Stream flush granularity override
+        if let Ok(chars_str) = env::var("KONA_STREAM_FLUSH_CHARS") {
+            if let Ok(chars) = chars_str.parse::<usize>() {
+                config.stream_flush_chars = chars;
+            } else {
+                debug!("Invalid KONA_STREAM_FLUSH_CHARS value: {}", chars_str);
+            }
+        }
+
+        // TUI collapse threshold override
+        if let Ok(lines_str) = env::var("KONA_TUI_COLLAPSE_LINES") {
+            if let Ok(lines) = lines_str.parse::<usize>() {
+                config.tui_collapse_lines = lines;
+            } else {
+                debug!("Invalid KONA_TUI_COLLAPSE_LINES value: {}", lines_str);
+            }
+        }
+
+        // File context limit override
+        if let Ok(limit_str) = env::var("KONA_MAX_FILE_CONTEXT_BYTES") {
+            if let Ok(limit) = limit_str.parse::<usize>() {
+                config.max_file_context_bytes = limit;
+            } else {
+                debug!("Invalid KONA_MAX_FILE_CONTEXT_BYTES value: {}", limit_str);
+            }
+        }
+
+        // Input size limit override
+        if let Ok(limit_str) = env::var("KONA_MAX_INPUT_CHARS") {
+            if let Ok(limit) = limit_str.parse::<usize>() {
+                config.max_input_chars = limit;
+            } else {
+                debug!("Invalid KONA_MAX_INPUT_CHARS value: {}", limit_str);
+            }
+        }
+
         Ok(())
     }
 
-    // Create a default config file if it doesn't exist
-    pub fn create_default_config_file() -> Result<PathBuf> {
+    // Back up the config file and overwrite it with defaults, optionally keeping the API key.
+    // Returns the config path and the backup path, if there was a file to back up.
+    pub fn reset_config_file(keep_api_key: bool) -> Result<(PathBuf, Option<PathBuf>)> {
         let config_path = Self::get_config_path()
             .ok_or_else(|| KonaError::ConfigError("Could not determine config directory".to_string()))?;
 
+        let format = ConfigFormat::from_path(&config_path);
+        let mut default_config = Config::default();
+        let mut backup_path = None;
+
+        if config_path.exists() {
+            let content = fs::read_to_string(&config_path)
+                .map_err(|e| KonaError::ConfigError(format!("Failed to read config file: {}", e)))?;
+
+            // Parse loosely so a key can be recovered even from a config that no longer deserializes
+            if keep_api_key
+                && let Some(api_key) = format
+                    .parse::<serde_json::Value>(&content)
+                    .ok()
+                    .and_then(|value| value.get("api_key").and_then(|k| k.as_str()).map(|k| k.to_string()))
+            {
+                default_config.api_key = api_key;
+            }
+
+            let backup = config_path.with_extension(format!("{}.bak", format.extension()));
+            fs::write(&backup, content)
+                .map_err(|e| KonaError::ConfigError(format!("Failed to write config backup: {}", e)))?;
+            backup_path = Some(backup);
+        }
+
+        let content = format.serialize(&default_config)?;
+
+        fs::write(&config_path, content)
+            .map_err(|e| KonaError::ConfigError(format!("Failed to write config file: {}", e)))?;
+
+        info!("Reset config file at: {:?}", config_path);
+
+        Ok((config_path, backup_path))
+    }
+
+    // Create a default config file in the given format if it doesn't exist
+    pub fn create_default_config_file(format: ConfigFormat) -> Result<PathBuf> {
+        let config_path = Self::new_config_path(format)?;
+
         // Check if file already exists
         if config_path.exists() {
             return Ok(config_path);
@@ -166,16 +921,73 @@ impl Config {
         // Create a default config
         let default_config = Config::default();
 
-        // Serialize to TOML
-        let toml_content = toml::to_string_pretty(&default_config)
-            .map_err(|e| KonaError::ConfigError(format!("Failed to serialize config: {}", e)))?;
+        // An explicit `--config` path keeps the format its extension implies
+        let content = ConfigFormat::from_path(&config_path).serialize(&default_config)?;
 
         // Write to file
-        fs::write(&config_path, toml_content)
+        fs::write(&config_path, content)
             .map_err(|e| KonaError::ConfigError(format!("Failed to write config file: {}", e)))?;
 
         info!("Created default config file at: {:?}", config_path);
 
         Ok(config_path)
     }
-}
\ No newline at end of file
+}
+
+// On-disk format of a config file; TOML unless the file ends in `.json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Json => "json",
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, content: &str) -> Result<T> {
+        let parsed = match self {
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        };
+        parsed.map_err(|e| KonaError::ConfigError(format!("Failed to parse config: {}", e)))
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        let serialized = match self {
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        };
+        serialized.map_err(|e| KonaError::ConfigError(format!("Failed to serialize config: {}", e)))
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "toml" => Ok(ConfigFormat::Toml),
+            "json" => Ok(ConfigFormat::Json),
+            _ => Err(format!("unknown config format '{}' (expected toml or json)", value)),
+        }
+    }
+}
+
+// A `.toml` path, or its `.json` sibling when only that one exists
+fn existing_format(toml_path: PathBuf) -> PathBuf {
+    let json_path = toml_path.with_extension("json");
+    if !toml_path.exists() && json_path.exists() { json_path } else { toml_path }
+}
@@ -3,4 +3,4 @@ pub mod config;
 #[cfg(test)]
 mod tests;
 
-pub use config::Config;
\ No newline at end of file
+pub use config::{Config, ConfigFormat};
\ No newline at end of file
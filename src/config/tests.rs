@@ -1,44 +1,98 @@
 #[cfg(test)]
 mod tests {
-    use super::Config;
+    use crate::config::Config;
     use std::env;
+    use std::ffi::OsStr;
+    use std::sync::{Mutex, MutexGuard};
+    
+    // Tests run in parallel but share one environment, so any test that reads or changes the
+    // `KONA_*` variables holds this for its whole run
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+    
+    fn env_lock() -> MutexGuard<'static, ()> {
+        // A test that failed while holding the lock leaves nothing to clean up
+        ENV_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    
+    // Edition 2024 marks changing the environment unsafe because another thread could read it at the
+    // same time through libc; the standard library serialises its own access, and nothing under test
+    // reads the environment other than through `std::env`
+    fn set_env(key: &str, value: impl AsRef<OsStr>) {
+        unsafe { env::set_var(key, value) }
+    }
+    
+    fn remove_env(key: &str) {
+        unsafe { env::remove_var(key) }
+    }
     
     fn setup() {
-        env::remove_var("ANTHROPIC_API_KEY");
-        env::remove_var("KONA_MODEL");
-        env::remove_var("KONA_MAX_TOKENS");
-        env::remove_var("KONA_SYSTEM_PROMPT");
-        env::remove_var("KONA_HISTORY_SIZE");
-        env::remove_var("KONA_USE_STREAMING");
+        remove_env("KONA_OPENROUTER_API_KEY");
+        remove_env("KONA_API_KEY");
+        remove_env("OPENROUTER_API_KEY");
+        remove_env("KONA_MODEL");
+        remove_env("KONA_MAX_TOKENS");
+        remove_env("KONA_SYSTEM_PROMPT");
+        remove_env("KONA_HISTORY_SIZE");
+        remove_env("KONA_USE_STREAMING");
+        remove_env("KONA_ASSISTANT_NAME");
+        remove_env("KONA_REQUEST_TIMEOUT");
+        remove_env("KONA_STREAM_IDLE_TIMEOUT");
+        remove_env("KONA_HTTP_REFERER");
+        remove_env("KONA_APP_TITLE");
+        remove_env("KONA_PROFILE");
+        remove_env("KONA_BASE_URL");
+        remove_env("KONA_PROXY");
+        remove_env("KONA_MAX_FILE_CONTEXT_BYTES");
+        remove_env("KONA_MAX_INPUT_CHARS");
+        remove_env("KONA_TEMPERATURE");
+        remove_env("KONA_CACHE");
+        remove_env("KONA_CACHE_TTL");
+        remove_env("KONA_CONFIG");
+        remove_env("KONA_SYSTEM_PROMPT_FILE");
+        remove_env("KONA_TUI_COLLAPSE_LINES");
+        remove_env("KONA_MIN_REQUEST_INTERVAL_MS");
+        remove_env("KONA_MAX_CONCURRENT_REQUESTS");
+        remove_env("KONA_STREAM_FLUSH_CHARS");
+        remove_env("KONA_PROMPT_CACHING");
+        remove_env("KONA_STORAGE_DIR");
+        remove_env("KONA_SHOW_STATS");
     }
     
     #[test]
     fn test_config_defaults() {
+        let _env = env_lock();
         setup();
         
         // Set API key to avoid error
-        env::set_var("ANTHROPIC_API_KEY", "sk-ant-REDACTED");
+        set_env("KONA_OPENROUTER_API_KEY", "sk-ant-REDACTED");
         
         let config = Config::new().unwrap();
         
         assert_eq!(config.api_key, "sk-ant-REDACTED");
-        assert_eq!(config.model, "claude-3-sonnet-20240229");
+        assert_eq!(config.model, "anthropic/claude-3-sonnet");
         assert_eq!(config.max_tokens, 1024);
         assert_eq!(config.system_prompt, Some("You are Claude, an AI assistant by Anthropic. You are helping the user via the Kona CLI interface.".to_string()));
         assert_eq!(config.history_size, 100);
         assert_eq!(config.use_streaming, true);
+        assert_eq!(config.request_timeout_secs, 120);
+        assert_eq!(config.stream_idle_timeout_secs, 60);
+        assert_eq!(config.http_referer.as_deref(), Some("https://github.com/alanwilhelm/kona"));
+        assert_eq!(config.app_title.as_deref(), Some("Kona"));
     }
     
     #[test]
     fn test_config_env_override() {
+        let _env = env_lock();
         setup();
         
-        env::set_var("ANTHROPIC_API_KEY", "sk-ant-api-custom-key");
-        env::set_var("KONA_MODEL", "claude-3-opus-20240229");
-        env::set_var("KONA_MAX_TOKENS", "2048");
-        env::set_var("KONA_SYSTEM_PROMPT", "Custom system prompt");
-        env::set_var("KONA_HISTORY_SIZE", "50");
-        env::set_var("KONA_USE_STREAMING", "false");
+        set_env("KONA_OPENROUTER_API_KEY", "sk-ant-api-custom-key");
+        set_env("KONA_MODEL", "claude-3-opus-20240229");
+        set_env("KONA_MAX_TOKENS", "2048");
+        set_env("KONA_SYSTEM_PROMPT", "Custom system prompt");
+        set_env("KONA_HISTORY_SIZE", "50");
+        set_env("KONA_USE_STREAMING", "false");
+        set_env("KONA_REQUEST_TIMEOUT", "30");
+        set_env("KONA_STREAM_IDLE_TIMEOUT", "15");
         
         let config = Config::new().unwrap();
         
@@ -48,10 +102,13 @@ mod tests {
         assert_eq!(config.system_prompt, Some("Custom system prompt".to_string()));
         assert_eq!(config.history_size, 50);
         assert_eq!(config.use_streaming, false);
+        assert_eq!(config.request_timeout_secs, 30);
+        assert_eq!(config.stream_idle_timeout_secs, 15);
     }
     
     #[test]
     fn test_config_invalid_api_key() {
+        let _env = env_lock();
         setup();
         
         // No API key
@@ -59,18 +116,355 @@ mod tests {
         assert!(result.is_err());
         
         // Empty API key
-        env::set_var("ANTHROPIC_API_KEY", "");
+        set_env("KONA_OPENROUTER_API_KEY", "");
         let result = Config::new();
         assert!(result.is_err());
         
         // Template API key
-        env::set_var("ANTHROPIC_API_KEY", "your_api_key_here");
+        set_env("KONA_OPENROUTER_API_KEY", "your_api_key_here");
         let result = Config::new();
         assert!(result.is_err());
         
         // Invalid test key 
-        env::set_var("ANTHROPIC_API_KEY", "sk-ant-api-not-a-real-key");
+        set_env("KONA_OPENROUTER_API_KEY", "sk-ant-api-not-a-real-key");
         let result = Config::new();
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_assistant_label() {
+        let mut config = Config::default();
+        
+        // Derived from the model when not set
+        assert_eq!(config.assistant_label(), "Claude");
+        config.model = "openai/gpt-4o".to_string();
+        assert_eq!(config.assistant_label(), "GPT");
+        config.model = "some-vendor/unknown-model".to_string();
+        assert_eq!(config.assistant_label(), "Assistant");
+        
+        // Explicit name wins over the model
+        config.assistant_name = Some("Bot".to_string());
+        assert_eq!(config.assistant_label(), "Bot");
+        
+        // Blank name falls back to the derived label
+        config.assistant_name = Some("  ".to_string());
+        assert_eq!(config.assistant_label(), "Assistant");
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_api_key_command() {
+        // Output is trimmed
+        let key = Config::run_api_key_command("echo '  sk-or-from-command  '").unwrap();
+        assert_eq!(key, "sk-or-from-command");
+        
+        // Failing or silent commands are errors
+        assert!(Config::run_api_key_command("exit 3").is_err());
+        assert!(Config::run_api_key_command("true").is_err());
+    }
+    
+    #[test]
+    fn test_profile_path() {
+        let _env = env_lock();
+        let path = Config::get_profile_path("work").unwrap();
+        assert_eq!(path.file_name().unwrap(), "config.work.toml");
+        assert_eq!(path.parent(), Config::get_config_path().unwrap().parent());
+        
+        assert!(Config::get_profile_path("../secrets").is_err());
+        assert!(Config::get_profile_path("").is_err());
+    }
+    
+    #[test]
+    fn test_missing_profile_is_an_error() {
+        let _env = env_lock();
+        setup();
+        set_env("KONA_OPENROUTER_API_KEY", "sk-ant-REDACTED");
+        
+        let result = Config::with_profile(Some("kona-test-profile-that-does-not-exist"));
+        assert!(result.is_err());
+    }
+    
+    #[test]
+    fn test_validate_sampling() {
+        let mut config = Config::default();
+        assert!(config.validate_sampling().is_ok());
+        
+        config.top_p = Some(0.9);
+        config.frequency_penalty = Some(-1.5);
+        assert!(config.validate_sampling().is_ok());
+        
+        config.top_p = Some(1.5);
+        assert!(config.validate_sampling().is_err());
+        
+        config.top_p = None;
+        config.temperature = 2.5;
+        assert!(config.validate_sampling().is_err());
+        config.temperature = 0.0;
+        
+        config.top_p = None;
+        config.presence_penalty = Some(3.0);
+        assert!(config.validate_sampling().is_err());
+        config.presence_penalty = None;
+        
+        config.stop_sequences = vec!["```".to_string(), "END".to_string()];
+        assert!(config.validate_sampling().is_ok());
+        config.stop_sequences = vec!["a", "b", "c", "d", "e"].into_iter().map(String::from).collect();
+        assert!(config.validate_sampling().is_err());
+        config.stop_sequences = vec![String::new()];
+        assert!(config.validate_sampling().is_err());
+    }
+    
+    #[test]
+    fn test_validate_base_url() {
+        let mut config = Config::default();
+        assert_eq!(config.base_url, "https://openrouter.ai/api/v1");
+        assert!(config.validate_base_url().is_ok());
+        
+        config.base_url = "http://localhost:4000/v1/".to_string();
+        assert!(config.validate_base_url().is_ok());
+        
+        config.base_url = "not a url".to_string();
+        assert!(config.validate_base_url().is_err());
+        
+        config.base_url = "ftp://example.com".to_string();
+        assert!(config.validate_base_url().is_err());
+    }
+    
+    #[test]
+    fn test_validate_api_key_and_config_file() {
+        let mut config = Config::default();
+        assert!(config.validate_api_key().is_err());
+        config.api_key = "your_api_key_here".to_string();
+        assert!(config.validate_api_key().is_err());
+        config.api_key = "sk-or-v1-test".to_string();
+        assert!(config.validate_api_key().is_ok());
+        
+        // Each problem is reported on its own, not just the first one found
+        config.api_key = String::new();
+        config.base_url = "not a url".to_string();
+        config.temperature = 5.0;
+        assert!(config.validate_api_key().is_err());
+        assert!(config.validate_base_url().is_err());
+        assert!(config.validate_sampling().is_err());
+        
+        // A file that loading would skip reports why
+        let dir = env::temp_dir().join(format!("kona_config_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let broken = dir.join("config.toml");
+        std::fs::write(&broken, "model = \"unterminated\n").unwrap();
+        assert!(Config::check_config_file(&broken).is_err());
+        let valid = dir.join("valid.toml");
+        std::fs::write(&valid, toml::to_string(&Config::default()).unwrap()).unwrap();
+        assert!(Config::check_config_file(&valid).is_ok());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    
+    #[test]
+    fn test_kona_config_path() {
+        let _env = env_lock();
+        setup();
+        set_env("KONA_OPENROUTER_API_KEY", "sk-ant-REDACTED");
+        
+        let dir = env::temp_dir().join(format!("kona_config_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("custom.toml");
+        let custom = Config {
+            model: "anthropic/claude-3-haiku".to_string(),
+            ..Config::default()
+        };
+        std::fs::write(&path, toml::to_string(&custom).unwrap()).unwrap();
+        
+        set_env("KONA_CONFIG", &path);
+        assert_eq!(Config::get_config_path(), Some(path.clone()));
+        assert_eq!(Config::new().unwrap().model, "anthropic/claude-3-haiku");
+        
+        // Profiles sit next to the explicit file
+        assert_eq!(Config::get_profile_path("work").unwrap(), dir.join("config.work.toml"));
+        
+        remove_env("KONA_CONFIG");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    
+    #[test]
+    fn test_config_formats() {
+        use crate::config::ConfigFormat;
+        use std::path::Path;
+        
+        assert_eq!(ConfigFormat::from_path(Path::new("config.json")), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path(Path::new("config.JSON")), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path(Path::new("config.toml")), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path(Path::new("config")), ConfigFormat::Toml);
+        assert_eq!("json".parse::<ConfigFormat>(), Ok(ConfigFormat::Json));
+        assert!("yaml".parse::<ConfigFormat>().is_err());
+        
+        let custom = Config {
+            model: "anthropic/claude-3-haiku".to_string(),
+            system_prompt: Some("Be brief.".to_string()),
+            stop_sequences: vec!["END".to_string()],
+            ..Config::default()
+        };
+        
+        // Both formats round-trip through a file, picked by its extension
+        let dir = env::temp_dir().join(format!("kona_format_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for format in [ConfigFormat::Toml, ConfigFormat::Json] {
+            let path = dir.join(format!("config.{}", format.extension()));
+            std::fs::write(&path, format.serialize(&custom).unwrap()).unwrap();
+            
+            let loaded = Config::load_from_file(&path).unwrap();
+            assert_eq!(loaded.model, custom.model);
+            assert_eq!(loaded.system_prompt, custom.system_prompt);
+            assert_eq!(loaded.stop_sequences, custom.stop_sequences);
+            assert_eq!(loaded.max_tokens, custom.max_tokens);
+        }
+        
+        // JSON is not mistaken for TOML
+        let path = dir.join("config.toml");
+        std::fs::write(&path, ConfigFormat::Json.serialize(&custom).unwrap()).unwrap();
+        assert!(Config::load_from_file(&path).is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    
+    #[test]
+    fn test_masked_json() {
+        let _env = env_lock();
+        let config = Config {
+            api_key: "sk-or-v1-secretsecretsecret".to_string(),
+            profile: Some("work".to_string()),
+            ..Config::default()
+        };
+        
+        let value = config.to_masked_json().unwrap();
+        assert_eq!(value["api_key"], "sk-o****cret");
+        assert!(!value.to_string().contains("secretsecret"));
+        assert_eq!(value["model"], config.model.as_str());
+        assert_eq!(value["profile"], "work");
+        assert!(value["config_path"].as_str().unwrap().ends_with("config.work.toml"));
+        assert!(value["config_exists"].is_boolean());
+    }
+    
+    #[test]
+    fn test_system_prompt_file() {
+        use crate::config::config::expand_home;
+        use std::path::{Path, PathBuf};
+        
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_home(Path::new("~/prompts/persona.md")), home.join("prompts/persona.md"));
+        assert_eq!(expand_home(Path::new("/etc/persona.md")), PathBuf::from("/etc/persona.md"));
+        assert_eq!(expand_home(Path::new("~other/persona.md")), PathBuf::from("~other/persona.md"));
+        
+        let path = env::temp_dir().join(format!("kona_prompt_test_{}.md", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "You are a pirate.\n").unwrap();
+        assert_eq!(Config::read_system_prompt_file(&path).unwrap(), "You are a pirate.");
+        std::fs::remove_file(&path).ok();
+        
+        // A missing file is reported rather than silently ignored
+        assert!(Config::read_system_prompt_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_system_prompt_env_beats_config_file() {
+        let _env = env_lock();
+        setup();
+        set_env("KONA_OPENROUTER_API_KEY", "sk-ant-REDACTED");
+        
+        let dir = env::temp_dir().join(format!("kona_prompt_env_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("file.md"), "From the file.\n").unwrap();
+        std::fs::write(dir.join("env.md"), "From the env file.\n").unwrap();
+        let path = dir.join("config.toml");
+        let custom = Config {
+            system_prompt_file: Some(dir.join("file.md")),
+            ..Config::default()
+        };
+        std::fs::write(&path, toml::to_string(&custom).unwrap()).unwrap();
+        set_env("KONA_CONFIG", &path);
+        
+        assert_eq!(Config::new().unwrap().system_prompt.as_deref(), Some("From the file."));
+        
+        set_env("KONA_SYSTEM_PROMPT", "From the environment.");
+        assert_eq!(Config::new().unwrap().system_prompt.as_deref(), Some("From the environment."));
+        
+        set_env("KONA_SYSTEM_PROMPT_FILE", dir.join("env.md"));
+        assert_eq!(Config::new().unwrap().system_prompt.as_deref(), Some("From the env file."));
+        
+        remove_env("KONA_SYSTEM_PROMPT");
+        remove_env("KONA_SYSTEM_PROMPT_FILE");
+        remove_env("KONA_CONFIG");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_project_config() {
+        use crate::config::config::find_project_config;
+        
+        let root = env::temp_dir().join(format!("kona_project_test_{}", uuid::Uuid::new_v4()));
+        let nested = root.join("src").join("cli");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_config(&nested), None);
+        
+        // Found from any subdirectory, like .gitignore
+        let path = root.join(".kona.toml");
+        std::fs::write(
+            &path,
+            "model = \"anthropic/claude-3-haiku\"\nsystem_prompt_file = \"prompt.md\"\napi_key_command = \"echo stolen\"\n",
+        )
+        .unwrap();
+        assert_eq!(find_project_config(&nested), Some(path.clone()));
+        
+        let user = Config {
+            api_key: "sk-or-user-key".to_string(),
+            max_tokens: 1234,
+            ..Config::default()
+        };
+        let layered = Config::apply_project_config(user, &path).unwrap();
+        
+        // Project settings win, the rest of the user config is kept
+        assert_eq!(layered.model, "anthropic/claude-3-haiku");
+        assert_eq!(layered.max_tokens, 1234);
+        assert_eq!(layered.api_key, "sk-or-user-key");
+        assert_eq!(layered.system_prompt_file, Some(root.join("prompt.md")));
+        assert_eq!(layered.project_config, Some(path.clone()));
+        
+        // A repository can't run commands through the API key settings
+        assert_eq!(layered.api_key_command, None);
+        
+        std::fs::remove_dir_all(&root).ok();
+    }
+    
+    #[test]
+    fn test_project_config_prompt_file_stays_in_project() {
+        let root = env::temp_dir().join(format!("kona_project_test_{}", uuid::Uuid::new_v4()));
+        let project = root.join("project");
+        std::fs::create_dir_all(project.join("docs")).unwrap();
+        std::fs::write(root.join("secret"), "outside the project").unwrap();
+        std::fs::write(project.join("docs").join("prompt.md"), "You review Rust code.").unwrap();
+        
+        let user = Config {
+            system_prompt_file: Some(std::path::PathBuf::from("/home/user/persona.md")),
+            ..Config::default()
+        };
+        let path = project.join(".kona.toml");
+        let layered = |file: &str| {
+            std::fs::write(&path, format!("system_prompt_file = {:?}\n", file)).unwrap();
+            Config::apply_project_config(user.clone(), &path).unwrap().system_prompt_file
+        };
+        
+        // Hostile paths are dropped and the user's own prompt file is kept
+        for hostile in ["~/.ssh/id_rsa", "/etc/passwd", "../secret", "docs/../../secret"] {
+            assert_eq!(layered(hostile), user.system_prompt_file, "accepted {:?}", hostile);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("secret"), project.join("link.md")).unwrap();
+            assert_eq!(layered("link.md"), user.system_prompt_file);
+        }
+        
+        // Files inside the project are fine
+        let expected = project.join("docs").join("prompt.md").canonicalize().unwrap();
+        assert_eq!(layered("docs/prompt.md"), Some(expected.clone()));
+        assert_eq!(layered("./docs/prompt.md"), Some(expected));
+        
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
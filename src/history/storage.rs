@@ -4,9 +4,10 @@ use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 use tracing::debug;
 
-use crate::api::Message;
+use crate::api::{Message, MessageContent};
 use crate::utils::error::{KonaError, Result};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,6 +28,12 @@ pub struct ConversationSummary {
     pub message_count: usize,
 }
 
+// Longest title derived from a message, in characters
+const AUTO_TITLE_CHARS: usize = 50;
+
+// Appended to a streamed answer that was cut short by Ctrl-C or a stream error
+pub const INCOMPLETE_MARKER: &str = "[response interrupted]";
+
 impl Conversation {
     pub fn new(title: String) -> Self {
         let now = Utc::now();
@@ -41,22 +48,96 @@ impl Conversation {
         }
     }
     
-    pub fn add_user_message(&mut self, content: String) {
-        self.messages.push(Message {
-            role: "user".to_string(),
-            content,
-        });
-        self.updated_at = Utc::now();
+    pub fn add_user_message(&mut self, content: impl Into<MessageContent>) {
+        self.push_message(Message::new("user", content));
     }
     
     pub fn add_assistant_message(&mut self, content: String) {
-        self.messages.push(Message {
-            role: "assistant".to_string(),
-            content,
+        self.push_message(Message::new("assistant", content));
+    }
+    
+    // Assistant message labelled with the model that wrote it, so compared answers stay distinguishable
+    pub fn add_assistant_message_with_model(&mut self, content: String, model: &str) {
+        self.push_message(Message {
+            model: Some(model.to_string()),
+            ..Message::new("assistant", content)
         });
+    }
+    
+    // Append a message stamped with the current time
+    fn push_message(&mut self, message: Message) {
+        let now = Utc::now();
+        self.messages.push(Message { timestamp: Some(now), ..message });
+        self.updated_at = now;
+    }
+    
+    // Record streamed text, marking it when the stream didn't finish; returns false if there was nothing to keep
+    pub fn add_streamed_assistant_message(&mut self, content: String, model: &str, complete: bool) -> bool {
+        if content.trim().is_empty() {
+            return false;
+        }
+        
+        let content = if complete { content } else { mark_incomplete(&content) };
+        self.add_assistant_message_with_model(content, model);
+        true
+    }
+    
+    // Title taken from the first line of a message, or None if it has no text
+    pub fn title_from_message(content: &str) -> Option<String> {
+        let first_line = content.lines().map(str::trim).find(|line| !line.is_empty())?;
+        if first_line.chars().count() <= AUTO_TITLE_CHARS {
+            return Some(first_line.to_string());
+        }
+        
+        let truncated: String = first_line.chars().take(AUTO_TITLE_CHARS - 3).collect();
+        Some(format!("{}...", truncated.trim_end()))
+    }
+    
+    pub fn rename(&mut self, title: String) {
+        self.title = title;
         self.updated_at = Utc::now();
     }
     
+    pub fn last_user_message(&self) -> Option<&Message> {
+        self.messages.iter().rev().find(|m| m.role == "user")
+    }
+    
+    pub fn last_assistant_message(&self) -> Option<&Message> {
+        self.messages.iter().rev().find(|m| m.role == "assistant")
+    }
+    
+    // Removes the trailing user/assistant pair, leaving the conversation untouched otherwise
+    pub fn pop_last_exchange(&mut self) -> Option<(Message, Message)> {
+        let len = self.messages.len();
+        if len < 2 || self.messages[len - 2].role != "user" || self.messages[len - 1].role != "assistant" {
+            return None;
+        }
+        
+        let assistant = self.messages.pop()?;
+        let user = self.messages.pop()?;
+        self.updated_at = Utc::now();
+        Some((user, assistant))
+    }
+    
+    // Keeps only the first `index` messages
+    pub fn truncate_to(&mut self, index: usize) {
+        if index < self.messages.len() {
+            self.messages.truncate(index);
+            self.updated_at = Utc::now();
+        }
+    }
+    
+    // The newest messages that fit within `limit`, starting on a user turn
+    pub fn recent_messages(&self, limit: usize) -> Vec<Message> {
+        recent_window(&self.messages, limit)
+    }
+    
+    // Context for asking the latest user message again, without the answers it already has
+    pub fn regenerate_context(&self, limit: usize) -> Option<Vec<Message>> {
+        let last_user = self.messages.iter().rposition(|m| m.role == "user")?;
+        Some(recent_window(&self.messages[..=last_user], limit))
+    }
+    
     pub fn to_summary(&self) -> ConversationSummary {
         ConversationSummary {
             id: self.id.clone(),
@@ -68,6 +149,30 @@ impl Conversation {
     }
 }
 
+// A partial answer with the interrupted marker appended
+pub fn mark_incomplete(content: &str) -> String {
+    format!("{}\n\n{}", content.trim_end(), INCOMPLETE_MARKER)
+}
+
+fn recent_window(messages: &[Message], limit: usize) -> Vec<Message> {
+    // A reply followed directly by another was superseded by /switch, so only the newest is sent
+    let current: Vec<&Message> = messages
+        .iter()
+        .enumerate()
+        .filter(|(i, m)| !(m.role == "assistant" && messages.get(i + 1).is_some_and(|next| next.role == "assistant")))
+        .map(|(_, m)| m)
+        .collect();
+    
+    let mut start = current.len().saturating_sub(limit);
+    while start < current.len() && current[start].role != "user" {
+        start += 1;
+    }
+    current[start..].iter().map(|m| (*m).clone()).collect()
+}
+
+// Set from the `storage_dir` config setting before any storage is opened
+static STORAGE_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
 pub struct ConversationStorage {
     storage_dir: PathBuf,
     conversations: HashMap<String, ConversationSummary>,
@@ -75,7 +180,25 @@ pub struct ConversationStorage {
 
 impl ConversationStorage {
     pub fn new() -> Result<Self> {
-        let storage_dir = Self::get_storage_dir()?;
+        Self::with_dir(Self::get_storage_dir()?)
+    }
+    
+    // Storage rooted at `storage_dir`, created if missing
+    pub fn with_dir(storage_dir: PathBuf) -> Result<Self> {
+        if !storage_dir.exists() {
+            fs::create_dir_all(&storage_dir).map_err(|e| {
+                let hint = if e.kind() == io::ErrorKind::PermissionDenied {
+                    " (check its permissions or set storage_dir to a writable location)"
+                } else {
+                    ""
+                };
+                KonaError::IoError(io::Error::new(
+                    e.kind(),
+                    format!("Failed to create conversation directory {}: {}{}", storage_dir.display(), e, hint),
+                ))
+            })?;
+        }
+        
         let conversations = Self::load_conversation_index(&storage_dir)?;
         
         Ok(Self {
@@ -84,7 +207,16 @@ impl ConversationStorage {
         })
     }
     
-    fn get_storage_dir() -> Result<PathBuf> {
+    // Use `dir` for every storage opened afterwards; only the first call has an effect
+    pub fn set_storage_dir(dir: PathBuf) {
+        let _ = STORAGE_DIR_OVERRIDE.set(dir);
+    }
+    
+    pub(crate) fn get_storage_dir() -> Result<PathBuf> {
+        if let Some(dir) = STORAGE_DIR_OVERRIDE.get() {
+            return Ok(dir.clone());
+        }
+        
         let mut dir = match dirs::data_dir() {
             Some(dir) => dir,
             None => return Err(KonaError::IoError(io::Error::new(
@@ -95,17 +227,6 @@ impl ConversationStorage {
         
         dir.push("kona");
         dir.push("conversations");
-        
-        // Create directory if it doesn't exist
-        if !dir.exists() {
-            fs::create_dir_all(&dir).map_err(|e| {
-                KonaError::IoError(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Failed to create conversation directory: {}", e),
-                ))
-            })?;
-        }
-        
         Ok(dir)
     }
     
@@ -115,10 +236,18 @@ impl ConversationStorage {
         path
     }
     
-    fn get_conversation_path(&self, id: &str) -> PathBuf {
+    // Ids become file names, so anything that could leave the storage directory is refused
+    pub fn get_conversation_path(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || id == "." || id == ".." || id.contains(['/', '\\', '\0']) {
+            return Err(KonaError::IoError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid conversation id: {:?}", id),
+            )));
+        }
+        
         let mut path = self.storage_dir.clone();
         path.push(format!("{}.json", id));
-        path
+        Ok(path)
     }
     
     fn load_conversation_index(storage_dir: &PathBuf) -> Result<HashMap<String, ConversationSummary>> {
@@ -129,17 +258,11 @@ impl ConversationStorage {
         }
         
         let content = fs::read_to_string(&index_path).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to read conversation index: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to read conversation index: {}", e)))
         })?;
         
         serde_json::from_str(&content).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to parse conversation index: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to parse conversation index: {}", e)))
         })
     }
     
@@ -147,17 +270,11 @@ impl ConversationStorage {
         let index_path = Self::get_index_path(&self.storage_dir);
         
         let content = serde_json::to_string_pretty(&self.conversations).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to serialize conversation index: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to serialize conversation index: {}", e)))
         })?;
         
         fs::write(&index_path, content).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to write conversation index: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to write conversation index: {}", e)))
         })
     }
     
@@ -167,6 +284,30 @@ impl ConversationStorage {
         conversations
     }
     
+    // Id of the most recently updated conversation, if there are any
+    pub fn latest_id(&self) -> Option<String> {
+        self.get_all_conversations().into_iter().next().map(|summary| summary.id)
+    }
+    
+    pub fn contains(&self, id: &str) -> bool {
+        self.conversations.contains_key(id)
+    }
+    
+    // Full id for an exact id or a prefix matching exactly one conversation
+    pub fn resolve_id(&self, id_or_prefix: &str) -> Option<String> {
+        if self.contains(id_or_prefix) {
+            return Some(id_or_prefix.to_string());
+        }
+        if id_or_prefix.is_empty() {
+            return None;
+        }
+        let mut matches = self.conversations.keys().filter(|id| id.starts_with(id_or_prefix));
+        match (matches.next(), matches.next()) {
+            (Some(id), None) => Some(id.clone()),
+            _ => None,
+        }
+    }
+    
     pub fn create_conversation(&mut self, title: String) -> Result<Conversation> {
         let conversation = Conversation::new(title);
         
@@ -183,6 +324,9 @@ impl ConversationStorage {
     }
     
     pub fn save_conversation(&mut self, conversation: &Conversation) -> Result<()> {
+        // Checked first so a bad id never reaches the index
+        let path = self.get_conversation_path(&conversation.id)?;
+        
         // Update index
         self.conversations.insert(
             conversation.id.clone(),
@@ -193,19 +337,12 @@ impl ConversationStorage {
         self.save_conversation_index()?;
         
         // Save conversation
-        let path = self.get_conversation_path(&conversation.id);
         let content = serde_json::to_string_pretty(conversation).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to serialize conversation: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to serialize conversation: {}", e)))
         })?;
         
         fs::write(&path, content).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to write conversation: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to write conversation: {}", e)))
         })?;
         
         debug!("Saved conversation to {}", path.display());
@@ -220,19 +357,13 @@ impl ConversationStorage {
             )));
         }
         
-        let path = self.get_conversation_path(id);
+        let path = self.get_conversation_path(id)?;
         let content = fs::read_to_string(&path).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to read conversation: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to read conversation: {}", e)))
         })?;
         
         serde_json::from_str(&content).map_err(|e| {
-            KonaError::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to parse conversation: {}", e),
-            ))
+            KonaError::IoError(io::Error::other(format!("Failed to parse conversation: {}", e)))
         })
     }
     
@@ -244,6 +375,8 @@ impl ConversationStorage {
             )));
         }
         
+        let path = self.get_conversation_path(id)?;
+        
         // Remove from index
         self.conversations.remove(id);
         
@@ -251,13 +384,9 @@ impl ConversationStorage {
         self.save_conversation_index()?;
         
         // Delete conversation file
-        let path = self.get_conversation_path(id);
         if path.exists() {
             fs::remove_file(&path).map_err(|e| {
-                KonaError::IoError(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Failed to delete conversation: {}", e),
-                ))
+                KonaError::IoError(io::Error::other(format!("Failed to delete conversation: {}", e)))
             })?;
         }
         
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use super::storage::{Conversation, ConversationStorage};
+    use crate::history::storage::{Conversation, ConversationStorage};
     use uuid::Uuid;
     
     #[test]
@@ -30,6 +30,24 @@ mod tests {
         assert_eq!(conversation.messages[1].content, "Hi there!");
     }
     
+    #[test]
+    fn test_message_timestamps() {
+        use crate::api::Message;
+        
+        // Messages added to a conversation record when, and keep it through a save
+        let mut conversation = Conversation::new("Test".to_string());
+        conversation.add_user_message("Hello".to_string());
+        let timestamp = conversation.messages[0].timestamp.expect("user message is stamped");
+        assert_eq!(conversation.updated_at, timestamp);
+        let saved: Conversation = serde_json::from_str(&serde_json::to_string(&conversation).unwrap()).unwrap();
+        assert_eq!(saved.messages[0].timestamp, Some(timestamp));
+        
+        // Conversations saved before timestamps existed still load, and unstamped messages don't write the field
+        let old: Message = serde_json::from_str(r#"{"role": "user", "content": "Hi"}"#).unwrap();
+        assert_eq!(old.timestamp, None);
+        assert!(serde_json::to_value(&old).unwrap().get("timestamp").is_none());
+    }
+    
     #[test]
     fn test_conversation_to_summary() {
         let mut conversation = Conversation::new("Test".to_string());
@@ -44,4 +62,211 @@ mod tests {
         assert_eq!(summary.updated_at, conversation.updated_at);
         assert_eq!(summary.message_count, 2);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_conversation_last_messages() {
+        let mut conversation = Conversation::new("Test".to_string());
+        assert!(conversation.last_user_message().is_none());
+        assert!(conversation.last_assistant_message().is_none());
+        
+        conversation.add_user_message("First".to_string());
+        conversation.add_assistant_message("Reply".to_string());
+        conversation.add_user_message("Second".to_string());
+        
+        assert_eq!(conversation.last_user_message().unwrap().content, "Second");
+        assert_eq!(conversation.last_assistant_message().unwrap().content, "Reply");
+    }
+    
+    #[test]
+    fn test_conversation_pop_last_exchange() {
+        let mut conversation = Conversation::new("Test".to_string());
+        conversation.add_user_message("Hello".to_string());
+        conversation.add_assistant_message("Hi there!".to_string());
+        conversation.add_user_message("How are you?".to_string());
+        conversation.add_assistant_message("Fine.".to_string());
+        
+        let (user, assistant) = conversation.pop_last_exchange().unwrap();
+        assert_eq!(user.content, "How are you?");
+        assert_eq!(assistant.content, "Fine.");
+        assert_eq!(conversation.messages.len(), 2);
+        
+        // A dangling user message is not a complete exchange
+        conversation.add_user_message("Unanswered".to_string());
+        assert!(conversation.pop_last_exchange().is_none());
+        assert_eq!(conversation.messages.len(), 3);
+    }
+    
+    #[test]
+    fn test_conversation_truncate_to() {
+        let mut conversation = Conversation::new("Test".to_string());
+        conversation.add_user_message("Hello".to_string());
+        conversation.add_assistant_message("Hi there!".to_string());
+        conversation.add_user_message("Bye".to_string());
+        
+        conversation.truncate_to(10);
+        assert_eq!(conversation.messages.len(), 3);
+        
+        conversation.truncate_to(1);
+        assert_eq!(conversation.messages.len(), 1);
+        assert_eq!(conversation.messages[0].content, "Hello");
+    }
+    
+    #[test]
+    fn test_conversation_recent_messages() {
+        let mut conversation = Conversation::new("Test".to_string());
+        conversation.add_user_message("One".to_string());
+        conversation.add_assistant_message("Reply one".to_string());
+        conversation.add_user_message("Two".to_string());
+        conversation.add_assistant_message("Reply two".to_string());
+        conversation.add_user_message("Three".to_string());
+        
+        // Everything fits
+        assert_eq!(conversation.recent_messages(10).len(), 5);
+        
+        // Oldest turns are dropped
+        let recent = conversation.recent_messages(3);
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0].content, "Two");
+        
+        // The window never starts on an assistant reply
+        let recent = conversation.recent_messages(4);
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0].role, "user");
+    }
+    
+    #[test]
+    fn test_title_from_message() {
+        assert_eq!(
+            Conversation::title_from_message("How do lifetimes work?\nSome more detail"),
+            Some("How do lifetimes work?".to_string())
+        );
+        assert_eq!(Conversation::title_from_message("  \n  "), None);
+        
+        let title = Conversation::title_from_message(&"word ".repeat(30)).unwrap();
+        assert!(title.chars().count() <= 50);
+        assert!(title.ends_with("..."));
+    }
+    
+    #[test]
+    fn test_conversation_regenerate_context() {
+        let mut conversation = Conversation::new("Test".to_string());
+        assert!(conversation.regenerate_context(10).is_none());
+        
+        conversation.add_user_message("Question".to_string());
+        conversation.add_assistant_message_with_model("First answer".to_string(), "anthropic/claude-3-haiku");
+        
+        // The existing answer is left out so the question can be asked again
+        let context = conversation.regenerate_context(10).unwrap();
+        assert_eq!(context.len(), 1);
+        assert_eq!(context[0].content, "Question");
+        
+        conversation.add_assistant_message_with_model("Second answer".to_string(), "anthropic/claude-3.5-sonnet");
+        assert_eq!(conversation.messages[1].model.as_deref(), Some("anthropic/claude-3-haiku"));
+        assert_eq!(conversation.messages[2].model.as_deref(), Some("anthropic/claude-3.5-sonnet"));
+        assert_eq!(conversation.regenerate_context(10).unwrap().len(), 1);
+        
+        // Both answers are kept, but only the newer one is sent with the next question
+        conversation.add_user_message("Follow-up".to_string());
+        let context = conversation.recent_messages(10);
+        assert_eq!(context.len(), 3);
+        assert_eq!(context[1].content, "Second answer");
+        assert_eq!(context[2].content, "Follow-up");
+        assert_eq!(conversation.messages.len(), 4);
+    }
+    
+    #[tokio::test]
+    async fn test_streamed_response_is_recorded() {
+        use crate::history::storage::INCOMPLETE_MARKER;
+        use crate::api::mock::MockOpenRouterClient;
+        use crate::config::Config;
+        use futures::StreamExt;
+        use std::time::Duration;
+        
+        let original = "Streaming keeps  every\nbit of whitespace.";
+        let mut client = MockOpenRouterClient::new(Config::default(), original.to_string());
+        client.chunk_delay = Duration::ZERO;
+        
+        // Word-by-word chunks reassemble into the original text
+        let mut stream = client.send_message_streaming("ignored").await.unwrap();
+        let mut full_response = String::new();
+        while let Some(chunk) = stream.next().await {
+            full_response.push_str(&chunk.unwrap());
+        }
+        
+        let mut conversation = Conversation::new("Test".to_string());
+        conversation.add_user_message("Question".to_string());
+        assert!(conversation.add_streamed_assistant_message(full_response, "anthropic/claude-3-haiku", true));
+        assert_eq!(conversation.last_assistant_message().unwrap().content, original);
+        
+        // An interrupted stream keeps what arrived, marked as incomplete
+        let mut stream = client.send_message_streaming("ignored").await.unwrap();
+        let partial = stream.next().await.unwrap().unwrap();
+        stream.abort();
+        assert!(conversation.add_streamed_assistant_message(partial, "anthropic/claude-3-haiku", false));
+        assert_eq!(
+            conversation.last_assistant_message().unwrap().content,
+            format!("Streaming\n\n{}", INCOMPLETE_MARKER)
+        );
+        
+        // Nothing streamed means nothing recorded
+        assert!(!conversation.add_streamed_assistant_message(String::new(), "anthropic/claude-3-haiku", false));
+        assert_eq!(conversation.messages.len(), 3);
+    }
+    
+    #[test]
+    fn test_storage_in_custom_dir() {
+        let dir = std::env::temp_dir().join(format!("kona_storage_test_{}", Uuid::new_v4())).join("nested");
+        
+        // Missing directories are created
+        let mut storage = ConversationStorage::with_dir(dir.clone()).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(storage.latest_id(), None);
+        
+        let mut conversation = storage.create_conversation("Saved".to_string()).unwrap();
+        conversation.add_user_message("Hello".to_string());
+        storage.save_conversation(&conversation).unwrap();
+        assert!(dir.join(format!("{}.json", conversation.id)).exists());
+        
+        // A fresh storage on the same directory sees the conversation
+        let reopened = ConversationStorage::with_dir(dir.clone()).unwrap();
+        assert_eq!(reopened.load_conversation(&conversation.id).unwrap().messages.len(), 1);
+        
+        // Ids can be shortened as long as the prefix is unambiguous
+        assert_eq!(reopened.resolve_id(&conversation.id), Some(conversation.id.clone()));
+        assert_eq!(reopened.resolve_id(&conversation.id[..8]), Some(conversation.id.clone()));
+        assert_eq!(reopened.resolve_id("not-an-id"), None);
+        assert_eq!(reopened.resolve_id(""), None);
+        
+        // The most recently updated conversation is the one `--continue` picks up
+        let mut other = storage.create_conversation("Other".to_string()).unwrap();
+        other.add_user_message("Hi".to_string());
+        storage.save_conversation(&other).unwrap();
+        assert_eq!(storage.latest_id(), Some(other.id.clone()));
+        conversation.add_assistant_message("Hello again".to_string());
+        storage.save_conversation(&conversation).unwrap();
+        assert_eq!(storage.latest_id(), Some(conversation.id.clone()));
+        
+        std::fs::remove_dir_all(dir.parent().unwrap()).ok();
+    }
+    
+    #[test]
+    fn test_conversation_path_rejects_traversal() {
+        let dir = std::env::temp_dir().join(format!("kona_storage_test_{}", Uuid::new_v4()));
+        let mut storage = ConversationStorage::with_dir(dir.clone()).unwrap();
+        
+        let id = Uuid::new_v4().to_string();
+        assert_eq!(storage.get_conversation_path(&id).unwrap(), dir.join(format!("{}.json", id)));
+        for bad in ["", ".", "..", "../escape", "../../.ssh/authorized_keys", "/tmp/abs", "a\\b"] {
+            assert!(storage.get_conversation_path(bad).is_err(), "accepted {:?}", bad);
+        }
+        
+        // A hostile id is refused before anything is written or indexed
+        let mut conversation = Conversation::new("Hostile".to_string());
+        conversation.id = "../escape".to_string();
+        assert!(storage.save_conversation(&conversation).is_err());
+        assert!(!dir.parent().unwrap().join("escape.json").exists());
+        assert_eq!(storage.latest_id(), None);
+        
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,8 @@
+// Library target for the `kona` binary, so benchmarks can link against the client and stream types
+pub mod cli;
+pub mod api;
+pub mod config;
+pub mod utils;
+pub mod history;
+#[cfg(feature = "serve")]
+pub mod server;
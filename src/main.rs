@@ -1,46 +1,168 @@
 use clap::Parser;
+use colored::Colorize;
 use dotenv::dotenv;
-use tracing::{error, info, Level};
+use std::fs::{self, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
-mod cli;
-mod api;
-mod config;
-mod utils;
-mod history;
+use kona::{api, cli, config, history, utils};
+#[cfg(feature = "serve")]
+use kona::server;
 
-use api::OpenRouterClient;
-use utils::mask_api_key;
+use api::cache::ResponseCache;
+use api::models::supports_vision;
+use api::schema;
+use api::{truncation_notice, JsonStreamEvent, OpenRouterClient, StreamEvent};
+use utils::flush::FlushBuffer;
+use utils::logging;
+use utils::spinner::Spinner;
+use utils::stats::ResponseTimer;
+use utils::tokens::oversized_input_warning;
+use utils::{confirm, mask_api_key};
 use cli::basic;
-use cli::cli::{Cli, Commands};
+use cli::cli::{CacheCommands, Cli, Commands, ConfigCommands, OutputFormat};
+use cli::conversations;
+use cli::credits;
+use cli::doctor;
+use cli::files;
+use cli::highlight::{highlight_code_blocks, highlighting_enabled, CodeHighlighter};
+use cli::images;
 use cli::mac;
-// use cli::interactive; // Old implementation
+use cli::pipe;
+use cli::interactive;
 // use cli::simple; // Had issues with text_io
 use cli::tui;
-// Will be used later
-// use history::storage::ConversationStorage;
+use cli::validate;
+use history::storage::{Conversation, ConversationStorage};
 use config::Config;
 
-fn setup_logging(verbosity: u8) {
-    // Force debug level during development
-    let verbosity = std::cmp::max(verbosity, 3);
+// Appended to the question in `ask --json` mode
+const JSON_MODE_INSTRUCTION: &str = "Respond with only valid JSON, with no commentary or code fences.";
 
-    let level = match verbosity {
-        0 => Level::ERROR,
-        1 => Level::WARN,
-        2 => Level::INFO,
-        3 => Level::DEBUG,
-        _ => Level::TRACE,
+// How many times `ask --json` re-asks the model after an invalid reply
+const JSON_CORRECTION_ATTEMPTS: usize = 1;
+
+fn setup_logging(verbosity: u8, plain_output: bool, quiet: bool) {
+    // Warnings by default, each -v one level more; logs go to stderr so stdout only carries answers
+    let level = match (quiet, verbosity) {
+        (true, _) => Level::ERROR,
+        (false, 0) => Level::WARN,
+        (false, 1) => Level::INFO,
+        (false, 2) => Level::DEBUG,
+        (false, _) => Level::TRACE,
     };
 
+    // Route through a switchable writer so the TUI can divert logs to a file
     let subscriber = FmtSubscriber::builder()
         .with_max_level(level)
+        .with_writer(logging::log_writer)
+        .with_ansi(!plain_output)
         .finish();
 
     tracing::subscriber::set_global_default(subscriber)
         .expect("Failed to set tracing subscriber");
 }
 
+// Persist a single question/answer pair as a new conversation, returning its id
+fn save_exchange(title: String, query: api::MessageContent, response: String) -> utils::error::Result<String> {
+    let mut storage = ConversationStorage::new()?;
+    let mut conversation = storage.create_conversation(title)?;
+    conversation.add_user_message(query);
+    conversation.add_assistant_message(response);
+    storage.save_conversation(&conversation)?;
+    Ok(conversation.id)
+}
+
+// The most recently updated conversation for `--continue`, or None (with a note) if nothing is saved yet
+fn latest_conversation() -> Option<(ConversationStorage, Conversation)> {
+    let loaded = ConversationStorage::new().and_then(|storage| match storage.latest_id() {
+        Some(id) => storage.load_conversation(&id).map(|conversation| Some((storage, conversation))),
+        None => Ok(None),
+    });
+    match loaded {
+        Ok(Some(latest)) => Some(latest),
+        Ok(None) => {
+            eprintln!("No saved conversations to continue, starting a new one");
+            None
+        }
+        Err(err) => {
+            error!("Failed to load the latest conversation: {}", err);
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Open the response cache and compute the key for this message; failures just disable caching
+fn response_cache(client: &OpenRouterClient, message: api::Message) -> Option<(ResponseCache, String)> {
+    let cache = ResponseCache::new(Duration::from_secs(client.config.cache_ttl_secs))
+        .map_err(|err| warn!("Response cache unavailable: {}", err))
+        .ok()?;
+    let key = client
+        .cache_key(message)
+        .map_err(|err| warn!("Could not compute cache key: {}", err))
+        .ok()?;
+    Some((cache, key))
+}
+
+// Write a response to `path`, creating parent directories as needed
+fn write_response_file(path: &Path, response: &str, append: bool) -> utils::error::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    file.write_all(response.as_bytes())?;
+    if !response.ends_with('\n') {
+        file.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// Handle `kona config reset`, exiting on failure
+fn reset_config(yes: bool, hard: bool) {
+    if !yes {
+        let prompt = if hard {
+            "Reset the config file to defaults, including the API key?"
+        } else {
+            "Reset the config file to defaults (keeping the API key)?"
+        };
+        match confirm(prompt) {
+            Ok(true) => {},
+            Ok(false) => {
+                println!("Aborted.");
+                return;
+            },
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match Config::reset_config_file(!hard) {
+        Ok((path, backup)) => {
+            if let Some(backup) = backup {
+                println!("Backed up previous config to: {:?}", backup);
+            }
+            println!("Reset config file at: {:?}", path);
+        },
+        Err(err) => {
+            error!("Failed to reset config file: {}", err);
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     // Load environment variables from .env file if present
@@ -50,15 +172,61 @@ async fn main() {
     };
 
     // Parse command line arguments
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
 
     // Setup logging based on verbosity flag
-    setup_logging(cli.verbose);
+    // Log files shouldn't contain color escape codes
+    setup_logging(cli.verbose, cli.tui_log.is_some(), cli.quiet);
 
     info!("Starting Kona v{}", env!("CARGO_PKG_VERSION"));
 
+    // Every config lookup below, including init and reset, goes to the explicit file
+    if let Some(path) = &cli.config_path {
+        Config::set_config_path(path.clone());
+    }
+    if cli.no_project_config {
+        Config::disable_project_config();
+    }
+
+    // Resetting must work even when the current config can't be loaded
+    if let Some(Commands::Config { action: Some(ConfigCommands::Reset { yes, hard }), .. }) = &cli.command {
+        reset_config(*yes, *hard);
+        return;
+    }
+
+    // Validation reports configuration errors itself instead of stopping at the first one
+    if let Some(Commands::Validate) = &cli.command {
+        if !validate::run_validation(cli.profile.as_deref()).await {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Like validation, the doctor has to work when the configuration doesn't load
+    if let Some(Commands::Doctor) = &cli.command {
+        if !doctor::run_doctor(cli.profile.as_deref()).await {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // The cache lives outside the config, so clearing it needs no API key
+    if let Some(Commands::Cache { action: CacheCommands::Clear }) = &cli.command {
+        let result = ResponseCache::get_cache_dir().and_then(|dir| ResponseCache::with_dir(dir, Duration::ZERO).clear());
+        match result {
+            Ok(count) => println!("Removed {} cached response(s)", count),
+            Err(err) => {
+                error!("Failed to clear the response cache: {}", err);
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     // Load configuration
-    let mut config = match Config::new() {
+    // `--profile` wins over KONA_PROFILE, which Config falls back to
+    let mut config = match Config::with_profile(cli.profile.as_deref()) {
         Ok(config) => config,
         Err(err) => {
             error!("Failed to load configuration: {}", err);
@@ -67,13 +235,23 @@ async fn main() {
         }
     };
 
-    // Display API key if in debug mode
+    // Conversations are opened in many places, so the location is fixed up front
+    if let Some(dir) = &config.storage_dir {
+        ConversationStorage::set_storage_dir(config::config::expand_home(dir));
+    }
+
+    // Display API key if in debug mode, on stderr so JSON output stays parseable
     if cli.debug {
-        println!("Debug mode enabled");
-        println!("API Key: {}", config.api_key);
-        println!("API Key length: {}", config.api_key.len());
-        println!("API Key (masked): {}", mask_api_key(&config.api_key));
-        println!("Model: {}", config.model);
+        eprintln!("Debug mode enabled");
+        // The raw key ends up in scrollback and screenshots, so it takes an explicit opt-in
+        if cli.show_secrets {
+            eprintln!("{}", "WARNING: printing secrets; clear your scrollback and don't share this output".red().bold());
+            eprintln!("API Key: {}", config.api_key);
+        } else {
+            eprintln!("API Key (masked): {}", mask_api_key(&config.api_key));
+        }
+        eprintln!("API Key length: {}", config.api_key.len());
+        eprintln!("Model: {}", config.model);
     } else {
         // Always show a masked version in normal logging
         info!("Using API Key: {}", mask_api_key(&config.api_key));
@@ -91,6 +269,65 @@ async fn main() {
         info!("Streaming disabled via command line flag");
     }
 
+    // Per-run model override; the client adds the provider prefix when it's missing
+    if let Some(model) = cli.model.take() {
+        info!("Model overridden via command line flag: {}", model);
+        config.model = model;
+    }
+
+    if let Some(max_tokens) = cli.max_tokens {
+        info!("Max tokens overridden via command line flag: {}", max_tokens);
+        config.max_tokens = max_tokens;
+    }
+
+    if let Some(temperature) = cli.temperature {
+        info!("Temperature overridden via command line flag: {}", temperature);
+        config.temperature = temperature;
+    }
+
+    config.dry_run = cli.dry_run;
+    config.raw_response = cli.raw_response;
+    config.verbose_stream = cli.verbose_stream;
+    config.no_color = cli.no_color;
+    if cli.stats {
+        config.show_stats = true;
+    }
+    if cli.no_color {
+        colored::control::set_override(false);
+    }
+
+    // Per-run system prompt overrides
+    if cli.no_system {
+        config.system_prompt = None;
+        info!("System prompt disabled via command line flag");
+    } else if let Some(system) = cli.system.take() {
+        config.system_prompt = Some(system);
+        info!("System prompt overridden via command line flag");
+    }
+
+    // `ask --stop` replaces the configured stop sequences for this question
+    if let Some(Commands::Ask { stop, schema, .. }) = &cli.command {
+        if !stop.is_empty() {
+            config.stop_sequences = stop.clone();
+            if let Err(err) = config.validate_sampling() {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+
+        // `ask --schema` asks the API for structured output in that shape
+        if let Some(path) = schema {
+            match schema::load_schema(path) {
+                Ok(json_schema) => config.response_schema = Some(json_schema),
+                Err(err) => {
+                    error!("Failed to load schema: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
     // Create API client
     // Clone the config for the client
     let config_for_client = config.clone();
@@ -104,36 +341,373 @@ async fn main() {
         }
     };
 
+    // Bare names other than claude-* are sent as typed, which OpenRouter usually rejects
+    if let Some(warning) = api::client::model_name_warning(&config.model) {
+        eprintln!("Warning: {}", warning);
+    }
+
+    // A limit beyond the context window can't be honoured, but the request may still succeed
+    if let Some(max_tokens) = cli.max_tokens
+        && let Some(context_length) = client.context_length(&config.model).await
+        && max_tokens > context_length
+    {
+        eprintln!(
+            "Warning: --max-tokens {} exceeds the {} token context length of {}",
+            max_tokens, context_length, config.model
+        );
+    }
+
     // Process commands
     match cli.command {
-        Some(Commands::Ask { query }) => {
-            println!("Asking Claude: {}", query);
+        Some(Commands::Ask { query, save_as, json, stream_json, output, append, files, images: image_paths, interactive, no_cache, force_cache, count, stop: _, schema: _ }) => {
+            // Only the question itself is echoed, not the context added to it below
+            let question = query.clone();
+
+            // Piped input becomes context for the question, e.g. `git diff | kona ask "review this"`
+            let query = match pipe::read_piped_stdin() {
+                // Follow-ups are read from stdin, which the pipe has already used up
+                Ok(Some(_)) if interactive => {
+                    eprintln!("Error: --interactive needs a terminal on stdin and can't be used with piped input");
+                    std::process::exit(1);
+                }
+                Ok(Some(input)) => pipe::piped_prompt(Some(&query), &input),
+                Ok(None) => query,
+                Err(err) => {
+                    error!("Failed to read stdin: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+
+            // Files go ahead of the question, e.g. `kona ask --file main.rs --file lib.rs "find the bug"`
+            let query = if files.is_empty() {
+                query
+            } else {
+                match files::read_context_files(&files, config.max_file_context_bytes) {
+                    Ok((context, warnings)) => {
+                        for warning in warnings {
+                            eprintln!("Warning: {}", warning);
+                        }
+                        format!("{}\n\n{}", context, query)
+                    }
+                    Err(err) => {
+                        error!("Failed to read context files: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            };
+
+            // Images are sent as content blocks after the question, e.g. `kona ask --image shot.png "what's wrong here?"`
+            let image_blocks = match images::read_images(&image_paths) {
+                Ok(blocks) => blocks,
+                Err(err) => {
+                    error!("Failed to read images: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+            if !image_blocks.is_empty() && !supports_vision(&config.model) {
+                eprintln!(
+                    "Warning: {} isn't known to accept images; the request may fail (try --model anthropic/claude-3.5-sonnet)",
+                    config.model
+                );
+            }
+
+            // `kona -c ask "..."` sends the question as one more turn of the latest conversation
+            let continued = if cli.continue_last { latest_conversation() } else { None };
+            let earlier = continued
+                .as_ref()
+                .map(|(_, conversation)| conversation.recent_messages(config.history_size.saturating_sub(1)))
+                .unwrap_or_default();
+            let with_earlier = |message: api::Message| {
+                let mut messages = earlier.clone();
+                messages.push(message);
+                messages
+            };
+
+            if config.dry_run {
+                let content = if json { format!("{}\n\n{}", query, JSON_MODE_INSTRUCTION) } else { query };
+                let messages = with_earlier(api::Message::new("user", images::with_images(content, &image_blocks)));
+                match client.dry_run_request(messages) {
+                    Ok(request) => println!("{}", request),
+                    Err(err) => {
+                        error!("Failed to build request: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            // There's nobody to confirm an oversized prompt, so refuse it rather than risk a surprise bill
+            if let Some(warning) = oversized_input_warning(&query, config.max_input_chars) {
+                eprintln!("Error: {}; raise max_input_chars (or KONA_MAX_INPUT_CHARS) to send it", warning);
+                std::process::exit(1);
+            }
+
+            // Machine-readable and quiet output keep stdout free of anything but the answer, as does
+            // output going to a pipe or file
+            let quiet = cli.quiet;
+            let machine_output = stream_json || json || config.response_schema.is_some();
+            if !machine_output && !quiet && std::io::stdout().is_terminal() {
+                println!("Asking {}: {}", config.assistant_label(), question);
+                if !files.is_empty() {
+                    let names: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+                    println!("Attached: {}", names.join(", "));
+                }
+            }
+
+            // Timing footers go to stderr so they never end up in captured answers
+            let show_stats = config.show_stats && !quiet;
+
+            // Sampled answers vary between runs, so they are only cached when forced; cache keys
+            // only cover the question, so continued conversations are never cached
+            let use_cache = !json
+                && !no_cache
+                && continued.is_none()
+                && config.response_schema.is_none()
+                && (force_cache || (config.cache_enabled && config.temperature == 0.0));
+            let user_message = api::Message::new("user", images::with_images(query.clone(), &image_blocks));
+            let messages = with_earlier(user_message.clone());
+
+            // Several answers to choose from, e.g. `kona ask --count 3 "names for a CLI tool"`
+            if count > 1 {
+                let mut spinner = (!quiet).then(|| Spinner::start("Waiting for responses..."));
+                let result = client.send_message_completions(messages, count).await;
+                if let Some(spinner) = spinner.as_mut() {
+                    spinner.stop();
+                }
+
+                let (completions, usage) = match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        error!("API call failed: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                };
+
+                let numbered = completions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, completion)| format!("[{}]\n{}", i + 1, completion.trim_end()))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                if quiet {
+                    println!("{}", numbered);
+                } else {
+                    println!("\n{}:\n{}\n", config.assistant_label(), numbered);
+                    println!(
+                        "Tokens: {} prompt + {} completion = {} total across {} answers",
+                        usage.prompt_tokens, usage.completion_tokens, usage.total_tokens, completions.len()
+                    );
+                    if usage.cached_tokens() > 0 {
+                        println!("Cached prompt tokens: {}", usage.cached_tokens());
+                    }
+                }
+
+                if let Some(path) = output
+                    && let Err(err) = write_response_file(&path, &numbered, append)
+                {
+                    error!("Failed to write response to {:?}: {}", path, err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+
+                // A continued conversation records the answers as one reply, numbered as printed
+                if let Some((mut storage, mut conversation)) = continued {
+                    conversation.add_user_message(user_message.content);
+                    conversation.add_assistant_message_with_model(numbered, &config.model);
+                    if let Err(err) = storage.save_conversation(&conversation) {
+                        error!("Failed to continue conversation: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            let cache = if use_cache { response_cache(&client, user_message.clone()) } else { None };
+            let cached = cache.as_ref().and_then(|(cache, key)| cache.get(key));
+            let cache_hit = cached.is_some();
 
-            // Use streaming if enabled in config
-            if config.use_streaming {
+            // JSON mode always streams so progress stays visible while the reply is validated
+            let response = if let Some(response) = cached {
+                if stream_json {
+                    println!("{}", serde_json::json!({ "delta": response }));
+                    println!("{}", serde_json::json!({ "done": true, "usage": null, "cached": true }));
+                } else if quiet {
+                    println!("{}", response);
+                } else {
+                    let highlighted = highlight_code_blocks(&response, highlighting_enabled(config.no_color));
+                    println!("\n{} (cached):\n{}\n", config.assistant_label(), highlighted);
+                }
+                response
+            } else if let Some(json_schema) = &config.response_schema {
+                // Structured output is checked against the schema before anything is printed
+                let mut spinner = (!quiet).then(|| Spinner::start("Waiting for response..."));
+                let result = client.send_message_with_history(messages).await;
+                if let Some(spinner) = spinner.as_mut() {
+                    spinner.stop();
+                }
+
+                let response = match result {
+                    Ok(response) => response,
+                    Err(err) => {
+                        error!("API call failed: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                };
+                match schema::check_response(&response, json_schema) {
+                    Ok(value) => {
+                        let pretty = serde_json::to_string_pretty(&value).unwrap_or_default();
+                        println!("{}", pretty);
+                        pretty
+                    }
+                    Err(err) => {
+                        println!("{}", response);
+                        eprintln!("Error: response does not match the schema: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            } else if json {
+                use std::io::{self, Write};
+
+                let content = format!("{}\n\n{}", query, JSON_MODE_INSTRUCTION);
+                let messages = with_earlier(api::Message::new("user", images::with_images(content, &image_blocks)));
+
+                if !quiet {
+                    println!("\n{}:", config.assistant_label());
+                }
+                let mut flusher = FlushBuffer::new(config.stream_flush_chars);
+                let result = client
+                    .send_message_streaming_json(messages, JSON_CORRECTION_ATTEMPTS, |event| match event {
+                        JsonStreamEvent::Chunk(chunk) => {
+                            if let Some(text) = flusher.push(chunk) {
+                                print!("{}", text);
+                                io::stdout().flush().ok();
+                            }
+                        }
+                        JsonStreamEvent::Validating => {
+                            print!("{}", flusher.finish().unwrap_or_default());
+                            eprintln!("\nvalidating JSON…");
+                        }
+                        JsonStreamEvent::Retrying(attempt) => {
+                            eprintln!("Invalid JSON, asking for a correction (attempt {})", attempt);
+                        }
+                    })
+                    .await;
+
+                match result {
+                    Ok(value) => {
+                        if quiet {
+                            println!();
+                        }
+                        match client.last_request_id() {
+                            Some(id) => eprintln!("Response is valid JSON (request id: {})", id),
+                            None => eprintln!("Response is valid JSON"),
+                        }
+                        serde_json::to_string_pretty(&value).unwrap_or_default()
+                    }
+                    Err(err) => {
+                        error!("JSON response failed: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            } else if stream_json {
                 use futures::StreamExt;
                 use std::io::{self, Write};
 
-                match client.send_message_streaming(&query).await {
+                match client.send_message_streaming_with_history(messages).await {
                     Ok(mut stream) => {
-                        println!("\nClaude:");
+                        let mut full_response = String::new();
+                        let mut stdout = io::stdout();
 
-                        // Process the stream
+                        // Chunks are complete strings, so each one serializes to valid JSON
                         while let Some(chunk_result) = stream.next().await {
                             match chunk_result {
                                 Ok(chunk) => {
-                                    print!("{}", chunk);
-                                    io::stdout().flush().ok(); // Ensure text appears immediately
+                                    writeln!(stdout, "{}", serde_json::json!({ "delta": chunk })).ok();
+                                    stdout.flush().ok();
+                                    full_response.push_str(&chunk);
                                 }
                                 Err(err) => {
                                     error!("Stream error: {}", err);
-                                    eprintln!("\nError: {}", err);
+                                    writeln!(stdout, "{}", serde_json::json!({ "error": err.to_string() })).ok();
                                     std::process::exit(1);
                                 }
                             }
                         }
 
-                        println!("\n"); // Add newline after response
+                        writeln!(
+                            stdout,
+                            "{}",
+                            serde_json::json!({ "done": true, "usage": stream.usage(), "finish_reason": stream.finish_reason() })
+                        )
+                        .ok();
+                        full_response
+                    }
+                    Err(err) => {
+                        error!("API call failed: {}", err);
+                        println!("{}", serde_json::json!({ "error": err.to_string() }));
+                        std::process::exit(1);
+                    }
+                }
+            } else if config.use_streaming {
+                use std::io::{self, Write};
+
+                let mut timer = ResponseTimer::start();
+                match client.send_message_streaming_with_history(messages).await {
+                    Ok(mut stream) => {
+                        if !quiet {
+                            println!("\n{}:", config.assistant_label());
+                        }
+                        let mut highlighter = CodeHighlighter::new(highlighting_enabled(config.no_color));
+                        let mut flusher = FlushBuffer::new(config.stream_flush_chars);
+
+                        // Process the stream
+                        let result = stream
+                            .for_each_event(|event| match event {
+                                StreamEvent::Text(chunk) => {
+                                    timer.chunk();
+                                    if let Some(text) = flusher.push(&highlighter.push(chunk)) {
+                                        print!("{}", text);
+                                        io::stdout().flush().ok();
+                                    }
+                                }
+                                extra if config.verbose_stream => interactive::print_stream_extra(extra),
+                                _ => {}
+                            })
+                            .await;
+                        let full_response = match result {
+                            Ok(full_response) => full_response,
+                            Err(err) => {
+                                print!("{}", flusher.finish().unwrap_or_default());
+                                error!("Stream error: {}", err);
+                                eprintln!("\nError: {}", err);
+                                std::process::exit(1);
+                            }
+                        };
+
+                        print!("{}{}", flusher.finish().unwrap_or_default(), highlighter.finish());
+
+                        // Add newline after response
+                        if quiet {
+                            println!();
+                        } else {
+                            println!("\n");
+                        }
+                        if let Some(notice) = truncation_notice(stream.finish_reason().as_deref()) {
+                            eprintln!("{}", notice);
+                        }
+                        if show_stats {
+                            let tokens = stream.usage().map(|usage| usage.completion_tokens).unwrap_or(0);
+                            eprintln!("{}", format!("({})", timer.footer(tokens, &full_response)).dimmed());
+                        }
+                        full_response
                     }
                     Err(err) => {
                         error!("API call failed: {}", err);
@@ -143,9 +717,28 @@ async fn main() {
                 }
             } else {
                 // Use non-streaming API
-                match client.send_message(&query).await {
+                let mut spinner = (!quiet).then(|| Spinner::start("Waiting for response..."));
+                let timer = ResponseTimer::start();
+                let result = client.send_message_with_history(messages).await;
+                if let Some(spinner) = spinner.as_mut() {
+                    spinner.stop();
+                }
+
+                match result {
                     Ok(response) => {
-                        println!("\nClaude: {}", response);
+                        if quiet {
+                            println!("{}", response);
+                        } else {
+                            let highlighted = highlight_code_blocks(&response, highlighting_enabled(config.no_color));
+                            println!("\n{}: {}", config.assistant_label(), highlighted);
+                        }
+                        if let Some(notice) = truncation_notice(client.last_finish_reason().as_deref()) {
+                            eprintln!("{}", notice);
+                        }
+                        if show_stats {
+                            eprintln!("{}", format!("({})", timer.footer(0, &response)).dimmed());
+                        }
+                        response
                     }
                     Err(err) => {
                         error!("API call failed: {}", err);
@@ -153,9 +746,77 @@ async fn main() {
                         std::process::exit(1);
                     }
                 }
+            };
+
+            // Store fresh answers for the next identical request
+            if let Some((cache, key)) = cache.as_ref().filter(|_| !cache_hit && !response.is_empty())
+                && let Err(err) = cache.put(key, &config.model, &response)
+            {
+                warn!("Failed to cache response: {}", err);
+            }
+
+            // Write the complete response once it has finished streaming
+            if let Some(path) = output {
+                if let Err(err) = write_response_file(&path, &response, append) {
+                    error!("Failed to write response to {:?}: {}", path, err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+                info!("Wrote response to {:?}", path);
+            }
+
+            // A continued conversation gets the exchange appended; --save-as renames it
+            if let Some((mut storage, mut conversation)) = continued {
+                conversation.add_user_message(user_message.content);
+                conversation.add_assistant_message_with_model(response, &config.model);
+                if let Some(title) = save_as {
+                    conversation.rename(title);
+                }
+
+                let result = if interactive {
+                    interactive::continue_conversation(client, conversation).await
+                } else {
+                    storage.save_conversation(&conversation)
+                };
+                if let Err(err) = result {
+                    error!("Failed to continue conversation: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+                return;
+            }
+
+            // Follow-ups continue from this exchange; the session saves it, so --save-as only names it
+            if interactive {
+                let title = save_as
+                    .or_else(|| Conversation::title_from_message(&query))
+                    .unwrap_or_else(|| "Ask follow-up".to_string());
+                let mut conversation = Conversation::new(title);
+                conversation.add_user_message(user_message.content);
+                conversation.add_assistant_message_with_model(response, &config.model);
+
+                if let Err(err) = interactive::continue_conversation(client, conversation).await {
+                    error!("Interactive mode error: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+                return;
+            }
+
+            // Archive the exchange as a new conversation if requested
+            if let Some(title) = save_as {
+                match save_exchange(title, user_message.content, response) {
+                    Ok(id) if quiet => eprintln!("Saved conversation: {}", id),
+                    Ok(id) => println!("Saved conversation: {}", id),
+                    Err(err) => {
+                        error!("Failed to save conversation: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
             }
         },
-        Some(Commands::Init { force }) => {
+        Some(Commands::Init { force, format }) => {
             // Handle initialization without creating the API client
             match Config::get_config_path() {
                 Some(path) => {
@@ -165,7 +826,7 @@ async fn main() {
                         return;
                     }
 
-                    match Config::create_default_config_file() {
+                    match Config::create_default_config_file(format) {
                         Ok(path) => {
                             println!("Created default config file at: {:?}", path);
                             println!("Please edit this file to add your API key and other settings");
@@ -184,18 +845,35 @@ async fn main() {
                 }
             }
         },
-        Some(Commands::Config) => {
+        Some(Commands::Config { action: Some(ConfigCommands::Reset { .. }), .. }) => {
+            // Handled before the configuration is loaded
+        },
+        Some(Commands::Config { action: None, format: OutputFormat::Json }) => {
+            match config.to_masked_json() {
+                Ok(value) => println!("{}", serde_json::to_string_pretty(&value).unwrap_or_default()),
+                Err(err) => {
+                    error!("Failed to serialize configuration: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Some(Commands::Config { action: None, .. }) => {
             // Show current configuration
             println!("Current configuration:");
+            println!("Profile: {}", config.profile.as_deref().unwrap_or("(default)"));
             println!("API Key: {}", mask_api_key(&config.api_key));
             println!("Model: {}", config.model);
             println!("Max Tokens: {}", config.max_tokens);
             println!("System Prompt: {:?}", config.system_prompt);
             println!("History Size: {}", config.history_size);
             println!("Streaming: {}", if config.use_streaming { "enabled" } else { "disabled" });
+            println!("Assistant Name: {}", config.assistant_label());
+            println!("Request Timeout: {}s", config.request_timeout_secs);
+            println!("Stream Idle Timeout: {}s", config.stream_idle_timeout_secs);
 
             // Show config file location
-            if let Some(path) = Config::get_config_path() {
+            if let Some(path) = config.config_file_path() {
                 println!("\nConfig file location: {:?}", path);
                 if path.exists() {
                     println!("Config file exists: Yes");
@@ -205,8 +883,72 @@ async fn main() {
             } else {
                 println!("\nConfig file location: Could not determine");
             }
+            if let Some(path) = &config.project_config {
+                println!("Project config: {:?}", path);
+            }
+        },
+        #[cfg(feature = "serve")]
+        Some(Commands::Serve { port, host }) => {
+            if let Err(err) = server::run_server(client, host, port).await {
+                error!("Server failed: {}", err);
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Conversations { action }) => {
+            if let Err(err) = conversations::handle_conversations_command(action, &config) {
+                error!("Conversation command failed: {}", err);
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Import { path }) => {
+            if let Err(err) = conversations::import_conversations(&path) {
+                error!("Import failed: {}", err);
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Validate) | Some(Commands::Doctor) => {
+            // Handled before the configuration is loaded
+        },
+        Some(Commands::Credits) => {
+            if let Err(err) = credits::show_credits(&client).await {
+                error!("Failed to fetch account details: {}", err);
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Cache { .. }) => {
+            // Handled before the configuration is loaded
+        },
+        Some(Commands::Search { query, regex }) => {
+            if let Err(err) = conversations::search_conversations(&query, regex) {
+                error!("Search failed: {}", err);
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
         },
         None => {
+            // With redirected stdin, answer it like `ask` and print nothing but the answer
+            match pipe::read_piped_stdin() {
+                Ok(Some(input)) => {
+                    info!("Reading prompt from stdin");
+                    if let Err(err) = pipe::run_pipe_mode(&client, &pipe::piped_prompt(None, &input)).await {
+                        error!("Pipe mode failed: {}", err);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                    return;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    error!("Failed to read stdin: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+
             // No subcommand was used, run TUI or interactive mode
             info!("Starting interactive mode with TUI");
 
@@ -219,8 +961,53 @@ async fn main() {
                 }
             }
 
+            // `kona --continue` resumes the latest conversation, or starts fresh if there is none
+            let resume = if cli.continue_last {
+                latest_conversation().map(|(_, conversation)| conversation.id)
+            } else {
+                cli.resume
+            };
+
+            // Only the readline interactive mode supports dry runs, so those go there
+            if config.dry_run {
+                if let Err(err) = interactive::start_interactive_mode(client, resume).await {
+                    error!("Interactive mode error: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+                return;
+            }
+
+            // Mac mode reads input from dialogs, so it also works without a terminal on stdin (e.g. a script runner in a
+            // desktop session), but only when someone is there to answer; it can't resume conversations, so those always use the TUI
+            if resume.is_none() && (cli.mac || (mac::has_gui_session() && !std::io::stdin().is_terminal())) {
+                if !mac::is_available() {
+                    eprintln!("Error: --mac needs macOS, where input is read with osascript");
+                    std::process::exit(1);
+                }
+                if let Err(err) = mac::start_mac_mode(client).await {
+                    error!("Mac mode error: {}", err);
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+                return;
+            }
+
+            // Keep log output off the alternate screen if requested
+            if let Some(path) = &cli.tui_log
+                && let Err(err) = logging::redirect_logs_to_file(path)
+            {
+                eprintln!("Error: Could not open TUI log {:?}: {}", path, err);
+                std::process::exit(1);
+            }
+
             // Try to use the TUI mode first, fall back to simple interactive mode if it fails
-            match tui::start_tui_mode(client.clone()).await {
+            let tui_result = tui::start_tui_mode(client.clone(), resume.clone()).await;
+            if cli.tui_log.is_some() {
+                logging::restore_log_output();
+            }
+
+            match tui_result {
                 Ok(_) => {
                     info!("TUI mode exited successfully");
                 }
@@ -239,9 +1026,15 @@ async fn main() {
                         error!("Failed to start TUI mode: {}", err);
                     }
 
-                    println!("Detected macOS, using Mac-friendly mode...");
-
-                    if let Err(err) = mac::start_mac_mode(client).await {
+                    // The dialog-based mode only exists on macOS; elsewhere the readline mode takes over
+                    let result = if mac::is_available() && resume.is_none() {
+                        println!("Detected macOS, using Mac-friendly mode...");
+                        mac::start_mac_mode(client).await
+                    } else {
+                        println!("Falling back to interactive mode...");
+                        interactive::start_interactive_mode(client, resume).await
+                    };
+                    if let Err(err) = result {
                         error!("Interactive mode error: {}", err);
                         eprintln!("Error: {}", err);
                         std::process::exit(1);
@@ -0,0 +1,111 @@
+// Local HTTP server exposing the configured client (`kona serve`)
+
+use futures::stream::{self, StreamExt};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::api::{Message, OpenRouterClient};
+use crate::utils::error::{KonaError, Result};
+
+#[derive(Debug, Deserialize)]
+struct ChatRequest {
+    messages: Vec<Message>,
+    // Falls back to the configured streaming preference
+    #[serde(default)]
+    stream: Option<bool>,
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_else(|_| Response::new(Body::empty()))
+}
+
+fn sse_event(data: serde_json::Value) -> String {
+    format!("data: {}\n\n", data)
+}
+
+async fn handle_chat(client: Arc<OpenRouterClient>, req: Request<Body>) -> Response<Body> {
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return json_response(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() })),
+    };
+
+    let chat: ChatRequest = match serde_json::from_slice(&body) {
+        Ok(chat) => chat,
+        Err(e) => {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "error": format!("Expected {{\"messages\": [...]}}: {}", e) }),
+            );
+        }
+    };
+
+    if chat.stream.unwrap_or(client.config.use_streaming) {
+        match client.send_message_streaming_with_history(chat.messages).await {
+            Ok(response_stream) => {
+                // Re-emit chunks as server-sent events; errors become an event rather than a dropped connection
+                let events = response_stream
+                    .map(|chunk| match chunk {
+                        Ok(delta) => Ok::<_, KonaError>(sse_event(json!({ "delta": delta }))),
+                        Err(e) => Ok(sse_event(json!({ "error": e.to_string() }))),
+                    })
+                    .chain(stream::once(async { Ok("data: [DONE]\n\n".to_string()) }));
+
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "text/event-stream")
+                    .header(header::CACHE_CONTROL, "no-cache")
+                    .body(Body::wrap_stream(events))
+                    .unwrap_or_else(|_| Response::new(Body::empty()))
+            }
+            Err(e) => json_response(StatusCode::BAD_GATEWAY, json!({ "error": e.to_string() })),
+        }
+    } else {
+        match client.send_message_with_usage(chat.messages).await {
+            Ok((content, usage)) => json_response(StatusCode::OK, json!({ "content": content, "usage": usage })),
+            Err(e) => json_response(StatusCode::BAD_GATEWAY, json!({ "error": e.to_string() })),
+        }
+    }
+}
+
+async fn handle_request(client: Arc<OpenRouterClient>, req: Request<Body>) -> std::result::Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::POST, "/chat") => handle_chat(client, req).await,
+        (&Method::GET, "/health") => json_response(StatusCode::OK, json!({ "status": "ok", "model": client.config.model })),
+        _ => json_response(StatusCode::NOT_FOUND, json!({ "error": "Not found. Use POST /chat" })),
+    };
+    Ok(response)
+}
+
+// Serve the client on `host:port` until the process is stopped
+pub async fn run_server(client: OpenRouterClient, host: IpAddr, port: u16) -> Result<()> {
+    let client = Arc::new(client);
+    let make_service = make_service_fn(move |_conn| {
+        let client = client.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle_request(client.clone(), req))) }
+    });
+
+    let addr = SocketAddr::new(host, port);
+    let server = Server::try_bind(&addr)
+        .map_err(|e| KonaError::IoError(std::io::Error::new(
+            std::io::ErrorKind::AddrNotAvailable,
+            format!("Failed to bind {}: {}", addr, e),
+        )))?
+        .serve(make_service);
+
+    info!("Serving on http://{}", addr);
+    println!("Kona server listening on http://{} (POST /chat)", addr);
+
+    server.await.map_err(|e| {
+        error!("Server error: {}", e);
+        KonaError::IoError(std::io::Error::other(format!("Server error: {}", e)))
+    })
+}
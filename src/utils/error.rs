@@ -1,10 +1,14 @@
 use std::fmt;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum KonaError {
     ApiError(String),
     ConfigError(String),
     IoError(std::io::Error),
+    Timeout(String),
+    RateLimited { retry_after: Option<Duration> },
+    Unauthorized,
 }
 
 impl fmt::Display for KonaError {
@@ -13,6 +17,18 @@ impl fmt::Display for KonaError {
             KonaError::ApiError(msg) => write!(f, "API Error: {}", msg),
             KonaError::ConfigError(msg) => write!(f, "Config Error: {}", msg),
             KonaError::IoError(err) => write!(f, "IO Error: {}", err),
+            KonaError::Timeout(msg) => write!(f, "Timeout: {}", msg),
+            KonaError::RateLimited { retry_after: Some(delay) } => {
+                write!(f, "Rate limited by OpenRouter. Retry after {} seconds.", delay.as_secs().max(1))
+            }
+            KonaError::RateLimited { retry_after: None } => {
+                write!(f, "Rate limited by OpenRouter. Please wait a moment and try again.")
+            }
+            KonaError::Unauthorized => write!(
+                f,
+                "Authentication failed with OpenRouter. Please check that your API key is valid and properly formatted. \
+                For OpenRouter, the API key should be from openrouter.ai and not directly from Anthropic."
+            ),
         }
     }
 }
@@ -0,0 +1,34 @@
+// Group streamed deltas so output is flushed (or redrawn) in sensible pieces rather than per token
+
+/// Collects stream chunks and releases them once `flush_chars` characters have built up
+///
+/// A chunk containing a newline always releases everything pending, so line-oriented output
+/// stays prompt. A threshold of 0 or 1 releases every chunk as it arrives.
+pub struct FlushBuffer {
+    pending: String,
+    flush_chars: usize,
+}
+
+impl FlushBuffer {
+    pub fn new(flush_chars: usize) -> Self {
+        Self { pending: String::new(), flush_chars }
+    }
+
+    /// Adds a chunk, returning the text to output now, if it's time to flush
+    pub fn push(&mut self, chunk: &str) -> Option<String> {
+        self.pending.push_str(chunk);
+        if self.pending.is_empty() {
+            return None;
+        }
+        if chunk.contains('\n') || self.pending.chars().count() >= self.flush_chars {
+            Some(std::mem::take(&mut self.pending))
+        } else {
+            None
+        }
+    }
+
+    /// Whatever is still pending, for when the stream ends
+    pub fn finish(&mut self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
@@ -0,0 +1,58 @@
+// Tracing output that can be redirected to a file while the TUI owns the terminal
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::utils::error::Result;
+
+// When set, log lines go here instead of stderr
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Ok(mut guard) = LOG_FILE.lock()
+            && let Some(file) = guard.as_mut()
+        {
+            return file.write(buf);
+        }
+        io::stderr().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Ok(mut guard) = LOG_FILE.lock()
+            && let Some(file) = guard.as_mut()
+        {
+            return file.flush();
+        }
+        io::stderr().flush()
+    }
+}
+
+// Writer factory handed to the tracing subscriber
+pub fn log_writer() -> LogWriter {
+    LogWriter
+}
+
+// Send all subsequent log output to `path`, appending to any existing content
+pub fn redirect_logs_to_file(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    if let Ok(mut guard) = LOG_FILE.lock() {
+        *guard = Some(file);
+    }
+    Ok(())
+}
+
+// Return log output to stderr
+pub fn restore_log_output() {
+    if let Ok(mut guard) = LOG_FILE.lock() {
+        *guard = None;
+    }
+}
@@ -1,8 +1,25 @@
 // Utility functions module
 pub mod error;
+pub mod flush;
+pub mod logging;
+pub mod spinner;
+pub mod stats;
+pub mod tokens;
 #[cfg(test)]
 mod tests;
 
+// Ask a yes/no question on stdin, defaulting to no
+pub fn confirm(prompt: &str) -> std::io::Result<bool> {
+    use std::io::Write;
+
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 pub fn mask_api_key(api_key: &str) -> String {
     if api_key.len() <= 8 {
         return "****".to_string();
@@ -0,0 +1,53 @@
+// Terminal spinner shown while waiting for a complete (non-streaming) response
+
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const FRAME_INTERVAL: Duration = Duration::from_millis(80);
+
+pub struct Spinner {
+    task: Option<JoinHandle<()>>,
+    message: String,
+}
+
+impl Spinner {
+    // Start animating on the current line; does nothing when stdout isn't a terminal
+    pub fn start(message: &str) -> Self {
+        let message = message.to_string();
+        if !io::stdout().is_terminal() {
+            return Self { task: None, message };
+        }
+
+        let text = message.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FRAME_INTERVAL);
+            for frame in FRAMES.iter().cycle() {
+                interval.tick().await;
+                let mut stdout = io::stdout();
+                let _ = write!(stdout, "\r{} {}", frame, text);
+                let _ = stdout.flush();
+            }
+        });
+
+        Self { task: Some(task), message }
+    }
+
+    // Stop animating and erase the spinner line
+    pub fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            let mut stdout = io::stdout();
+            let width = self.message.chars().count() + 2;
+            let _ = write!(stdout, "\r{}\r", " ".repeat(width));
+            let _ = stdout.flush();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
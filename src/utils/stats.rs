@@ -0,0 +1,54 @@
+// Response timing for `--stats`: wall-clock time and, when streaming, throughput
+
+use std::time::{Duration, Instant};
+
+use crate::utils::tokens::estimate_tokens;
+
+/// Measures how long a response took, from the request to its last chunk
+pub struct ResponseTimer {
+    start: Instant,
+    first_chunk: Option<Instant>,
+    last_chunk: Option<Instant>,
+}
+
+impl ResponseTimer {
+    pub fn start() -> Self {
+        Self { start: Instant::now(), first_chunk: None, last_chunk: None }
+    }
+
+    /// Records the arrival of a streamed chunk
+    pub fn chunk(&mut self) {
+        let now = Instant::now();
+        self.first_chunk.get_or_insert(now);
+        self.last_chunk = Some(now);
+    }
+
+    /// The footer for a finished response; `completion_tokens` of 0 falls back to an estimate from `text`
+    pub fn footer(&self, completion_tokens: u32, text: &str) -> String {
+        let elapsed = self.last_chunk.unwrap_or_else(Instant::now) - self.start;
+        let streamed = match (self.first_chunk, self.last_chunk) {
+            (Some(first), Some(last)) => Some(last - first),
+            _ => None,
+        };
+
+        let (tokens, estimated) = if completion_tokens > 0 {
+            (completion_tokens, false)
+        } else {
+            (estimate_tokens(text) as u32, true)
+        };
+        format_stats(elapsed, streamed.map(|duration| (duration, tokens, estimated)))
+    }
+}
+
+// "2.4s" alone, or with the streaming rate as "2.4s, 38.5 tokens/s" (prefixed "~" when estimated)
+pub fn format_stats(elapsed: Duration, streamed: Option<(Duration, u32, bool)>) -> String {
+    let mut stats = format!("{:.1}s", elapsed.as_secs_f64());
+    if let Some((duration, tokens, estimated)) = streamed {
+        // A single chunk has no measurable spread
+        if !duration.is_zero() {
+            let rate = tokens as f64 / duration.as_secs_f64();
+            stats.push_str(&format!(", {}{:.1} tokens/s", if estimated { "~" } else { "" }, rate));
+        }
+    }
+    stats
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use super::mask_api_key;
+    use crate::utils::mask_api_key;
 
     #[test]
     fn test_mask_api_key() {
@@ -19,9 +19,113 @@ mod tests {
         let masked_empty = mask_api_key(empty_key);
         assert_eq!(masked_empty, "****");
         
-        // Test with exactly 8 chars key
+        // Test with exactly 8 chars key (showing both ends would reveal all of it)
         let exact_key = "12345678";
         let masked_exact = mask_api_key(exact_key);
-        assert_eq!(masked_exact, "1234****");
+        assert_eq!(masked_exact, "****");
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_error_display() {
+        use crate::utils::error::KonaError;
+        use std::time::Duration;
+        
+        let limited = KonaError::RateLimited { retry_after: Some(Duration::from_secs(12)) };
+        assert!(limited.to_string().contains("12 seconds"));
+        
+        let limited = KonaError::RateLimited { retry_after: None };
+        assert!(limited.to_string().contains("Rate limited"));
+        
+        assert!(KonaError::Unauthorized.to_string().contains("API key"));
+        assert!(KonaError::Timeout("slow".to_string()).to_string().starts_with("Timeout"));
+    }
+    
+    #[test]
+    fn test_format_stats() {
+        use crate::utils::stats::format_stats;
+        use std::time::Duration;
+        
+        assert_eq!(format_stats(Duration::from_millis(2400), None), "2.4s");
+        assert_eq!(
+            format_stats(Duration::from_secs(3), Some((Duration::from_secs(2), 90, false))),
+            "3.0s, 45.0 tokens/s"
+        );
+        assert_eq!(
+            format_stats(Duration::from_secs(3), Some((Duration::from_secs(2), 90, true))),
+            "3.0s, ~45.0 tokens/s"
+        );
+        
+        // A single chunk can't give a rate
+        assert_eq!(format_stats(Duration::from_secs(1), Some((Duration::ZERO, 5, false))), "1.0s");
+    }
+    
+    #[test]
+    fn test_trim_to_budget() {
+        use crate::api::Message;
+        use crate::utils::tokens::{estimate_message_tokens, estimate_tokens, trim_to_budget};
+        
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        
+        let long = "x".repeat(400);
+        let messages = vec![
+            Message::new("user", long.as_str()),
+            Message::new("assistant", long.as_str()),
+            Message::new("user", long.as_str()),
+            Message::new("assistant", long.as_str()),
+            Message::new("user", "Latest question"),
+        ];
+        let total = estimate_message_tokens(&messages);
+        
+        // Everything fits, nothing is dropped
+        let (kept, trimmed) = trim_to_budget(messages.clone(), total);
+        assert_eq!((kept.len(), trimmed), (5, 0));
+        
+        // Whole turns go, oldest first
+        let (kept, trimmed) = trim_to_budget(messages.clone(), total - 1);
+        assert_eq!((kept.len(), trimmed), (3, 1));
+        assert_eq!(kept[0].role, "user");
+        
+        // The latest question survives even a tiny budget
+        let (kept, trimmed) = trim_to_budget(messages, 1);
+        assert_eq!(trimmed, 2);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].content, "Latest question");
+    }
+
+    #[test]
+    fn test_oversized_input_warning() {
+        use crate::utils::tokens::oversized_input_warning;
+        
+        assert_eq!(oversized_input_warning("short", 10), None);
+        assert_eq!(oversized_input_warning(&"x".repeat(10), 10), None);
+        
+        let warning = oversized_input_warning(&"x".repeat(41), 40).unwrap();
+        assert!(warning.contains("41 characters"));
+        assert!(warning.contains("about 11 tokens"));
+        
+        // 0 turns the check off
+        assert_eq!(oversized_input_warning(&"x".repeat(1_000_000), 0), None);
+    }
+    
+    #[test]
+    fn test_flush_buffer() {
+        use crate::utils::flush::FlushBuffer;
+        
+        let mut buffer = FlushBuffer::new(8);
+        assert_eq!(buffer.push("Hel"), None);
+        assert_eq!(buffer.push("lo, wo"), Some("Hello, wo".to_string()));
+        
+        // A newline flushes early
+        assert_eq!(buffer.push("rld\n"), Some("rld\n".to_string()));
+        assert_eq!(buffer.push("!"), None);
+        assert_eq!(buffer.finish(), Some("!".to_string()));
+        assert_eq!(buffer.finish(), None);
+        
+        // A threshold of 1 passes every chunk straight through
+        let mut buffer = FlushBuffer::new(1);
+        assert_eq!(buffer.push("a"), Some("a".to_string()));
+        assert_eq!(buffer.push(""), None);
+    }
+}
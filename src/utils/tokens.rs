@@ -0,0 +1,54 @@
+// Rough token estimates, for decisions that can't wait for the API's own counts
+
+use crate::api::Message;
+
+// Characters per token for typical English text and code
+pub const CHARS_PER_TOKEN: usize = 4;
+
+// Role markers and separators each message adds on top of its content
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Estimated token count of `text`, rounded up
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Warning for input longer than `max_chars` characters, with its estimated token count; 0 means no limit
+pub fn oversized_input_warning(input: &str, max_chars: usize) -> Option<String> {
+    let chars = input.chars().count();
+    (max_chars > 0 && chars > max_chars).then(|| {
+        format!(
+            "Input is {} characters (about {} tokens), over the max_input_chars limit of {}",
+            chars,
+            estimate_tokens(input),
+            max_chars
+        )
+    })
+}
+
+/// Estimated token count of a list of messages, including per-message overhead
+pub fn estimate_message_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|message| estimate_tokens(&message.content.text()) + MESSAGE_OVERHEAD_TOKENS)
+        .sum()
+}
+
+/// Drops the oldest turns until the messages fit in `budget` tokens, returning how many turns went
+///
+/// A turn runs from one user message to the next. The latest turn is always kept, even if it alone
+/// is over budget, so the question itself is never lost.
+pub fn trim_to_budget(mut messages: Vec<Message>, budget: usize) -> (Vec<Message>, usize) {
+    let mut trimmed = 0;
+    while estimate_message_tokens(&messages) > budget {
+        let next_turn = messages.iter().skip(1).position(|m| m.role == "user").map(|i| i + 1);
+        match next_turn {
+            Some(start) => {
+                messages.drain(..start);
+                trimmed += 1;
+            }
+            None => break,
+        }
+    }
+    (messages, trimmed)
+}
@@ -74,4 +74,54 @@ fn test_cli_config() {
     assert!(stdout.contains("Current configuration:"));
     assert!(stdout.contains("API Key:"));
     assert!(stdout.contains("Model:"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_stream_json_stdout_is_ndjson() {
+    let mut server = mockito::Server::new();
+    let body = concat!(
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\", \\\"world\\\"\\n\"},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":3,\"total_tokens\":8}}\n\n",
+        "data: [DONE]\n\n",
+    );
+    let mock = server
+        .mock("POST", "/chat/completions")
+        .match_body(mockito::Matcher::Regex(r#""stream_options":\{"include_usage":true\}"#.to_string()))
+        .with_header("content-type", "text/event-stream")
+        .with_body(body)
+        .create();
+
+    let dir = env::temp_dir().join(format!("kona-ndjson-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // Debug logging on, to check logs stay off stdout
+    let output = Command::new(env!("CARGO_BIN_EXE_kona"))
+        .args(["-vv", "--no-project-config", "ask", "--stream-json", "say hello"])
+        .current_dir(&dir)
+        .env("KONA_OPENROUTER_API_KEY", "test-key")
+        .env("KONA_BASE_URL", server.url())
+        .env("KONA_CONFIG", dir.join("config.toml"))
+        .env("KONA_STORAGE_DIR", dir.join("conversations"))
+        .env("KONA_CACHE", "false")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("Failed to execute command");
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    mock.assert();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("not JSON ({}): {:?}", e, line)))
+        .collect();
+    let text: String = events.iter().filter_map(|event| event["delta"].as_str()).collect();
+    assert_eq!(text, "Hello, \"world\"\n");
+
+    let done = events.last().unwrap();
+    assert_eq!(done["done"], true);
+    assert_eq!(done["usage"]["total_tokens"], 8);
+    assert_eq!(done["finish_reason"], "stop");
+}